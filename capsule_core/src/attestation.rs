@@ -0,0 +1,309 @@
+// ============================================================================
+// Detached Signatures and Attestations
+// ============================================================================
+//
+// Both sign the canonical CBOR of a statement tagged with its kind, never a
+// bare content hash, so neither can be replayed as a capsule signature (or
+// as each other).
+//
+// An attestation is a signed claim about existing artifacts, e.g.
+//
+//   subjects:  ["RenderV1:abc...", "ExprV1:def..."]
+//   predicate: "rendered-from"
+//
+// The predicate gives the subjects their roles, so subject order matters.
+
+use crate::store::parse_key;
+use crate::{
+    canonical_cbor, CanonicalSerialize, CapsuleError, Clock, ContentAddressable, SystemClock,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+// ============================================================================
+// Detached Signatures
+// ============================================================================
+
+/// A signature over a content hash, kept apart from the content it names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetachedSignature {
+    pub content_hash: String,
+    #[serde(with = "serde_bytes")]
+    pub public_key: [u8; 32],
+    #[serde(with = "serde_bytes")]
+    pub signature: [u8; 64],
+}
+
+#[derive(Serialize)]
+struct DetachedStatement<'a> {
+    kind: &'static str,
+    content_hash: &'a str,
+}
+
+fn detached_statement(content_hash: &str) -> Result<Vec<u8>, CapsuleError> {
+    canonical_cbor(&DetachedStatement {
+        kind: "DetachedV1",
+        content_hash,
+    })
+}
+
+/// Sign `content_hash` with `keypair`.
+pub fn sign_detached(
+    keypair: &SigningKey,
+    content_hash: &str,
+) -> Result<DetachedSignature, CapsuleError> {
+    parse_key(content_hash)?;
+    let statement = detached_statement(content_hash)?;
+    Ok(DetachedSignature {
+        content_hash: content_hash.to_string(),
+        public_key: keypair.verifying_key().to_bytes(),
+        signature: keypair.sign(&statement).to_bytes(),
+    })
+}
+
+/// Check a detached signature, reporting why it fails.
+pub fn check_detached(detached: &DetachedSignature) -> Result<(), CapsuleError> {
+    check_signature(
+        &detached.public_key,
+        &detached.signature,
+        &detached_statement(&detached.content_hash)?,
+        &detached.content_hash,
+    )
+}
+
+pub fn verify_detached(detached: &DetachedSignature) -> bool {
+    check_detached(detached).is_ok()
+}
+
+// ============================================================================
+// Attestations
+// ============================================================================
+
+/// A signed statement that `predicate` holds for `subjects`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    /// Content hashes of the artifacts the statement is about.
+    pub subjects: Vec<String>,
+    pub predicate: String,
+    #[serde(with = "serde_bytes")]
+    pub signer: [u8; 32],
+    pub timestamp: u64,
+    #[serde(with = "serde_bytes")]
+    pub signature: [u8; 64],
+}
+
+#[derive(Serialize)]
+struct AttestationStatement<'a> {
+    kind: &'static str,
+    subjects: &'a [String],
+    predicate: &'a str,
+    #[serde(with = "serde_bytes")]
+    signer: &'a [u8; 32],
+    timestamp: u64,
+}
+
+fn attestation_statement(
+    subjects: &[String],
+    predicate: &str,
+    signer: &[u8; 32],
+    timestamp: u64,
+) -> Result<Vec<u8>, CapsuleError> {
+    canonical_cbor(&AttestationStatement {
+        kind: "AttestationV1",
+        subjects,
+        predicate,
+        signer,
+        timestamp,
+    })
+}
+
+pub fn create_attestation(
+    keypair: &SigningKey,
+    subjects: Vec<String>,
+    predicate: String,
+) -> Result<Attestation, CapsuleError> {
+    create_attestation_with_clock(keypair, subjects, predicate, &SystemClock)
+}
+
+/// [`create_attestation`] with the timestamp taken from `clock`.
+pub fn create_attestation_with_clock(
+    keypair: &SigningKey,
+    subjects: Vec<String>,
+    predicate: String,
+    clock: &dyn Clock,
+) -> Result<Attestation, CapsuleError> {
+    if subjects.is_empty() {
+        return Err(CapsuleError::InvalidArgument(
+            "attestation needs at least one subject".to_string(),
+        ));
+    }
+    if predicate.is_empty() {
+        return Err(CapsuleError::InvalidArgument(
+            "attestation predicate must not be empty".to_string(),
+        ));
+    }
+    for subject in &subjects {
+        parse_key(subject)?;
+    }
+
+    let signer = keypair.verifying_key().to_bytes();
+    let timestamp = clock.now();
+    let statement = attestation_statement(&subjects, &predicate, &signer, timestamp)?;
+
+    Ok(Attestation {
+        subjects,
+        predicate,
+        signer,
+        timestamp,
+        signature: keypair.sign(&statement).to_bytes(),
+    })
+}
+
+/// Check an attestation's signature, reporting why it fails.
+pub fn check_attestation(attestation: &Attestation) -> Result<(), CapsuleError> {
+    let statement = attestation_statement(
+        &attestation.subjects,
+        &attestation.predicate,
+        &attestation.signer,
+        attestation.timestamp,
+    )?;
+    check_signature(
+        &attestation.signer,
+        &attestation.signature,
+        &statement,
+        &attestation.content_hash(),
+    )
+}
+
+pub fn verify_attestation(attestation: &Attestation) -> bool {
+    check_attestation(attestation).is_ok()
+}
+
+impl Attestation {
+    /// Whether `hash` is one of the attested subjects.
+    pub fn covers(&self, hash: &str) -> bool {
+        self.subjects.iter().any(|s| s == hash)
+    }
+}
+
+impl CanonicalSerialize for Attestation {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        canonical_cbor(self).expect("Attestation serialization should not fail")
+    }
+}
+
+impl ContentAddressable for Attestation {
+    const HASH_PREFIX: &'static str = "AttestV1";
+}
+
+fn check_signature(
+    public_key: &[u8; 32],
+    signature: &[u8; 64],
+    statement: &[u8],
+    content_hash: &str,
+) -> Result<(), CapsuleError> {
+    let public_key = VerifyingKey::from_bytes(public_key)
+        .map_err(|e| CapsuleError::InvalidKey(e.to_string()))?;
+    public_key
+        .verify(statement, &Signature::from_bytes(signature))
+        .map_err(|_| CapsuleError::SignatureInvalid {
+            key_id: crate::key_id(&public_key),
+            content_hash: content_hash.to_string(),
+        })
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_root_capsule, generate_keypair, verify_capsule, FixedClock};
+
+    fn render_attestation(keypair: &SigningKey) -> Attestation {
+        create_attestation_with_clock(
+            keypair,
+            vec![
+                format!("RenderV1:{}", "ab".repeat(32)),
+                format!("ExprV1:{}", "cd".repeat(32)),
+            ],
+            "rendered-from".to_string(),
+            &FixedClock(1_700_000_000),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_attestation_sign_and_verify() {
+        let keypair = generate_keypair();
+        let attestation = render_attestation(&keypair);
+        assert!(verify_attestation(&attestation));
+        assert!(attestation.covers(&format!("ExprV1:{}", "cd".repeat(32))));
+        assert_eq!(attestation.timestamp, 1_700_000_000);
+
+        // Ed25519 signing is deterministic, so equal inputs sign equally
+        assert_eq!(render_attestation(&keypair), attestation);
+        assert!(attestation.content_hash().starts_with("AttestV1:"));
+    }
+
+    #[test]
+    fn test_attestation_tampering_detected() {
+        let keypair = generate_keypair();
+        let attestation = render_attestation(&keypair);
+
+        let mut swapped = attestation.clone();
+        swapped.subjects.reverse();
+        assert!(matches!(
+            check_attestation(&swapped),
+            Err(CapsuleError::SignatureInvalid { .. })
+        ));
+
+        let mut predicate = attestation.clone();
+        predicate.predicate = "approved".to_string();
+        assert!(!verify_attestation(&predicate));
+
+        let mut timestamp = attestation.clone();
+        timestamp.timestamp += 1;
+        assert!(!verify_attestation(&timestamp));
+
+        let mut signer = attestation;
+        signer.signer = generate_keypair().verifying_key().to_bytes();
+        assert!(!verify_attestation(&signer));
+    }
+
+    #[test]
+    fn test_attestation_rejects_bad_subjects() {
+        let keypair = generate_keypair();
+        let create = |subjects: Vec<String>| {
+            create_attestation(&keypair, subjects, "derived-from".to_string())
+        };
+        assert!(matches!(
+            create(vec![]),
+            Err(CapsuleError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            create(vec!["not a hash".to_string()]),
+            Err(CapsuleError::InvalidHash(_))
+        ));
+    }
+
+    #[test]
+    fn test_detached_signature() {
+        let keypair = generate_keypair();
+        let capsule = create_root_capsule(&keypair, "Artifact".to_string()).unwrap();
+        let hash = &capsule.signature_block.content_hash;
+
+        let detached = sign_detached(&keypair, hash).unwrap();
+        assert!(verify_detached(&detached));
+
+        let mut other = detached.clone();
+        other.content_hash = "ff".repeat(32);
+        assert!(!verify_detached(&other));
+
+        // A detached signature cannot stand in for the capsule's own
+        let mut forged = capsule.clone();
+        forged.signature_block.signature = detached.signature;
+        assert!(!verify_capsule(&forged).crypto_valid);
+    }
+}
@@ -0,0 +1,514 @@
+// ============================================================================
+// Deterministic CBOR (RFC 8949 §4.2.1)
+// ============================================================================
+//
+// Every content hash in this crate is computed over bytes produced here, so
+// two producers given the same value must emit byte-identical output. The
+// encoder applies the core deterministic encoding requirements:
+//
+// - integers, lengths and tags use the shortest argument encoding
+// - floats use the shortest of f16/f32/f64 that preserves the value, and NaN
+//   is always encoded as the canonical half-precision quiet NaN
+// - map keys are sorted by the bytewise order of their encodings
+// - indefinite-length items are never produced
+//
+// `check_canonical` enforces the same rules on input so that non-canonical
+// bytes are rejected instead of silently re-hashed to a different value.
+// Input nested deeper than `MAX_DEPTH` arrays, maps and tags is rejected
+// as well, so hostile bytes cannot exhaust the stack.
+
+use crate::CapsuleError;
+use ciborium::value::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+const CANONICAL_NAN: [u8; 3] = [0xf9, 0x7e, 0x00];
+
+/// Deepest nesting of arrays, maps and tags accepted on input. Matches
+/// ciborium's own recursion limit for decoding.
+pub const MAX_DEPTH: usize = 256;
+
+// ============================================================================
+// Encoder
+// ============================================================================
+
+/// Encode `data` as deterministic CBOR.
+pub fn encode<T: Serialize + ?Sized>(data: &T) -> Result<Vec<u8>, CapsuleError> {
+    let value = Value::serialized(data).map_err(|e| CapsuleError::Encode(e.to_string()))?;
+    let mut buffer = Vec::new();
+    encode_value(&value, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Decode `bytes` into `T`, rejecting input that is not deterministic CBOR.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CapsuleError> {
+    check_canonical(bytes)?;
+    ciborium::from_reader(bytes).map_err(|e| CapsuleError::Decode(e.to_string()))
+}
+
+fn write_head(major: u8, arg: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if arg < 24 {
+        out.push(major | arg as u8);
+    } else if arg <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(arg as u8);
+    } else if arg <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+fn encode_value(value: &Value, out: &mut Vec<u8>) -> Result<(), CapsuleError> {
+    match value {
+        Value::Integer(i) => {
+            let n = i128::from(*i);
+            if n >= 0 {
+                write_head(MAJOR_UNSIGNED, n as u64, out);
+            } else {
+                write_head(MAJOR_NEGATIVE, (-1 - n) as u64, out);
+            }
+        }
+        Value::Bytes(bytes) => {
+            write_head(MAJOR_BYTES, bytes.len() as u64, out);
+            out.extend_from_slice(bytes);
+        }
+        Value::Text(text) => {
+            write_head(MAJOR_TEXT, text.len() as u64, out);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(MAJOR_ARRAY, items.len() as u64, out);
+            for item in items {
+                encode_value(item, out)?;
+            }
+        }
+        Value::Map(entries) => {
+            let mut encoded = Vec::with_capacity(entries.len());
+            for (key, val) in entries {
+                let mut key_bytes = Vec::new();
+                encode_value(key, &mut key_bytes)?;
+                let mut val_bytes = Vec::new();
+                encode_value(val, &mut val_bytes)?;
+                encoded.push((key_bytes, val_bytes));
+            }
+            encoded.sort_by(|a, b| a.0.cmp(&b.0));
+            if encoded.windows(2).any(|w| w[0].0 == w[1].0) {
+                return Err(CapsuleError::Encode("duplicate map key".to_string()));
+            }
+
+            write_head(MAJOR_MAP, encoded.len() as u64, out);
+            for (key_bytes, val_bytes) in encoded {
+                out.extend_from_slice(&key_bytes);
+                out.extend_from_slice(&val_bytes);
+            }
+        }
+        Value::Tag(tag, inner) => {
+            write_head(MAJOR_TAG, *tag, out);
+            encode_value(inner, out)?;
+        }
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Null => out.push(0xf6),
+        Value::Float(f) => encode_float(*f, out),
+        other => {
+            return Err(CapsuleError::Encode(format!(
+                "unsupported value {:?}",
+                other
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn encode_float(f: f64, out: &mut Vec<u8>) {
+    if f.is_nan() {
+        out.extend_from_slice(&CANONICAL_NAN);
+        return;
+    }
+
+    let single = f as f32;
+    if single as f64 != f {
+        out.push(0xfb);
+        out.extend_from_slice(&f.to_bits().to_be_bytes());
+    } else if let Some(half) = f32_to_f16_exact(single) {
+        out.push(0xf9);
+        out.extend_from_slice(&half.to_be_bytes());
+    } else {
+        out.push(0xfa);
+        out.extend_from_slice(&single.to_bits().to_be_bytes());
+    }
+}
+
+/// Convert to IEEE 754 binary16 if (and only if) no precision is lost.
+fn f32_to_f16_exact(f: f32) -> Option<u16> {
+    let bits = f.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+
+    if f == 0.0 {
+        return Some(sign);
+    }
+    if f.is_infinite() {
+        return Some(sign | 0x7c00);
+    }
+
+    let exp = ((bits >> 23) & 0xff) as i32 - 127;
+    let mantissa = bits & 0x7f_ffff;
+
+    if (-14..=15).contains(&exp) {
+        // Normal half: 10 mantissa bits, the low 13 must be zero
+        if mantissa & 0x1fff != 0 {
+            return None;
+        }
+        Some(sign | (((exp + 15) as u16) << 10) | (mantissa >> 13) as u16)
+    } else if (-24..-14).contains(&exp) {
+        // Subnormal half: value = m * 2^-24 with m < 2^10
+        let full = 0x80_0000 | mantissa;
+        let shift = (-(exp + 1)) as u32;
+        if full & ((1 << shift) - 1) != 0 {
+            return None;
+        }
+        Some(sign | (full >> shift) as u16)
+    } else {
+        None
+    }
+}
+
+fn f16_to_f64(half: u16) -> f64 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f64;
+    match exp {
+        0 => sign * mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => sign * f64::INFINITY,
+        31 => f64::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f64.powi(exp - 15),
+    }
+}
+
+// ============================================================================
+// Conformance Checker
+// ============================================================================
+
+/// Returns true if `bytes` is exactly one deterministically encoded CBOR item.
+pub fn is_canonical(bytes: &[u8]) -> bool {
+    check_canonical(bytes).is_ok()
+}
+
+/// Like [`is_canonical`], but explains the first violation found.
+pub fn check_canonical(bytes: &[u8]) -> Result<(), CapsuleError> {
+    let end = check_item(bytes, 0, 0).map_err(CapsuleError::NonCanonical)?;
+    if end != bytes.len() {
+        return Err(CapsuleError::NonCanonical(format!(
+            "{} trailing bytes after item",
+            bytes.len() - end
+        )));
+    }
+    Ok(())
+}
+
+fn take(bytes: &[u8], pos: usize, len: usize) -> Result<&[u8], String> {
+    bytes
+        .get(pos..pos.checked_add(len).ok_or("length overflow")?)
+        .ok_or_else(|| format!("truncated item at offset {}", pos))
+}
+
+/// Read an item head, returning (major, additional info, argument, next offset).
+fn read_head(bytes: &[u8], pos: usize) -> Result<(u8, u8, u64, usize), String> {
+    let initial = take(bytes, pos, 1)?[0];
+    let major = initial >> 5;
+    let info = initial & 0x1f;
+    let pos = pos + 1;
+
+    let (arg, len) = match info {
+        0..=23 => (info as u64, 0),
+        24 => (take(bytes, pos, 1)?[0] as u64, 1),
+        25 => {
+            let b = take(bytes, pos, 2)?;
+            (u16::from_be_bytes([b[0], b[1]]) as u64, 2)
+        }
+        26 => {
+            let b = take(bytes, pos, 4)?;
+            (u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as u64, 4)
+        }
+        27 => {
+            let b = take(bytes, pos, 8)?;
+            let mut arr = [0u8; 8];
+            arr.copy_from_slice(b);
+            (u64::from_be_bytes(arr), 8)
+        }
+        31 => return Err(format!("indefinite-length item at offset {}", pos - 1)),
+        _ => {
+            return Err(format!(
+                "reserved additional info {} at offset {}",
+                info,
+                pos - 1
+            ))
+        }
+    };
+
+    Ok((major, info, arg, pos + len))
+}
+
+fn check_item(bytes: &[u8], start: usize, depth: usize) -> Result<usize, String> {
+    let (major, info, arg, pos) = read_head(bytes, start)?;
+    if matches!(major, MAJOR_ARRAY | MAJOR_MAP | MAJOR_TAG) && depth >= MAX_DEPTH {
+        return Err(format!(
+            "nesting deeper than {} at offset {}",
+            MAX_DEPTH, start
+        ));
+    }
+
+    // Floats and simple values carry their payload in the argument bytes, so
+    // "shortest argument" does not apply to them in the same way.
+    if major != MAJOR_SIMPLE {
+        let shortest = match arg {
+            0..=23 => info == arg as u8,
+            24..=0xff => info == 24,
+            0x100..=0xffff => info == 25,
+            0x1_0000..=0xffff_ffff => info == 26,
+            _ => info == 27,
+        };
+        if !shortest {
+            return Err(format!(
+                "argument {} not in shortest form at offset {}",
+                arg, start
+            ));
+        }
+    }
+
+    match major {
+        MAJOR_UNSIGNED | MAJOR_NEGATIVE => Ok(pos),
+        MAJOR_BYTES => Ok(pos + take(bytes, pos, arg as usize)?.len()),
+        MAJOR_TEXT => {
+            let text = take(bytes, pos, arg as usize)?;
+            std::str::from_utf8(text).map_err(|_| format!("invalid UTF-8 at offset {}", pos))?;
+            Ok(pos + text.len())
+        }
+        MAJOR_ARRAY => {
+            let mut pos = pos;
+            for _ in 0..arg {
+                pos = check_item(bytes, pos, depth + 1)?;
+            }
+            Ok(pos)
+        }
+        MAJOR_MAP => {
+            let mut pos = pos;
+            let mut prev_key: Option<&[u8]> = None;
+            for _ in 0..arg {
+                let key_start = pos;
+                pos = check_item(bytes, pos, depth + 1)?;
+                let key = &bytes[key_start..pos];
+                if let Some(prev) = prev_key {
+                    if prev >= key {
+                        return Err(format!(
+                            "map keys unsorted or duplicated at offset {}",
+                            key_start
+                        ));
+                    }
+                }
+                prev_key = Some(key);
+                pos = check_item(bytes, pos, depth + 1)?;
+            }
+            Ok(pos)
+        }
+        MAJOR_TAG => check_item(bytes, pos, depth + 1),
+        _ => check_simple(bytes, start, info, arg, pos),
+    }
+}
+
+fn check_simple(
+    bytes: &[u8],
+    start: usize,
+    info: u8,
+    arg: u64,
+    pos: usize,
+) -> Result<usize, String> {
+    match info {
+        0..=23 => Ok(pos),
+        24 if arg >= 32 => Ok(pos),
+        24 => Err(format!(
+            "simple value {} not in shortest form at offset {}",
+            arg, start
+        )),
+        25 => {
+            let value = f16_to_f64(arg as u16);
+            if value.is_nan() && bytes[start..pos] != CANONICAL_NAN[..] {
+                return Err(format!("non-canonical NaN at offset {}", start));
+            }
+            Ok(pos)
+        }
+        26 => {
+            let value = f32::from_bits(arg as u32);
+            if value.is_nan() {
+                return Err(format!("non-canonical NaN at offset {}", start));
+            }
+            if f32_to_f16_exact(value).is_some() {
+                return Err(format!(
+                    "float32 {} fits in float16 at offset {}",
+                    value, start
+                ));
+            }
+            Ok(pos)
+        }
+        _ => {
+            let value = f64::from_bits(arg);
+            if value.is_nan() {
+                return Err(format!("non-canonical NaN at offset {}", start));
+            }
+            if (value as f32) as f64 == value {
+                return Err(format!(
+                    "float64 {} fits in float32 at offset {}",
+                    value, start
+                ));
+            }
+            Ok(pos)
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn hex(bytes: &[u8]) -> String {
+        ::hex::encode(bytes)
+    }
+
+    #[test]
+    fn test_shortest_integer_encoding() {
+        assert_eq!(hex(&encode(&0u64).unwrap()), "00");
+        assert_eq!(hex(&encode(&23u64).unwrap()), "17");
+        assert_eq!(hex(&encode(&24u64).unwrap()), "1818");
+        assert_eq!(hex(&encode(&1000u64).unwrap()), "1903e8");
+        assert_eq!(hex(&encode(&1_000_000u64).unwrap()), "1a000f4240");
+        assert_eq!(
+            hex(&encode(&(u32::MAX as u64 + 1)).unwrap()),
+            "1b0000000100000000"
+        );
+        assert_eq!(hex(&encode(&-1i64).unwrap()), "20");
+        assert_eq!(hex(&encode(&-1000i64).unwrap()), "3903e7");
+    }
+
+    #[test]
+    fn test_shortest_float_encoding() {
+        // Vectors from RFC 8949 Appendix A
+        assert_eq!(hex(&encode(&0.0f64).unwrap()), "f90000");
+        assert_eq!(hex(&encode(&-0.0f64).unwrap()), "f98000");
+        assert_eq!(hex(&encode(&1.5f64).unwrap()), "f93e00");
+        assert_eq!(hex(&encode(&65504.0f64).unwrap()), "f97bff");
+        assert_eq!(hex(&encode(&5.960464477539063e-8f64).unwrap()), "f90001");
+        assert_eq!(hex(&encode(&100000.0f64).unwrap()), "fa47c35000");
+        assert_eq!(hex(&encode(&1.1f64).unwrap()), "fb3ff199999999999a");
+        assert_eq!(hex(&encode(&f64::INFINITY).unwrap()), "f97c00");
+        assert_eq!(hex(&encode(&f64::NAN).unwrap()), "f97e00");
+    }
+
+    #[test]
+    fn test_map_keys_sorted_regardless_of_insertion_order() {
+        let mut a = HashMap::new();
+        let mut b = HashMap::new();
+        for (k, v) in [("zeta", 1), ("a", 2), ("mid", 3), ("bb", 4)] {
+            a.insert(k.to_string(), v);
+        }
+        for (k, v) in [("bb", 4), ("mid", 3), ("a", 2), ("zeta", 1)] {
+            b.insert(k.to_string(), v);
+        }
+        let ea = encode(&a).unwrap();
+        assert_eq!(ea, encode(&b).unwrap());
+        assert!(is_canonical(&ea));
+        // Shorter keys sort first because their length prefix is smaller
+        assert_eq!(&ea[1..3], &[0x61, b'a']);
+    }
+
+    #[test]
+    fn test_round_trip_through_decode() {
+        let value: Vec<(String, i64)> = vec![("x".to_string(), -5), ("y".to_string(), 300)];
+        let bytes = encode(&value).unwrap();
+        let decoded: Vec<(String, i64)> = decode(&bytes).unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_rejects_non_shortest_integer() {
+        assert!(is_canonical(&[0x17]));
+        assert!(!is_canonical(&[0x18, 0x17]));
+        assert!(!is_canonical(&[0x19, 0x00, 0xff]));
+        assert!(!is_canonical(&[0x62, b'a']), "truncated text");
+    }
+
+    #[test]
+    fn test_rejects_indefinite_length() {
+        // [_ 1, 2]
+        assert!(!is_canonical(&[0x9f, 0x01, 0x02, 0xff]));
+        // (_ "a")
+        assert!(!is_canonical(&[0x7f, 0x61, b'a', 0xff]));
+    }
+
+    #[test]
+    fn test_rejects_unsorted_or_duplicate_map_keys() {
+        // {"b": 1, "a": 2}
+        assert!(!is_canonical(&[0xa2, 0x61, b'b', 0x01, 0x61, b'a', 0x02]));
+        // {"a": 1, "a": 2}
+        assert!(!is_canonical(&[0xa2, 0x61, b'a', 0x01, 0x61, b'a', 0x02]));
+        // {"a": 1, "b": 2}
+        assert!(is_canonical(&[0xa2, 0x61, b'a', 0x01, 0x61, b'b', 0x02]));
+    }
+
+    #[test]
+    fn test_rejects_oversized_floats_and_nan() {
+        // 1.5 as float32 and float64
+        assert!(!is_canonical(&[0xfa, 0x3f, 0xc0, 0x00, 0x00]));
+        assert!(!is_canonical(&[0xfb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]));
+        // Non-canonical NaN payload
+        assert!(!is_canonical(&[0xf9, 0x7e, 0x01]));
+        assert!(is_canonical(&CANONICAL_NAN));
+    }
+
+    #[test]
+    fn test_rejects_excessive_nesting() {
+        // [[[...0...]]] nested exactly MAX_DEPTH arrays deep is fine
+        let nested = |depth: usize| {
+            let mut bytes = vec![0x81; depth];
+            bytes.push(0x00);
+            bytes
+        };
+        assert!(is_canonical(&nested(MAX_DEPTH)));
+
+        let err = check_canonical(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert!(matches!(err, CapsuleError::NonCanonical(msg) if msg.contains("nesting")));
+
+        // Deep enough to overflow the stack without the cap
+        assert!(!is_canonical(&nested(1_000_000)));
+        // Tags count towards the depth too
+        assert!(!is_canonical(
+            &[vec![0xc1; MAX_DEPTH + 1], vec![0x00]].concat()
+        ));
+    }
+
+    #[test]
+    fn test_rejects_trailing_bytes() {
+        assert!(!is_canonical(&[0x01, 0x02]));
+        assert!(!is_canonical(&[]));
+    }
+}
@@ -0,0 +1,610 @@
+// ============================================================================
+// Chunked Capsule Content with Merkle Hashing
+// ============================================================================
+//
+// Large payloads are split into fixed-size chunks that live in a
+// `CapsuleStore` under `ChunkV1:<hash>` keys. The capsule itself carries only
+// a `ChunkManifest` as its content; the manifest's Merkle root is therefore
+// covered by the capsule's signed content hash, and any single chunk can be
+// checked against that root with a `MerkleProof`.
+//
+// Tree shape: leaves are the chunk hashes in order, each internal node is
+// SHA-256("MerkleV1" || left || right), and an unpaired last node is promoted
+// to the next level unchanged. The published root is
+// SHA-256("MerkleRootV1" || leaf count as u64 BE || tree root), so a proof
+// cannot pass off a chunk as part of a tree with a different shape.
+
+use crate::store::CapsuleStore;
+use crate::{canonical_cbor, cbor, Capsule, CapsuleError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+const CHUNK_PREFIX: &str = "ChunkV1";
+const MERKLE_PREFIX: &str = "MerkleV1";
+const ROOT_PREFIX: &str = "MerkleRootV1";
+const MANIFEST_KIND: &str = "ChunkedV1";
+
+type Digest32 = [u8; 32];
+
+fn leaf_hash(chunk: &[u8]) -> Digest32 {
+    let mut hasher = Sha256::new();
+    hasher.update(CHUNK_PREFIX.as_bytes());
+    hasher.update(chunk);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Digest32, right: &Digest32) -> Digest32 {
+    let mut hasher = Sha256::new();
+    hasher.update(MERKLE_PREFIX.as_bytes());
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn chunk_key(leaf: &Digest32) -> String {
+    format!("{}:{}", CHUNK_PREFIX, hex::encode(leaf))
+}
+
+fn parse_digest(prefix: &str, s: &str) -> Result<Digest32, CapsuleError> {
+    s.strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .and_then(|hex_part| hex::decode(hex_part).ok())
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| CapsuleError::InvalidHash(s.to_string()))
+}
+
+/// Hash one tree level into the next, promoting an unpaired last node.
+fn next_level(level: &[Digest32]) -> Vec<Digest32> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Bind the root of the tree to the number of leaves under it.
+fn commit_root(leaf_count: u64, tree_root: &Digest32) -> Digest32 {
+    let mut hasher = Sha256::new();
+    hasher.update(ROOT_PREFIX.as_bytes());
+    hasher.update(leaf_count.to_be_bytes());
+    hasher.update(tree_root);
+    hasher.finalize().into()
+}
+
+/// Compute the Merkle root over `leaves`.
+fn merkle_root(leaves: &[Digest32]) -> Digest32 {
+    let tree_root = if leaves.is_empty() {
+        Sha256::digest(MERKLE_PREFIX.as_bytes()).into()
+    } else {
+        let mut level = leaves.to_vec();
+        while level.len() > 1 {
+            level = next_level(&level);
+        }
+        level[0]
+    };
+    commit_root(leaves.len() as u64, &tree_root)
+}
+
+// ============================================================================
+// Manifest
+// ============================================================================
+
+/// Describes chunked content: how it was split and the Merkle root that
+/// commits to it. Stored as the `content` of a chunked capsule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    pub kind: String,
+    pub chunk_size: u32,
+    pub total_len: u64,
+    /// Store keys of every chunk, in order.
+    pub chunks: Vec<String>,
+    /// `MerkleV1:<hex>` root over the chunk hashes and their count.
+    pub merkle_root: String,
+}
+
+impl ChunkManifest {
+    /// Encode the manifest as capsule content.
+    pub fn to_content(&self) -> Result<Vec<u8>, CapsuleError> {
+        canonical_cbor(self)
+    }
+
+    /// Read the manifest out of a chunked capsule.
+    pub fn from_capsule(capsule: &Capsule) -> Result<Self, CapsuleError> {
+        let manifest: ChunkManifest = cbor::decode(&capsule.content)?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Check that the manifest is internally consistent.
+    pub fn validate(&self) -> Result<(), CapsuleError> {
+        self.check_layout()?;
+
+        let computed = format!(
+            "{}:{}",
+            MERKLE_PREFIX,
+            hex::encode(merkle_root(&self.leaves()?))
+        );
+        if computed != self.merkle_root {
+            return Err(CapsuleError::HashMismatch {
+                expected: self.merkle_root.clone(),
+                actual: computed,
+            });
+        }
+        Ok(())
+    }
+
+    /// The cheap part of [`validate`](Self::validate): the kind is known and
+    /// the chunk count matches the size fields, so chunk offsets and lengths
+    /// can be computed without overflow.
+    fn check_layout(&self) -> Result<(), CapsuleError> {
+        if self.kind != MANIFEST_KIND {
+            return Err(CapsuleError::Decode(format!(
+                "unknown manifest kind: {}",
+                self.kind
+            )));
+        }
+        if self.chunk_size == 0 {
+            return Err(CapsuleError::Decode(
+                "manifest chunk size must be non-zero".to_string(),
+            ));
+        }
+        let expected_chunks = self.total_len.div_ceil(self.chunk_size as u64);
+        if self.chunks.len() as u64 != expected_chunks {
+            return Err(CapsuleError::Decode(format!(
+                "manifest lists {} chunks but {} bytes need {}",
+                self.chunks.len(),
+                self.total_len,
+                expected_chunks
+            )));
+        }
+        Ok(())
+    }
+
+    fn leaves(&self) -> Result<Vec<Digest32>, CapsuleError> {
+        self.chunks
+            .iter()
+            .map(|key| parse_digest(CHUNK_PREFIX, key))
+            .collect()
+    }
+
+    /// Build an inclusion proof for chunk `index`.
+    pub fn prove_chunk(&self, index: usize) -> Result<MerkleProof, CapsuleError> {
+        let leaves = self.leaves()?;
+        if index >= leaves.len() {
+            return Err(CapsuleError::InvalidArgument(format!(
+                "chunk index {} out of range ({} chunks)",
+                index,
+                leaves.len()
+            )));
+        }
+
+        let mut siblings = Vec::new();
+        let mut level = leaves;
+        let mut i = index;
+        while level.len() > 1 {
+            let sibling = i ^ 1;
+            if sibling < level.len() {
+                siblings.push(hex::encode(level[sibling]));
+            }
+            level = next_level(&level);
+            i /= 2;
+        }
+
+        Ok(MerkleProof {
+            index: index as u64,
+            leaf_count: self.chunks.len() as u64,
+            siblings,
+        })
+    }
+}
+
+// ============================================================================
+// Inclusion Proofs
+// ============================================================================
+
+/// Sibling hashes from a chunk up to the Merkle root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: u64,
+    pub leaf_count: u64,
+    pub siblings: Vec<String>,
+}
+
+/// Check that `chunk` is chunk `proof.index` of the content committed to by
+/// `merkle_root`, without access to any other chunk. The root commits to the
+/// leaf count, so `proof.leaf_count` need not be trusted.
+pub fn verify_chunk(merkle_root: &str, chunk: &[u8], proof: &MerkleProof) -> bool {
+    let Ok(expected) = parse_digest(MERKLE_PREFIX, merkle_root) else {
+        return false;
+    };
+    if proof.index >= proof.leaf_count {
+        return false;
+    }
+
+    let mut current = leaf_hash(chunk);
+    let mut index = proof.index;
+    let mut width = proof.leaf_count;
+    let mut siblings = proof.siblings.iter();
+
+    while width > 1 {
+        let has_sibling = index % 2 == 1 || index + 1 < width;
+        if has_sibling {
+            let Some(sibling) = siblings.next() else {
+                return false;
+            };
+            let Ok(sibling) = hex::decode(sibling) else {
+                return false;
+            };
+            let Ok(sibling): Result<Digest32, _> = sibling.try_into() else {
+                return false;
+            };
+            current = if index % 2 == 1 {
+                node_hash(&sibling, &current)
+            } else {
+                node_hash(&current, &sibling)
+            };
+        }
+        index /= 2;
+        width = width.div_ceil(2);
+    }
+
+    siblings.next().is_none() && commit_root(proof.leaf_count, &current) == expected
+}
+
+// ============================================================================
+// Streaming Writer
+// ============================================================================
+
+/// Splits a byte stream into chunks, storing each chunk as soon as it is
+/// full so the whole payload never has to be held in memory.
+pub struct ChunkedWriter<'a, S: CapsuleStore + ?Sized> {
+    store: &'a S,
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    leaves: Vec<Digest32>,
+    total_len: u64,
+}
+
+impl<'a, S: CapsuleStore + ?Sized> ChunkedWriter<'a, S> {
+    pub fn new(store: &'a S) -> Self {
+        Self::with_chunk_size(store, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(store: &'a S, chunk_size: usize) -> Self {
+        assert!(
+            chunk_size > 0 && chunk_size <= u32::MAX as usize,
+            "chunk size must fit in u32 and be non-zero"
+        );
+        Self {
+            store,
+            chunk_size,
+            buffer: Vec::with_capacity(chunk_size),
+            leaves: Vec::new(),
+            total_len: 0,
+        }
+    }
+
+    fn flush_chunk(&mut self) -> Result<(), CapsuleError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let leaf = leaf_hash(&self.buffer);
+        self.store.put(&chunk_key(&leaf), &self.buffer)?;
+        self.leaves.push(leaf);
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Store the final partial chunk and return the manifest.
+    pub fn finish(mut self) -> Result<ChunkManifest, CapsuleError> {
+        self.flush_chunk()?;
+        Ok(ChunkManifest {
+            kind: MANIFEST_KIND.to_string(),
+            chunk_size: self.chunk_size as u32,
+            total_len: self.total_len,
+            chunks: self.leaves.iter().map(chunk_key).collect(),
+            merkle_root: format!(
+                "{}:{}",
+                MERKLE_PREFIX,
+                hex::encode(merkle_root(&self.leaves))
+            ),
+        })
+    }
+}
+
+impl<S: CapsuleStore + ?Sized> Write for ChunkedWriter<'_, S> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let room = self.chunk_size - self.buffer.len();
+        let take = room.min(data.len());
+        self.buffer.extend_from_slice(&data[..take]);
+        self.total_len += take as u64;
+        if self.buffer.len() == self.chunk_size {
+            self.flush_chunk().map_err(io::Error::other)?;
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Only full chunks are written early; a partial chunk must wait for
+        // `finish` so that every chunk but the last has the same size.
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Streaming Reader
+// ============================================================================
+
+/// Reads chunked content back from a store, verifying each chunk against
+/// the manifest as it is fetched.
+pub struct ChunkedReader<'a, S: CapsuleStore + ?Sized> {
+    store: &'a S,
+    manifest: &'a ChunkManifest,
+    next_chunk: usize,
+    current: Vec<u8>,
+    offset: usize,
+}
+
+impl<'a, S: CapsuleStore + ?Sized> ChunkedReader<'a, S> {
+    pub fn new(store: &'a S, manifest: &'a ChunkManifest) -> Self {
+        Self {
+            store,
+            manifest,
+            next_chunk: 0,
+            current: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Fetch and verify chunk `index` on its own.
+    pub fn read_chunk(
+        store: &S,
+        manifest: &ChunkManifest,
+        index: usize,
+    ) -> Result<Vec<u8>, CapsuleError> {
+        manifest.check_layout()?;
+        let key = manifest.chunks.get(index).ok_or_else(|| {
+            CapsuleError::InvalidArgument(format!("chunk index {} out of range", index))
+        })?;
+        let data = store
+            .get(key)?
+            .ok_or_else(|| CapsuleError::NotFound(key.clone()))?;
+        let actual = chunk_key(&leaf_hash(&data));
+        if actual != *key {
+            return Err(CapsuleError::HashMismatch {
+                expected: key.clone(),
+                actual,
+            });
+        }
+
+        let is_last = index + 1 == manifest.chunks.len();
+        let expected_len = if is_last {
+            manifest.total_len - (manifest.chunk_size as u64 * index as u64)
+        } else {
+            manifest.chunk_size as u64
+        };
+        if data.len() as u64 != expected_len {
+            return Err(CapsuleError::Decode(format!(
+                "chunk {} has {} bytes, expected {}",
+                key,
+                data.len(),
+                expected_len
+            )));
+        }
+        Ok(data)
+    }
+}
+
+impl<S: CapsuleStore + ?Sized> Read for ChunkedReader<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset == self.current.len() {
+            if self.next_chunk == self.manifest.chunks.len() {
+                return Ok(0);
+            }
+            self.current = Self::read_chunk(self.store, self.manifest, self.next_chunk)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.next_chunk += 1;
+            self.offset = 0;
+        }
+
+        let n = buf.len().min(self.current.len() - self.offset);
+        buf[..n].copy_from_slice(&self.current[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{FsStore, MemoryStore};
+    use crate::{create_child_capsule, create_root_capsule, generate_keypair, verify_capsule};
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn write_chunked(store: &MemoryStore, data: &[u8], chunk_size: usize) -> ChunkManifest {
+        let mut writer = ChunkedWriter::with_chunk_size(store, chunk_size);
+        // Feed in uneven pieces to exercise buffering
+        for piece in data.chunks(7) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let store = MemoryStore::new();
+        let data = payload(1000);
+        let manifest = write_chunked(&store, &data, 64);
+        assert_eq!(manifest.chunks.len(), 16);
+        assert_eq!(manifest.total_len, 1000);
+        manifest.validate().unwrap();
+
+        let mut out = Vec::new();
+        ChunkedReader::new(&store, &manifest)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn test_empty_content() {
+        let store = MemoryStore::new();
+        let manifest = write_chunked(&store, &[], 64);
+        assert!(manifest.chunks.is_empty());
+        manifest.validate().unwrap();
+
+        let mut out = Vec::new();
+        ChunkedReader::new(&store, &manifest)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_root_is_independent_of_write_pattern() {
+        let data = payload(300);
+        let a = write_chunked(&MemoryStore::new(), &data, 32);
+
+        let store = MemoryStore::new();
+        let mut writer = ChunkedWriter::with_chunk_size(&store, 32);
+        writer.write_all(&data).unwrap();
+        let b = writer.finish().unwrap();
+
+        assert_eq!(a, b);
+        assert_ne!(
+            a.merkle_root,
+            write_chunked(&MemoryStore::new(), &data, 64).merkle_root
+        );
+    }
+
+    #[test]
+    fn test_inclusion_proofs_for_every_chunk() {
+        let store = MemoryStore::new();
+        let data = payload(700);
+        // 11 chunks: exercises promotion of unpaired nodes at several levels
+        let manifest = write_chunked(&store, &data, 64);
+        assert_eq!(manifest.chunks.len(), 11);
+
+        for (index, chunk) in data.chunks(64).enumerate() {
+            let proof = manifest.prove_chunk(index).unwrap();
+            assert!(
+                verify_chunk(&manifest.merkle_root, chunk, &proof),
+                "chunk {}",
+                index
+            );
+
+            let mut wrong_index = proof.clone();
+            wrong_index.index = (proof.index + 1) % proof.leaf_count;
+            assert!(!verify_chunk(&manifest.merkle_root, chunk, &wrong_index));
+        }
+
+        let proof = manifest.prove_chunk(3).unwrap();
+        assert!(!verify_chunk(
+            &manifest.merkle_root,
+            b"forged chunk",
+            &proof
+        ));
+        assert!(manifest.prove_chunk(11).is_err());
+    }
+
+    #[test]
+    fn test_proof_cannot_claim_a_different_leaf_count() {
+        // With 3 leaves the root is node(node(a, b), c). Claiming a 2-leaf
+        // tree, c would be index 1 with sibling node(a, b).
+        let store = MemoryStore::new();
+        let data = payload(3 * 16);
+        let manifest = write_chunked(&store, &data, 16);
+        let leaves: Vec<Digest32> = data.chunks(16).map(leaf_hash).collect();
+
+        let forged = MerkleProof {
+            index: 1,
+            leaf_count: 2,
+            siblings: vec![hex::encode(node_hash(&leaves[0], &leaves[1]))],
+        };
+        assert!(!verify_chunk(&manifest.merkle_root, &data[32..], &forged));
+
+        let honest = manifest.prove_chunk(2).unwrap();
+        assert!(verify_chunk(&manifest.merkle_root, &data[32..], &honest));
+    }
+
+    #[test]
+    fn test_reader_rejects_inconsistent_manifest() {
+        let store = MemoryStore::new();
+        let mut manifest = write_chunked(&store, &payload(100), 64);
+
+        // The last chunk would start past the end of the content
+        manifest.total_len = 10;
+        assert!(matches!(
+            ChunkedReader::read_chunk(&store, &manifest, 1),
+            Err(CapsuleError::Decode(_))
+        ));
+
+        manifest.total_len = 100;
+        manifest.chunk_size = 0;
+        let mut out = Vec::new();
+        let err = ChunkedReader::new(&store, &manifest)
+            .read_to_end(&mut out)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_reader_detects_corrupted_chunk() {
+        let dir = std::env::temp_dir().join(format!("chunked_corrupt_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let store = FsStore::open(&dir).unwrap();
+        let mut writer = ChunkedWriter::with_chunk_size(&store, 64);
+        writer.write_all(&payload(200)).unwrap();
+        let manifest = writer.finish().unwrap();
+
+        // Overwrite the second chunk on disk behind the store's back
+        let path = store.blob_path(&manifest.chunks[1]).unwrap();
+        std::fs::write(&path, vec![0u8; 64]).unwrap();
+
+        let mut out = Vec::new();
+        let err = ChunkedReader::new(&store, &manifest)
+            .read_to_end(&mut out)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_chunked_capsule_commits_to_merkle_root() {
+        let store = MemoryStore::new();
+        let manifest = write_chunked(&store, &payload(5000), 1024);
+
+        let keypair = generate_keypair();
+        let root = create_root_capsule(&keypair, "Root".to_string()).unwrap();
+        let capsule = create_child_capsule(
+            &root,
+            &keypair,
+            "Framebuffer".to_string(),
+            manifest.to_content().unwrap(),
+        )
+        .unwrap();
+        assert!(verify_capsule(&capsule).content_hash_valid);
+        assert_eq!(ChunkManifest::from_capsule(&capsule).unwrap(), manifest);
+
+        // Swapping the root in the signed manifest breaks the capsule hash
+        let mut forged = manifest.clone();
+        forged.merkle_root = format!("{}:{}", MERKLE_PREFIX, "00".repeat(32));
+        let mut tampered = capsule.clone();
+        tampered.content = forged.to_content().unwrap();
+        assert!(!verify_capsule(&tampered).content_hash_valid);
+        assert!(ChunkManifest::from_capsule(&tampered).is_err());
+    }
+}
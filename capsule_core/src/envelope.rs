@@ -0,0 +1,274 @@
+// ============================================================================
+// Capsule Envelope and Format Migrations
+// ============================================================================
+//
+// Serialized capsules are wrapped in a fixed 8-byte header:
+//
+//   magic "CPSL" | format version (u16 BE) | hash algorithm (u16 BE)
+//
+// followed by the payload for that format version. Format 1 is the
+// canonical CBOR of `Capsule`. Bytes without the magic are treated as
+// format 0, the raw CBOR written before envelopes existed. The magic begins
+// with 0x43, a CBOR byte-string head, so it never collides with a legacy
+// capsule, which always starts with a map head.
+//
+// Older payloads are upgraded one version at a time by the functions in a
+// `MigrationRegistry` before they are decoded.
+
+use crate::{canonical_cbor, cbor, Capsule, CapsuleError, HashAlgorithm};
+use std::collections::BTreeMap;
+
+pub const ENVELOPE_MAGIC: [u8; 4] = *b"CPSL";
+
+/// The format version written by this crate.
+pub const CURRENT_FORMAT_VERSION: u16 = 1;
+
+/// Format version assigned to bytes that carry no envelope header.
+pub const LEGACY_FORMAT_VERSION: u16 = 0;
+
+const HEADER_LEN: usize = 8;
+
+/// A capsule payload tagged with its format version and hash algorithm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub format_version: u16,
+    pub hash_algorithm: HashAlgorithm,
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    /// Wrap `capsule` in an envelope of the current format.
+    pub fn seal(capsule: &Capsule) -> Result<Self, CapsuleError> {
+        Ok(Envelope {
+            format_version: CURRENT_FORMAT_VERSION,
+            hash_algorithm: HashAlgorithm::Sha256,
+            payload: canonical_cbor(capsule)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CapsuleError> {
+        let code = self.hash_algorithm.code();
+        let algorithm =
+            u16::try_from(code).map_err(|_| CapsuleError::UnknownHashAlgorithm(code))?;
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        bytes.extend_from_slice(&ENVELOPE_MAGIC);
+        bytes.extend_from_slice(&self.format_version.to_be_bytes());
+        bytes.extend_from_slice(&algorithm.to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        Ok(bytes)
+    }
+
+    /// Split `bytes` into header and payload. Headerless bytes are returned
+    /// as a [`LEGACY_FORMAT_VERSION`] envelope; the payload is not checked.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CapsuleError> {
+        if !bytes.starts_with(&ENVELOPE_MAGIC) {
+            return Ok(Envelope {
+                format_version: LEGACY_FORMAT_VERSION,
+                hash_algorithm: HashAlgorithm::Sha256,
+                payload: bytes.to_vec(),
+            });
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(CapsuleError::Decode(
+                "truncated capsule envelope header".to_string(),
+            ));
+        }
+
+        Ok(Envelope {
+            format_version: u16::from_be_bytes([bytes[4], bytes[5]]),
+            hash_algorithm: HashAlgorithm::from_code(
+                u16::from_be_bytes([bytes[6], bytes[7]]) as u64
+            )?,
+            payload: bytes[HEADER_LEN..].to_vec(),
+        })
+    }
+
+    /// Decode the capsule in a current-format envelope.
+    pub fn open(&self) -> Result<Capsule, CapsuleError> {
+        if self.format_version != CURRENT_FORMAT_VERSION {
+            return Err(CapsuleError::UnsupportedFormat(self.format_version));
+        }
+        cbor::decode(&self.payload)
+    }
+}
+
+// ============================================================================
+// Migration Registry
+// ============================================================================
+
+/// Converts a payload of one format version into the next version.
+pub type Migration = Box<dyn Fn(&[u8]) -> Result<Vec<u8>, CapsuleError> + Send + Sync>;
+
+/// Upgrade steps keyed by the format version they upgrade *from*.
+pub struct MigrationRegistry {
+    migrations: BTreeMap<u16, Migration>,
+}
+
+impl MigrationRegistry {
+    /// A registry with no migrations; only current-format envelopes load.
+    pub fn new() -> Self {
+        Self {
+            migrations: BTreeMap::new(),
+        }
+    }
+
+    /// Register the step from `from_version` to `from_version + 1`,
+    /// replacing any existing step for that version.
+    pub fn register<F>(&mut self, from_version: u16, migration: F)
+    where
+        F: Fn(&[u8]) -> Result<Vec<u8>, CapsuleError> + Send + Sync + 'static,
+    {
+        self.migrations.insert(from_version, Box::new(migration));
+    }
+
+    /// Apply migrations until `envelope` is in the current format.
+    pub fn upgrade(&self, mut envelope: Envelope) -> Result<Envelope, CapsuleError> {
+        if envelope.format_version > CURRENT_FORMAT_VERSION {
+            return Err(CapsuleError::UnsupportedFormat(envelope.format_version));
+        }
+        while envelope.format_version < CURRENT_FORMAT_VERSION {
+            let migration = self
+                .migrations
+                .get(&envelope.format_version)
+                .ok_or(CapsuleError::NoMigration(envelope.format_version))?;
+            envelope.payload = migration(&envelope.payload)?;
+            envelope.format_version += 1;
+        }
+        Ok(envelope)
+    }
+
+    /// Decode a serialized capsule of any known format version.
+    pub fn load(&self, bytes: &[u8]) -> Result<Capsule, CapsuleError> {
+        self.upgrade(Envelope::from_bytes(bytes)?)?.open()
+    }
+}
+
+impl Default for MigrationRegistry {
+    /// A registry holding the migrations shipped with this crate.
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(LEGACY_FORMAT_VERSION, migrate_legacy);
+        registry
+    }
+}
+
+/// Format 0 -> 1: legacy capsules were written by an encoder that did not
+/// sort map keys, so re-encode them canonically. The signature block is
+/// carried over unchanged: its content hash was computed over the legacy
+/// encoding, which capsule verification still accepts.
+fn migrate_legacy(payload: &[u8]) -> Result<Vec<u8>, CapsuleError> {
+    let capsule: Capsule =
+        ciborium::from_reader(payload).map_err(|e| CapsuleError::Decode(e.to_string()))?;
+    canonical_cbor(&capsule)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        create_root_capsule, deserialize_capsule, generate_keypair, serialize_capsule,
+        verify_capsule,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn sample() -> Capsule {
+        create_root_capsule(&generate_keypair(), "Envelope".to_string()).unwrap()
+    }
+
+    fn assert_same(a: &Capsule, b: &Capsule) {
+        assert_eq!(canonical_cbor(a).unwrap(), canonical_cbor(b).unwrap());
+    }
+
+    /// A root capsule written by `serialize_capsule` before envelopes and
+    /// deterministic CBOR existed, signed with the key `[7; 32]`.
+    const LEGACY_ROOT: &[u8] = include_bytes!("../tests/fixtures/legacy_root.capsule");
+    const LEGACY_ROOT_HASH: &str =
+        "b03566c595d0cc7f3b1d9e9679a6c0630654782185db696f4dc1bbd26f45fe47";
+
+    #[test]
+    fn test_header_layout() {
+        let capsule = sample();
+        let bytes = serialize_capsule(&capsule).unwrap();
+        assert_eq!(&bytes[..4], b"CPSL");
+        assert_eq!(&bytes[4..6], &CURRENT_FORMAT_VERSION.to_be_bytes());
+        assert_eq!(&bytes[6..8], &[0x00, 0x12]);
+        assert_eq!(&bytes[8..], canonical_cbor(&capsule).unwrap().as_slice());
+        assert_same(&deserialize_capsule(&bytes).unwrap(), &capsule);
+    }
+
+    #[test]
+    fn test_legacy_capsule_is_migrated() {
+        let envelope = Envelope::from_bytes(LEGACY_ROOT).unwrap();
+        assert_eq!(envelope.format_version, LEGACY_FORMAT_VERSION);
+
+        let capsule = deserialize_capsule(LEGACY_ROOT).unwrap();
+        assert_eq!(capsule.metadata.claim, "Baseline fixture");
+        assert_eq!(capsule.signature_block.content_hash, LEGACY_ROOT_HASH);
+
+        let proof = verify_capsule(&capsule);
+        assert!(proof.crypto_valid);
+        assert!(proof.content_hash_valid);
+        assert!(proof.root_lineage);
+
+        // Once migrated, the capsule round-trips through the current format
+        let current = serialize_capsule(&capsule).unwrap();
+        assert_same(&deserialize_capsule(&current).unwrap(), &capsule);
+        assert!(verify_capsule(&deserialize_capsule(&current).unwrap()).content_hash_valid);
+
+        assert!(matches!(
+            MigrationRegistry::new().load(LEGACY_ROOT),
+            Err(CapsuleError::NoMigration(0))
+        ));
+    }
+
+    #[test]
+    fn test_registered_migration_replaces_builtin() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = Arc::clone(&calls);
+        let mut registry = MigrationRegistry::default();
+        registry.register(LEGACY_FORMAT_VERSION, move |payload| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            migrate_legacy(payload)
+        });
+
+        let capsule = registry.load(LEGACY_ROOT).unwrap();
+        // Current-format bytes need no migration
+        registry
+            .load(&serialize_capsule(&capsule).unwrap())
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_unknown_versions_and_corruption_are_distinguished() {
+        let mut bytes = serialize_capsule(&sample()).unwrap();
+
+        let mut future = bytes.clone();
+        future[4..6].copy_from_slice(&(CURRENT_FORMAT_VERSION + 1).to_be_bytes());
+        assert!(matches!(
+            deserialize_capsule(&future),
+            Err(CapsuleError::UnsupportedFormat(v)) if v == CURRENT_FORMAT_VERSION + 1
+        ));
+
+        let mut unknown_hash = bytes.clone();
+        unknown_hash[6..8].copy_from_slice(&0xbeefu16.to_be_bytes());
+        assert!(matches!(
+            deserialize_capsule(&unknown_hash),
+            Err(CapsuleError::UnknownHashAlgorithm(0xbeef))
+        ));
+
+        assert!(matches!(
+            deserialize_capsule(&bytes[..6]),
+            Err(CapsuleError::Decode(_))
+        ));
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(deserialize_capsule(&bytes).is_err());
+    }
+}
@@ -0,0 +1,489 @@
+// ============================================================================
+// Hash Algorithms, Multihashes and CIDs
+// ============================================================================
+//
+// Content hashes are `H(prefix || canonical CBOR)` for a hash function H.
+// SHA-256 hashes keep the `prefix:hex` string form; other algorithms name
+// themselves as `prefix:algorithm:hex` so the two can never be confused.
+//
+// A content hash digest can be read back out as a multihash, and a CID is
+// that multihash under a codec: an object's CID, its content hash and its
+// store key all carry the same digest, so the CAS can name an object by any
+// of them. CIDv1s with codec dag-cbor are how the CAS names capsules at
+// boot. CIDv0 (`Qm...`) strings are accepted when parsing, since they are
+// SHA-256 multihashes. [`Cid::for_block`] hashes raw bytes alone, for blocks
+// that carry no type prefix.
+
+use crate::store::parse_key;
+use crate::{Capsule, CapsuleError};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+/// Multicodec code for CBOR-encoded IPLD data.
+pub const CODEC_DAG_CBOR: u64 = 0x71;
+
+/// Multicodec code for raw bytes.
+pub const CODEC_RAW: u64 = 0x55;
+
+/// Multicodec code for dag-pb, the implicit codec of CIDv0.
+pub const CODEC_DAG_PB: u64 = 0x70;
+
+/// Hash algorithms, identified by their multicodec codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn code(self) -> u64 {
+        match self {
+            HashAlgorithm::Sha256 => 0x12,
+            HashAlgorithm::Blake3 => 0x1e,
+        }
+    }
+
+    pub fn from_code(code: u64) -> Result<Self, CapsuleError> {
+        match code {
+            0x12 => Ok(HashAlgorithm::Sha256),
+            0x1e => Ok(HashAlgorithm::Blake3),
+            other => Err(CapsuleError::UnknownHashAlgorithm(other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha2-256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// The algorithm whose [`name`](Self::name) is `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [HashAlgorithm::Sha256, HashAlgorithm::Blake3]
+            .into_iter()
+            .find(|a| a.name() == name)
+    }
+
+    /// Hash the concatenation of `parts`.
+    pub fn digest(self, parts: &[&[u8]]) -> [u8; 32] {
+        match self {
+            HashAlgorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                for part in parts {
+                    hasher.update(part);
+                }
+                hasher.finalize().into()
+            }
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                for part in parts {
+                    hasher.update(part);
+                }
+                hasher.finalize().into()
+            }
+        }
+    }
+}
+
+/// A hash function chosen at compile time, used to parameterize
+/// [`crate::ContentAddressable`].
+pub trait ContentHasher {
+    const ALGORITHM: HashAlgorithm;
+}
+
+pub struct Sha256Hasher;
+
+impl ContentHasher for Sha256Hasher {
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Sha256;
+}
+
+pub struct Blake3Hasher;
+
+impl ContentHasher for Blake3Hasher {
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Blake3;
+}
+
+/// [`crate::compute_content_hash_with_prefix`] for any hash algorithm.
+pub fn compute_content_hash_with<H: ContentHasher>(prefix: &str, cbor_data: &[u8]) -> String {
+    let digest = H::ALGORITHM.digest(&[prefix.as_bytes(), cbor_data]);
+    match H::ALGORITHM {
+        HashAlgorithm::Sha256 => format!("{}:{}", prefix, hex::encode(digest)),
+        other => format!("{}:{}:{}", prefix, other.name(), hex::encode(digest)),
+    }
+}
+
+// ============================================================================
+// Multihash
+// ============================================================================
+
+/// A digest tagged with the algorithm that produced it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Multihash {
+    pub algorithm: HashAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl Multihash {
+    pub fn new(algorithm: HashAlgorithm, digest: Vec<u8>) -> Self {
+        Self { algorithm, digest }
+    }
+
+    /// Read the digest out of a content hash string (`prefix:hex`,
+    /// `prefix:algorithm:hex` or bare SHA-256 hex). Accepts exactly the keys
+    /// the CAS accepts.
+    pub fn from_content_hash(hash: &str) -> Result<Self, CapsuleError> {
+        let key = parse_key(hash)?;
+        let digest =
+            hex::decode(key.digest).map_err(|_| CapsuleError::InvalidHash(hash.to_string()))?;
+        if digest.len() != 32 {
+            return Err(CapsuleError::InvalidHash(hash.to_string()));
+        }
+        Ok(Self {
+            algorithm: key.algorithm,
+            digest,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.digest.len() + 4);
+        write_varint(&mut bytes, self.algorithm.code());
+        write_varint(&mut bytes, self.digest.len() as u64);
+        bytes.extend_from_slice(&self.digest);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CapsuleError> {
+        let (multihash, rest) = Self::read(bytes)?;
+        if !rest.is_empty() {
+            return Err(CapsuleError::InvalidCid(
+                "trailing bytes after multihash".to_string(),
+            ));
+        }
+        Ok(multihash)
+    }
+
+    fn read(bytes: &[u8]) -> Result<(Self, &[u8]), CapsuleError> {
+        let (code, rest) = read_varint(bytes)?;
+        let (len, rest) = read_varint(rest)?;
+        let len = len as usize;
+        if rest.len() < len {
+            return Err(CapsuleError::InvalidCid("truncated multihash".to_string()));
+        }
+        let multihash = Self {
+            algorithm: HashAlgorithm::from_code(code)?,
+            digest: rest[..len].to_vec(),
+        };
+        Ok((multihash, &rest[len..]))
+    }
+}
+
+// ============================================================================
+// CIDs
+// ============================================================================
+
+/// A content identifier: a multihash plus the codec of the hashed bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cid {
+    pub version: u8,
+    pub codec: u64,
+    pub hash: Multihash,
+}
+
+impl Cid {
+    pub fn new_v1(codec: u64, hash: Multihash) -> Self {
+        Self {
+            version: 1,
+            codec,
+            hash,
+        }
+    }
+
+    /// The CIDv1 of `block`, hashing exactly those bytes.
+    pub fn for_block(codec: u64, algorithm: HashAlgorithm, block: &[u8]) -> Self {
+        let digest = algorithm.digest(&[block]);
+        Self::new_v1(codec, Multihash::new(algorithm, digest.to_vec()))
+    }
+
+    /// The CID the CAS uses for `capsule`: the digest of its signed content
+    /// hash, which is also the key [`crate::put_capsule`] stores it under.
+    pub fn for_capsule(capsule: &Capsule) -> Result<Self, CapsuleError> {
+        let hash = Multihash::from_content_hash(&capsule.signature_block.content_hash)?;
+        Ok(Self::new_v1(CODEC_DAG_CBOR, hash))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if self.version == 0 {
+            return self.hash.to_bytes();
+        }
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, self.version as u64);
+        write_varint(&mut bytes, self.codec);
+        bytes.extend_from_slice(&self.hash.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CapsuleError> {
+        // A CIDv0 is a bare SHA-256 multihash: 0x12 0x20 <digest>
+        if bytes.len() == 34 && bytes[0] == 0x12 && bytes[1] == 0x20 {
+            return Ok(Self {
+                version: 0,
+                codec: CODEC_DAG_PB,
+                hash: Multihash::from_bytes(bytes)?,
+            });
+        }
+
+        let (version, rest) = read_varint(bytes)?;
+        if version != 1 {
+            return Err(CapsuleError::InvalidCid(format!(
+                "unsupported CID version {}",
+                version
+            )));
+        }
+        let (codec, rest) = read_varint(rest)?;
+        Ok(Self::new_v1(codec, Multihash::from_bytes(rest)?))
+    }
+}
+
+impl fmt::Display for Cid {
+    /// CIDv0 in base58btc, CIDv1 in multibase base32 (`b...`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.version == 0 {
+            write!(f, "{}", bs58::encode(self.to_bytes()).into_string())
+        } else {
+            write!(f, "b{}", base32_encode(&self.to_bytes()))
+        }
+    }
+}
+
+impl FromStr for Cid {
+    type Err = CapsuleError;
+
+    fn from_str(s: &str) -> Result<Self, CapsuleError> {
+        let bytes = if s.len() == 46 && s.starts_with("Qm") {
+            bs58::decode(s)
+                .into_vec()
+                .map_err(|e| CapsuleError::InvalidCid(e.to_string()))?
+        } else if let Some(encoded) = s.strip_prefix('b') {
+            base32_decode(encoded)
+                .ok_or_else(|| CapsuleError::InvalidCid(format!("bad base32 in {}", s)))?
+        } else {
+            return Err(CapsuleError::InvalidCid(format!(
+                "unsupported multibase in {}",
+                s
+            )));
+        };
+        Self::from_bytes(&bytes)
+    }
+}
+
+// ============================================================================
+// Encoding Helpers
+// ============================================================================
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read an unsigned LEB128 varint, limited to 9 bytes as multiformats
+/// require, rejecting non-minimal encodings.
+fn read_varint(bytes: &[u8]) -> Result<(u64, &[u8]), CapsuleError> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(9) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            if byte == 0 && i > 0 {
+                return Err(CapsuleError::InvalidCid("non-minimal varint".to_string()));
+            }
+            return Ok((value, &bytes[i + 1..]));
+        }
+    }
+    Err(CapsuleError::InvalidCid("truncated varint".to_string()))
+}
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// RFC 4648 base32, lowercase, without padding.
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    // Leftover bits are padding and must be zero
+    if bits >= 5 || buffer & ((1 << bits) - 1) != 0 {
+        return None;
+    }
+    Some(out)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_content_hash_with_prefix, CanonicalSerialize, ContentAddressable, Glyph};
+
+    fn glyph() -> Glyph {
+        Glyph {
+            name: "ident".to_string(),
+            version: 1,
+            payload: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn test_sha256_matches_legacy_prefix_hash() {
+        let data = b"\xa1\x61a\x01";
+        assert_eq!(
+            compute_content_hash_with::<Sha256Hasher>("GlyphV1", data),
+            compute_content_hash_with_prefix("GlyphV1", data)
+        );
+        assert_eq!(
+            glyph().content_hash_with::<Sha256Hasher>(),
+            glyph().content_hash()
+        );
+    }
+
+    #[test]
+    fn test_blake3_content_hash() {
+        let g = glyph();
+        let hash = g.content_hash_with::<Blake3Hasher>();
+        let expected = blake3::hash(&[b"GlyphV1".as_slice(), &g.canonical_serialize()].concat());
+        assert_eq!(hash, format!("GlyphV1:blake3:{}", expected.to_hex()));
+        assert_ne!(hash, g.content_hash());
+
+        let multihash = Multihash::from_content_hash(&hash).unwrap();
+        assert_eq!(multihash.algorithm, HashAlgorithm::Blake3);
+        assert_eq!(multihash.digest, expected.as_bytes().to_vec());
+
+        // A prefix may not pass for an algorithm name, as in the store
+        let untyped = format!("blake3:{}", expected.to_hex());
+        assert!(Multihash::from_content_hash(&untyped).is_err());
+        assert!(crate::store::parse_key(&untyped).is_err());
+        let sha = format!("GlyphV1:{}", "00".repeat(32));
+        assert_eq!(
+            Multihash::from_content_hash(&sha).unwrap().algorithm,
+            HashAlgorithm::Sha256
+        );
+    }
+
+    #[test]
+    fn test_known_cid_vectors() {
+        // sha2-256 of the empty string, as a raw-codec CIDv1 and a CIDv0
+        let digest =
+            hex::decode("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+                .unwrap();
+        let v1 = Cid::new_v1(CODEC_RAW, Multihash::new(HashAlgorithm::Sha256, digest));
+        assert_eq!(
+            v1.to_string(),
+            "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku"
+        );
+        assert_eq!(v1.to_string().parse::<Cid>().unwrap(), v1);
+
+        let v0: Cid = "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n"
+            .parse()
+            .unwrap();
+        assert_eq!(v0.version, 0);
+        assert_eq!(v0.codec, CODEC_DAG_PB);
+        assert_eq!(v0.hash.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(
+            v0.to_string(),
+            "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n"
+        );
+    }
+
+    #[test]
+    fn test_capsule_cid_roundtrip() {
+        let keypair = crate::generate_keypair();
+        let capsule = crate::create_root_capsule(&keypair, "CID".to_string()).unwrap();
+
+        let cid = Cid::for_capsule(&capsule).unwrap();
+        let parsed: Cid = cid.to_string().parse().unwrap();
+        assert_eq!(parsed, cid);
+        assert_eq!(parsed.codec, CODEC_DAG_CBOR);
+
+        let blake = glyph().cid::<Blake3Hasher>();
+        assert_eq!(blake.to_string().parse::<Cid>().unwrap(), blake);
+    }
+
+    #[test]
+    fn test_cids_carry_the_content_hash_digest() {
+        let g = glyph();
+        let prefixed = [b"GlyphV1".as_slice(), &g.canonical_serialize()].concat();
+
+        let cid = g.cid::<Sha256Hasher>();
+        assert_eq!(cid.hash.digest, Sha256::digest(&prefixed).to_vec());
+        assert_eq!(
+            Multihash::from_content_hash(&g.content_hash()).unwrap(),
+            cid.hash
+        );
+
+        let blake = g.cid::<Blake3Hasher>();
+        assert_eq!(
+            blake.hash.digest,
+            blake3::hash(&prefixed).as_bytes().to_vec()
+        );
+        assert_eq!(
+            Multihash::from_content_hash(&g.content_hash_with::<Blake3Hasher>()).unwrap(),
+            blake.hash
+        );
+
+        // A capsule's CID names the key the store holds it under
+        let keypair = crate::generate_keypair();
+        let capsule = crate::create_root_capsule(&keypair, "Block".to_string()).unwrap();
+        let store = crate::MemoryStore::new();
+        let key = crate::put_capsule(&store, &capsule).unwrap();
+        assert_eq!(
+            Cid::for_capsule(&capsule).unwrap().hash,
+            Multihash::from_content_hash(&key).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_malformed_cids_rejected() {
+        for bad in ["", "b", "bafy!", "zQm", "b7777777777777"] {
+            assert!(bad.parse::<Cid>().is_err(), "accepted {:?}", bad);
+        }
+        // Unknown hash algorithm code
+        let mut bytes = Cid::for_block(CODEC_RAW, HashAlgorithm::Sha256, b"").to_bytes();
+        bytes[2] = 0x13;
+        assert!(matches!(
+            Cid::from_bytes(&bytes),
+            Err(CapsuleError::UnknownHashAlgorithm(0x13))
+        ));
+        assert!(Multihash::from_content_hash("GlyphV1:md5:00").is_err());
+    }
+}
@@ -0,0 +1,957 @@
+// ============================================================================
+// Key Management: Key Ids, Keyring, Rotation and Revocation
+// ============================================================================
+//
+// Keys are named by `KeyV1:<sha256>` ids derived from the public key, so an
+// id is stable across machines. A keyring directory looks like:
+//
+//   <dir>/<hex>.key             secret key, PEM-like, optionally encrypted
+//   <dir>/rotations/<hex>.cbor  rotation record, keyed by the *old* key
+//   <dir>/revoked/<hex>.capsule revocation capsule, keyed by the revoked key
+//
+// A revoked key invalidates every capsule it signed, regardless of the
+// capsule's timestamp: once a key has leaked, timestamps it signed are
+// worthless. Only the `Keyring` methods know about revocations; the free
+// verification functions check signatures alone.
+//
+// Key files are written to a temporary file and renamed into place, and on
+// Unix they are created readable by their owner only.
+
+use crate::attestation::{check_attestation, Attestation};
+use crate::multisig::{verify_multisig, MultiSigResult, TrustPolicy};
+use crate::{
+    canonical_cbor, cbor, compute_content_hash_with_prefix, load_capsule, seal_capsule,
+    serialize_capsule, verify_capsule, verify_chain, Capsule, CapsuleError, CapsuleMetadata,
+    ChainBreak, Clock, ProofResult, SystemClock,
+};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const PEM_BEGIN: &str = "-----BEGIN CAPSULE SIGNING KEY-----";
+const PEM_END: &str = "-----END CAPSULE SIGNING KEY-----";
+const ENCRYPTION_NONE: &str = "none";
+const ENCRYPTION_CHACHA: &str = "chacha20poly1305-pbkdf2-sha256";
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// Range of PBKDF2 rounds accepted from a key file. Fewer rounds would make
+/// the passphrase cheap to brute-force; more would let a crafted file stall
+/// the process.
+const PBKDF2_ROUNDS_RANGE: std::ops::RangeInclusive<u32> = 10_000..=10_000_000;
+
+// ============================================================================
+// Key Ids
+// ============================================================================
+
+/// Stable identifier for a public key: `KeyV1:<hex sha256>`.
+pub fn key_id(public_key: &VerifyingKey) -> String {
+    compute_content_hash_with_prefix("KeyV1", public_key.as_bytes())
+}
+
+fn key_id_hex(id: &str) -> &str {
+    id.split_once(':').map(|(_, hex)| hex).unwrap_or(id)
+}
+
+// ============================================================================
+// PEM-like Key Encoding
+// ============================================================================
+
+fn derive_file_key(passphrase: &str, salt: &[u8], rounds: u32) -> Key {
+    let mut key = Key::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, rounds, &mut key);
+    key
+}
+
+/// Encode a signing key in the keyring's PEM-like text format. With a
+/// passphrase the secret is sealed with ChaCha20-Poly1305 under a
+/// PBKDF2-SHA256 derived key.
+pub fn encode_key_pem(key: &SigningKey, passphrase: Option<&str>) -> Result<String, CapsuleError> {
+    let mut headers = vec![format!("Key-Id: {}", key_id(&key.verifying_key()))];
+
+    let body = match passphrase {
+        None => {
+            headers.push(format!("Encryption: {}", ENCRYPTION_NONE));
+            hex::encode(key.to_bytes())
+        }
+        Some(passphrase) => {
+            let mut salt = [0u8; 16];
+            let mut nonce = [0u8; 12];
+            rand::rngs::OsRng.fill_bytes(&mut salt);
+            rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+            let cipher = ChaCha20Poly1305::new(&derive_file_key(passphrase, &salt, PBKDF2_ROUNDS));
+            let sealed = cipher
+                .encrypt(Nonce::from_slice(&nonce), key.to_bytes().as_slice())
+                .map_err(|_| CapsuleError::Crypto("failed to encrypt signing key".to_string()))?;
+
+            headers.push(format!("Encryption: {}", ENCRYPTION_CHACHA));
+            headers.push(format!("Rounds: {}", PBKDF2_ROUNDS));
+            headers.push(format!("Salt: {}", hex::encode(salt)));
+            headers.push(format!("Nonce: {}", hex::encode(nonce)));
+            hex::encode(sealed)
+        }
+    };
+
+    Ok(format!(
+        "{}\n{}\n\n{}\n{}\n",
+        PEM_BEGIN,
+        headers.join("\n"),
+        body,
+        PEM_END
+    ))
+}
+
+/// Decode a key produced by [`encode_key_pem`], checking it against its
+/// recorded key id.
+pub fn decode_key_pem(text: &str, passphrase: Option<&str>) -> Result<SigningKey, CapsuleError> {
+    let inner = text
+        .trim()
+        .strip_prefix(PEM_BEGIN)
+        .and_then(|rest| rest.strip_suffix(PEM_END))
+        .ok_or_else(|| CapsuleError::InvalidKey("missing BEGIN/END markers".to_string()))?;
+
+    let (header_text, body) = inner
+        .trim()
+        .split_once("\n\n")
+        .ok_or_else(|| CapsuleError::InvalidKey("missing header separator".to_string()))?;
+
+    let headers: BTreeMap<&str, &str> = header_text
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .collect();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .copied()
+            .ok_or_else(|| CapsuleError::InvalidKey(format!("missing {} header", name)))
+    };
+    let hex_header = |name: &str| {
+        hex::decode(header(name)?)
+            .map_err(|e| CapsuleError::InvalidKey(format!("bad {} header: {}", name, e)))
+    };
+
+    let body = hex::decode(body.trim())
+        .map_err(|e| CapsuleError::InvalidKey(format!("bad key body: {}", e)))?;
+
+    let secret = match header("Encryption")? {
+        ENCRYPTION_NONE => body,
+        ENCRYPTION_CHACHA => {
+            let passphrase = passphrase.ok_or_else(|| {
+                CapsuleError::Crypto("key file is encrypted; passphrase required".to_string())
+            })?;
+            let rounds: u32 = header("Rounds")?
+                .parse()
+                .map_err(|_| CapsuleError::InvalidKey("bad Rounds header".to_string()))?;
+            if !PBKDF2_ROUNDS_RANGE.contains(&rounds) {
+                return Err(CapsuleError::InvalidKey(format!(
+                    "Rounds {} outside {}..={}",
+                    rounds,
+                    PBKDF2_ROUNDS_RANGE.start(),
+                    PBKDF2_ROUNDS_RANGE.end()
+                )));
+            }
+            let salt = hex_header("Salt")?;
+            let nonce = hex_header("Nonce")?;
+            if nonce.len() != 12 {
+                return Err(CapsuleError::InvalidKey("bad Nonce length".to_string()));
+            }
+
+            let cipher = ChaCha20Poly1305::new(&derive_file_key(passphrase, &salt, rounds));
+            cipher
+                .decrypt(Nonce::from_slice(&nonce), body.as_slice())
+                .map_err(|_| {
+                    CapsuleError::Crypto(
+                        "failed to decrypt key: wrong passphrase or corrupted file".to_string(),
+                    )
+                })?
+        }
+        other => {
+            return Err(CapsuleError::InvalidKey(format!(
+                "unsupported key encryption: {}",
+                other
+            )))
+        }
+    };
+
+    let secret: [u8; 32] = secret
+        .try_into()
+        .map_err(|_| CapsuleError::InvalidKey("secret key must be 32 bytes".to_string()))?;
+    let key = SigningKey::from_bytes(&secret);
+
+    let expected_id = header("Key-Id")?;
+    let actual_id = key_id(&key.verifying_key());
+    if expected_id != actual_id {
+        return Err(CapsuleError::InvalidKey(format!(
+            "key id mismatch: file says {}, key is {}",
+            expected_id, actual_id
+        )));
+    }
+
+    Ok(key)
+}
+
+// ============================================================================
+// Rotation Records
+// ============================================================================
+
+/// A statement by `old_key` that `new_key` is its successor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    #[serde(with = "serde_bytes")]
+    pub old_key: [u8; 32],
+    #[serde(with = "serde_bytes")]
+    pub new_key: [u8; 32],
+    pub timestamp: u64,
+    #[serde(with = "serde_bytes")]
+    pub signature: [u8; 64],
+}
+
+#[derive(Serialize)]
+struct RotationStatement<'a> {
+    kind: &'static str,
+    #[serde(with = "serde_bytes")]
+    old_key: &'a [u8; 32],
+    #[serde(with = "serde_bytes")]
+    new_key: &'a [u8; 32],
+    timestamp: u64,
+}
+
+fn rotation_statement(
+    old_key: &[u8; 32],
+    new_key: &[u8; 32],
+    timestamp: u64,
+) -> Result<Vec<u8>, CapsuleError> {
+    canonical_cbor(&RotationStatement {
+        kind: "KeyRotationV1",
+        old_key,
+        new_key,
+        timestamp,
+    })
+}
+
+/// Have `old_key` endorse `new_key` as its successor.
+pub fn create_rotation(
+    old_key: &SigningKey,
+    new_key: &VerifyingKey,
+) -> Result<KeyRotation, CapsuleError> {
+    create_rotation_with_clock(old_key, new_key, &SystemClock)
+}
+
+/// [`create_rotation`] with the timestamp taken from `clock`.
+pub fn create_rotation_with_clock(
+    old_key: &SigningKey,
+    new_key: &VerifyingKey,
+    clock: &dyn Clock,
+) -> Result<KeyRotation, CapsuleError> {
+    let old = old_key.verifying_key().to_bytes();
+    let new = new_key.to_bytes();
+    if old == new {
+        return Err(CapsuleError::InvalidArgument(
+            "cannot rotate a key to itself".to_string(),
+        ));
+    }
+
+    let timestamp = clock.now();
+    let statement = rotation_statement(&old, &new, timestamp)?;
+
+    Ok(KeyRotation {
+        old_key: old,
+        new_key: new,
+        timestamp,
+        signature: old_key.sign(&statement).to_bytes(),
+    })
+}
+
+/// Check that a rotation record was signed by its old key.
+pub fn verify_rotation(rotation: &KeyRotation) -> bool {
+    let Ok(statement) =
+        rotation_statement(&rotation.old_key, &rotation.new_key, rotation.timestamp)
+    else {
+        return false;
+    };
+    let Ok(old_key) = VerifyingKey::from_bytes(&rotation.old_key) else {
+        return false;
+    };
+    old_key
+        .verify(&statement, &Signature::from_bytes(&rotation.signature))
+        .is_ok()
+}
+
+// ============================================================================
+// Revocation Capsules
+// ============================================================================
+
+/// Content of a revocation capsule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevocationStatement {
+    pub key_id: String,
+    #[serde(with = "serde_bytes")]
+    pub public_key: [u8; 32],
+}
+
+/// Create a capsule revoking `revoked`, signed by `signer`. The signer must
+/// be the revoked key itself or a key the keyring already trusts to act for
+/// it (see [`Keyring::add_revocation`]).
+pub fn create_revocation_capsule(
+    signer: &SigningKey,
+    revoked: &VerifyingKey,
+    reason: String,
+) -> Result<Capsule, CapsuleError> {
+    create_revocation_capsule_with_clock(signer, revoked, reason, &SystemClock)
+}
+
+/// [`create_revocation_capsule`] with the timestamp taken from `clock`.
+pub fn create_revocation_capsule_with_clock(
+    signer: &SigningKey,
+    revoked: &VerifyingKey,
+    reason: String,
+    clock: &dyn Clock,
+) -> Result<Capsule, CapsuleError> {
+    let statement = RevocationStatement {
+        key_id: key_id(revoked),
+        public_key: revoked.to_bytes(),
+    };
+
+    let metadata = CapsuleMetadata {
+        id: format!("revoke:{}", statement.key_id),
+        version: "1.0.0".to_string(),
+        timestamp: clock.now(),
+        parent_hash: None,
+        claim: reason,
+    };
+
+    seal_capsule(signer, metadata, canonical_cbor(&statement)?)
+}
+
+// ============================================================================
+// Keyring
+// ============================================================================
+
+/// A set of signing keys plus the rotation and revocation records that
+/// decide which public keys are still trustworthy.
+#[derive(Debug, Default)]
+pub struct Keyring {
+    dir: Option<PathBuf>,
+    keys: BTreeMap<String, SigningKey>,
+    rotations: BTreeMap<String, KeyRotation>,
+    revocations: BTreeMap<String, Capsule>,
+}
+
+impl Keyring {
+    /// An empty keyring that is never written to disk.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Open the keyring stored in `dir`, creating it if necessary.
+    /// `passphrase` is used for any encrypted key files.
+    pub fn open<P: AsRef<Path>>(dir: P, passphrase: Option<&str>) -> Result<Self, CapsuleError> {
+        let dir = dir.as_ref().to_path_buf();
+        for sub in ["", "rotations", "revoked"] {
+            fs::create_dir_all(dir.join(sub)).map_err(|e| CapsuleError::io(&dir, e))?;
+        }
+
+        let mut keyring = Self {
+            dir: Some(dir.clone()),
+            ..Self::default()
+        };
+
+        for path in list_files(&dir, "key")? {
+            let text = fs::read_to_string(&path).map_err(|e| CapsuleError::io(&path, e))?;
+            let key = decode_key_pem(&text, passphrase)?;
+            keyring.keys.insert(key_id(&key.verifying_key()), key);
+        }
+
+        for path in list_files(&dir.join("rotations"), "cbor")? {
+            let data = fs::read(&path).map_err(|e| CapsuleError::io(&path, e))?;
+            let rotation: KeyRotation = cbor::decode(&data)?;
+            keyring.record_rotation(rotation)?;
+        }
+
+        // Revocations were authorized when they were added. Revoking the
+        // key that signed one later does not undo it, so on reload only the
+        // records themselves are checked, whatever order they load in.
+        for path in list_files(&dir.join("revoked"), "capsule")? {
+            let revocation = load_capsule(&path.to_string_lossy())?;
+            let revoked_id = check_revocation(&revocation)?;
+            keyring.revocations.insert(revoked_id, revocation);
+        }
+
+        Ok(keyring)
+    }
+
+    /// Ids of all secret keys held by this keyring.
+    pub fn key_ids(&self) -> Vec<String> {
+        self.keys.keys().cloned().collect()
+    }
+
+    pub fn get_key(&self, id: &str) -> Option<&SigningKey> {
+        self.keys.get(id)
+    }
+
+    /// Add a secret key, writing it to disk (encrypted if `passphrase` is
+    /// given) for on-disk keyrings. Returns the key id.
+    pub fn add_key(
+        &mut self,
+        key: SigningKey,
+        passphrase: Option<&str>,
+    ) -> Result<String, CapsuleError> {
+        let id = key_id(&key.verifying_key());
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("{}.key", key_id_hex(&id)));
+            write_private_file(&path, encode_key_pem(&key, passphrase)?.as_bytes())?;
+        }
+        self.keys.insert(id.clone(), key);
+        Ok(id)
+    }
+
+    /// Generate `new_key`'s endorsement by the held key `old_id`, then add
+    /// both the new key and the rotation record to the keyring.
+    pub fn rotate(
+        &mut self,
+        old_id: &str,
+        new_key: SigningKey,
+        passphrase: Option<&str>,
+    ) -> Result<KeyRotation, CapsuleError> {
+        self.rotate_with_clock(old_id, new_key, passphrase, &SystemClock)
+    }
+
+    /// [`Keyring::rotate`] with the rotation timestamp taken from `clock`.
+    pub fn rotate_with_clock(
+        &mut self,
+        old_id: &str,
+        new_key: SigningKey,
+        passphrase: Option<&str>,
+        clock: &dyn Clock,
+    ) -> Result<KeyRotation, CapsuleError> {
+        let old_key = self
+            .keys
+            .get(old_id)
+            .ok_or_else(|| CapsuleError::UnknownKey(old_id.to_string()))?;
+        let rotation = create_rotation_with_clock(old_key, &new_key.verifying_key(), clock)?;
+        self.add_key(new_key, passphrase)?;
+        self.add_rotation(rotation.clone())?;
+        Ok(rotation)
+    }
+
+    /// Record a rotation produced elsewhere.
+    pub fn add_rotation(&mut self, rotation: KeyRotation) -> Result<(), CapsuleError> {
+        self.record_rotation(rotation.clone())?;
+        if let Some(dir) = &self.dir {
+            let old_id = key_id_for_bytes(&rotation.old_key)?;
+            let path = dir
+                .join("rotations")
+                .join(format!("{}.cbor", key_id_hex(&old_id)));
+            write_private_file(&path, &canonical_cbor(&rotation)?)?;
+        }
+        Ok(())
+    }
+
+    fn record_rotation(&mut self, rotation: KeyRotation) -> Result<(), CapsuleError> {
+        if !verify_rotation(&rotation) {
+            return Err(CapsuleError::SignatureInvalid {
+                key_id: key_id_for_bytes(&rotation.old_key)?,
+                content_hash: "KeyRotationV1".to_string(),
+            });
+        }
+        let old_id = key_id_for_bytes(&rotation.old_key)?;
+        if let Some(existing) = self.rotations.get(&old_id) {
+            if existing.new_key != rotation.new_key {
+                return Err(CapsuleError::InvalidArgument(format!(
+                    "key {} was already rotated to a different key",
+                    old_id
+                )));
+            }
+        }
+        self.rotations.insert(old_id, rotation);
+        Ok(())
+    }
+
+    /// Follow rotation records from `id` to the newest successor key.
+    pub fn current_key_id(&self, id: &str) -> String {
+        let mut current = id.to_string();
+        // Rotations form a chain; cap the walk in case records form a loop
+        for _ in 0..=self.rotations.len() {
+            match self
+                .rotations
+                .get(&current)
+                .and_then(|r| key_id_for_bytes(&r.new_key).ok())
+            {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        current
+    }
+
+    /// Accept a revocation capsule. It must verify and be signed either by
+    /// the revoked key itself, by a key this keyring holds, or by a
+    /// successor the revoked key rotated to.
+    pub fn add_revocation(&mut self, revocation: Capsule) -> Result<(), CapsuleError> {
+        self.record_revocation(revocation.clone())?;
+        if let Some(dir) = &self.dir {
+            let statement: RevocationStatement = cbor::decode(&revocation.content)?;
+            let path = dir
+                .join("revoked")
+                .join(format!("{}.capsule", key_id_hex(&statement.key_id)));
+            write_private_file(&path, &serialize_capsule(&revocation)?)?;
+        }
+        Ok(())
+    }
+
+    fn record_revocation(&mut self, revocation: Capsule) -> Result<(), CapsuleError> {
+        let revoked_id = check_revocation(&revocation)?;
+        let statement: RevocationStatement = cbor::decode(&revocation.content)?;
+        let signer = revocation.signature_block.public_key;
+        let signer_id = key_id_for_bytes(&signer)?;
+        let authorized = signer == statement.public_key
+            || (self.keys.contains_key(&signer_id) && !self.is_revoked(&signer))
+            || self
+                .rotations
+                .get(&revoked_id)
+                .is_some_and(|r| r.new_key == signer);
+        if !authorized {
+            return Err(CapsuleError::InvalidArgument(format!(
+                "key {} is not allowed to revoke {}",
+                signer_id, revoked_id
+            )));
+        }
+
+        self.revocations.insert(revoked_id, revocation);
+        Ok(())
+    }
+
+    pub fn is_revoked(&self, public_key: &[u8; 32]) -> bool {
+        key_id_for_bytes(public_key)
+            .map(|id| self.revocations.contains_key(&id))
+            .unwrap_or(false)
+    }
+
+    /// [`verify_capsule`], additionally failing capsules signed by a
+    /// revoked key. A revoked signature is reported as `crypto_valid: false`
+    /// with `key_revoked: true`.
+    pub fn verify_capsule(&self, capsule: &Capsule) -> ProofResult {
+        let mut result = verify_capsule(capsule);
+        if self.is_revoked(&capsule.signature_block.public_key) {
+            result.crypto_valid = false;
+            result.key_revoked = true;
+        }
+        result
+    }
+
+    /// [`crate::verify_chain`], additionally failing the chain at the first
+    /// capsule signed by a revoked key.
+    pub fn verify_chain(&self, chain: &[Capsule]) -> Result<(), ChainBreak> {
+        verify_chain(chain)?;
+        for (index, capsule) in chain.iter().enumerate() {
+            let public_key = &capsule.signature_block.public_key;
+            if self.is_revoked(public_key) {
+                return Err(ChainBreak::KeyRevoked {
+                    index,
+                    id: capsule.metadata.id.clone(),
+                    key_id: key_id_for_bytes(public_key).unwrap_or_default(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// [`crate::verify_multisig`], not counting signers whose key is
+    /// revoked. They are reported in `revoked_signers` instead.
+    pub fn verify_multisig(&self, capsule: &Capsule, policy: &TrustPolicy) -> MultiSigResult {
+        let mut result = verify_multisig(capsule, policy);
+        let (revoked, valid) = result
+            .valid_signers
+            .into_iter()
+            .partition(|key| self.is_revoked(key));
+        result.valid_signers = valid;
+        result.revoked_signers = revoked;
+        result.threshold_met =
+            result.content_hash_valid && result.valid_signers.len() >= policy.threshold();
+        result
+    }
+
+    /// [`crate::check_capsule`], additionally rejecting capsules signed by
+    /// a revoked key with [`CapsuleError::KeyRevoked`].
+    pub fn check_capsule(&self, capsule: &Capsule) -> Result<(), CapsuleError> {
+        crate::check_capsule(capsule)?;
+        let public_key = &capsule.signature_block.public_key;
+        if self.is_revoked(public_key) {
+            return Err(CapsuleError::KeyRevoked(key_id_for_bytes(public_key)?));
+        }
+        Ok(())
+    }
+
+    /// [`crate::check_attestation`], additionally rejecting attestations by
+    /// a revoked key.
+    pub fn check_attestation(&self, attestation: &Attestation) -> Result<(), CapsuleError> {
+        check_attestation(attestation)?;
+        if self.is_revoked(&attestation.signer) {
+            return Err(CapsuleError::KeyRevoked(key_id_for_bytes(
+                &attestation.signer,
+            )?));
+        }
+        Ok(())
+    }
+}
+
+fn key_id_for_bytes(public_key: &[u8; 32]) -> Result<String, CapsuleError> {
+    VerifyingKey::from_bytes(public_key)
+        .map(|k| key_id(&k))
+        .map_err(|e| CapsuleError::InvalidKey(e.to_string()))
+}
+
+/// Write `contents` to `path` through a temporary file in the same
+/// directory, so a crash never leaves a truncated key behind. On Unix the
+/// file is created with mode 0600.
+/// Check that a revocation capsule verifies and names the key it carries,
+/// returning that key's id. Says nothing about who may revoke it.
+fn check_revocation(revocation: &Capsule) -> Result<String, CapsuleError> {
+    let proof = verify_capsule(revocation);
+    if !proof.crypto_valid || !proof.content_hash_valid {
+        return Err(CapsuleError::SignatureInvalid {
+            key_id: key_id_for_bytes(&revocation.signature_block.public_key)?,
+            content_hash: revocation.signature_block.content_hash.clone(),
+        });
+    }
+
+    let statement: RevocationStatement = cbor::decode(&revocation.content)?;
+    let revoked_id = key_id_for_bytes(&statement.public_key)?;
+    if revoked_id != statement.key_id {
+        return Err(CapsuleError::InvalidKey(format!(
+            "revocation names {} but carries the key {}",
+            statement.key_id, revoked_id
+        )));
+    }
+    Ok(revoked_id)
+}
+
+fn write_private_file(path: &Path, contents: &[u8]) -> Result<(), CapsuleError> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // Keep the temporary name's extension distinct so `open` never loads it
+    let tmp_path = path.with_file_name(format!(".{}.tmp-{}", file_name, std::process::id()));
+
+    let write = || -> std::io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    };
+
+    write().map_err(|e| {
+        fs::remove_file(&tmp_path).ok();
+        CapsuleError::io(path, e)
+    })
+}
+
+fn list_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, CapsuleError> {
+    let entries = fs::read_dir(dir).map_err(|e| CapsuleError::io(dir, e))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| CapsuleError::io(dir, e))?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == extension) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_root_capsule, generate_keypair};
+
+    fn temp_keyring_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("capsule_keyring_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[test]
+    fn test_key_id_is_stable() {
+        let key = generate_keypair();
+        let id = key_id(&key.verifying_key());
+        assert!(id.starts_with("KeyV1:"));
+        assert_eq!(
+            id,
+            key_id(&SigningKey::from_bytes(&key.to_bytes()).verifying_key())
+        );
+        assert_ne!(id, key_id(&generate_keypair().verifying_key()));
+    }
+
+    #[test]
+    fn test_pem_roundtrip_plain_and_encrypted() {
+        let key = generate_keypair();
+
+        let plain = encode_key_pem(&key, None).unwrap();
+        assert!(plain.starts_with(PEM_BEGIN));
+        assert_eq!(
+            decode_key_pem(&plain, None).unwrap().to_bytes(),
+            key.to_bytes()
+        );
+
+        let sealed = encode_key_pem(&key, Some("hunter2")).unwrap();
+        assert!(!sealed.contains(&hex::encode(key.to_bytes())));
+        assert_eq!(
+            decode_key_pem(&sealed, Some("hunter2")).unwrap().to_bytes(),
+            key.to_bytes()
+        );
+        assert!(decode_key_pem(&sealed, Some("wrong")).is_err());
+        assert!(decode_key_pem(&sealed, None).is_err());
+    }
+
+    #[test]
+    fn test_pem_rejects_out_of_range_rounds() {
+        let key = generate_keypair();
+        let sealed = encode_key_pem(&key, Some("pw")).unwrap();
+        let header = format!("Rounds: {}", PBKDF2_ROUNDS);
+        assert!(sealed.contains(&header));
+
+        for rounds in [0, 1, u32::MAX] {
+            let pem = sealed.replace(&header, &format!("Rounds: {}", rounds));
+            assert!(matches!(
+                decode_key_pem(&pem, Some("pw")),
+                Err(CapsuleError::InvalidKey(msg)) if msg.contains("Rounds")
+            ));
+        }
+    }
+
+    #[test]
+    fn test_pem_rejects_mismatched_key_id() {
+        let key = generate_keypair();
+        let other = generate_keypair();
+        let pem = encode_key_pem(&key, None).unwrap().replace(
+            &key_id(&key.verifying_key()),
+            &key_id(&other.verifying_key()),
+        );
+        assert!(decode_key_pem(&pem, None).is_err());
+    }
+
+    #[test]
+    fn test_rotation_records() {
+        let mut keyring = Keyring::in_memory();
+        let old_id = keyring.add_key(generate_keypair(), None).unwrap();
+        let new_key = generate_keypair();
+        let new_id = key_id(&new_key.verifying_key());
+
+        let mut rotation = keyring.rotate(&old_id, new_key, None).unwrap();
+        assert!(verify_rotation(&rotation));
+        assert_eq!(keyring.current_key_id(&old_id), new_id);
+
+        rotation.timestamp += 1;
+        assert!(!verify_rotation(&rotation));
+        assert!(keyring.add_rotation(rotation).is_err());
+    }
+
+    #[test]
+    fn test_fixed_clock_gives_reproducible_records() {
+        let clock = crate::FixedClock(1_700_000_000);
+        let old_key = SigningKey::from_bytes(&[1; 32]);
+        let new_key = SigningKey::from_bytes(&[2; 32]).verifying_key();
+
+        let rotation = create_rotation_with_clock(&old_key, &new_key, &clock).unwrap();
+        assert_eq!(rotation.timestamp, 1_700_000_000);
+        assert_eq!(
+            rotation,
+            create_rotation_with_clock(&old_key, &new_key, &clock).unwrap()
+        );
+
+        let revocation = |clock: &dyn Clock| {
+            create_revocation_capsule_with_clock(&old_key, &new_key, "retired".to_string(), clock)
+                .unwrap()
+        };
+        assert_eq!(revocation(&clock).metadata.timestamp, 1_700_000_000);
+        assert_eq!(
+            crate::serialize_capsule(&revocation(&clock)).unwrap(),
+            crate::serialize_capsule(&revocation(&clock)).unwrap()
+        );
+
+        let mut keyring = Keyring::in_memory();
+        let old_id = keyring.add_key(old_key.clone(), None).unwrap();
+        let recorded = keyring
+            .rotate_with_clock(&old_id, SigningKey::from_bytes(&[2; 32]), None, &clock)
+            .unwrap();
+        assert_eq!(recorded, rotation);
+    }
+
+    #[test]
+    fn test_revocation_invalidates_signed_capsules() {
+        let leaked = generate_keypair();
+        let capsule = create_root_capsule(&leaked, "Signed before leak".to_string()).unwrap();
+
+        let mut keyring = Keyring::in_memory();
+        assert!(keyring.verify_capsule(&capsule).crypto_valid);
+
+        let revocation =
+            create_revocation_capsule(&leaked, &leaked.verifying_key(), "leaked".to_string())
+                .unwrap();
+        keyring.add_revocation(revocation).unwrap();
+
+        let proof = keyring.verify_capsule(&capsule);
+        assert!(proof.key_revoked);
+        assert!(!proof.crypto_valid);
+        assert!(proof.content_hash_valid);
+        assert!(matches!(
+            keyring.check_capsule(&capsule),
+            Err(CapsuleError::KeyRevoked(id)) if id == key_id(&leaked.verifying_key())
+        ));
+        let attestation = crate::create_attestation(
+            &leaked,
+            vec![capsule.signature_block.content_hash.clone()],
+            "reviewed".to_string(),
+        )
+        .unwrap();
+        assert!(crate::verify_attestation(&attestation));
+        assert!(matches!(
+            keyring.check_attestation(&attestation),
+            Err(CapsuleError::KeyRevoked(_))
+        ));
+        assert!(matches!(
+            keyring.verify_chain(std::slice::from_ref(&capsule)),
+            Err(ChainBreak::KeyRevoked { index: 0, key_id, .. })
+                if key_id == crate::key_id(&leaked.verifying_key())
+        ));
+
+        // The keyring-free checks ignore revocations
+        assert!(verify_capsule(&capsule).crypto_valid);
+        assert!(!verify_capsule(&capsule).key_revoked);
+        assert_eq!(verify_chain(std::slice::from_ref(&capsule)), Ok(()));
+    }
+
+    #[test]
+    fn test_revocation_requires_authorized_signer() {
+        let victim = generate_keypair();
+        let stranger = generate_keypair();
+        let mut keyring = Keyring::in_memory();
+
+        let forged =
+            create_revocation_capsule(&stranger, &victim.verifying_key(), "grief".to_string())
+                .unwrap();
+        assert!(keyring.add_revocation(forged).is_err());
+
+        // A successor the victim rotated to may revoke it
+        let successor = generate_keypair();
+        keyring
+            .add_rotation(create_rotation(&victim, &successor.verifying_key()).unwrap())
+            .unwrap();
+        let revocation =
+            create_revocation_capsule(&successor, &victim.verifying_key(), "rotated".to_string())
+                .unwrap();
+        keyring.add_revocation(revocation).unwrap();
+        assert!(keyring.is_revoked(&victim.verifying_key().to_bytes()));
+    }
+
+    #[test]
+    fn test_keyring_persists_keys_rotations_and_revocations() {
+        let dir = temp_keyring_dir("persist");
+        let passphrase = Some("correct horse");
+
+        let (old_id, new_id) = {
+            let mut keyring = Keyring::open(&dir, passphrase).unwrap();
+            let old_id = keyring.add_key(generate_keypair(), passphrase).unwrap();
+            let new_key = generate_keypair();
+            let new_id = key_id(&new_key.verifying_key());
+            keyring.rotate(&old_id, new_key, passphrase).unwrap();
+
+            let old_key = keyring.get_key(&old_id).unwrap();
+            let revocation =
+                create_revocation_capsule(old_key, &old_key.verifying_key(), "retired".to_string())
+                    .unwrap();
+            keyring.add_revocation(revocation).unwrap();
+            (old_id, new_id)
+        };
+
+        let reopened = Keyring::open(&dir, passphrase).unwrap();
+        let mut expected = vec![old_id.clone(), new_id.clone()];
+        expected.sort();
+        assert_eq!(reopened.key_ids(), expected);
+        assert_eq!(reopened.current_key_id(&old_id), new_id);
+        let old_public = reopened
+            .get_key(&old_id)
+            .unwrap()
+            .verifying_key()
+            .to_bytes();
+        assert!(reopened.is_revoked(&old_public));
+
+        assert!(
+            Keyring::open(&dir, None).is_err(),
+            "encrypted keys need the passphrase"
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_revoking_a_revoker_survives_reopen() {
+        let dir = temp_keyring_dir("revoke_revoker");
+        let mut keyring = Keyring::open(&dir, None).unwrap();
+        let name = |key: &SigningKey| key_id_hex(&key_id(&key.verifying_key())).to_string();
+        let target = generate_keypair();
+        // Make the first revoker's record load before its target's
+        let a = std::iter::repeat_with(generate_keypair)
+            .find(|key| name(key) < name(&target))
+            .unwrap();
+        let b = generate_keypair();
+        keyring.add_key(a.clone(), None).unwrap();
+        keyring.add_key(b.clone(), None).unwrap();
+
+        let revoke = |signer: &SigningKey, revoked: &SigningKey| {
+            create_revocation_capsule(signer, &revoked.verifying_key(), "compromised".to_string())
+                .unwrap()
+        };
+        keyring.add_revocation(revoke(&a, &target)).unwrap();
+        keyring.add_revocation(revoke(&b, &a)).unwrap();
+        drop(keyring);
+
+        let reopened = Keyring::open(&dir, None).unwrap();
+        assert!(reopened.is_revoked(&target.verifying_key().to_bytes()));
+        assert!(reopened.is_revoked(&a.verifying_key().to_bytes()));
+        assert!(!reopened.is_revoked(&b.verifying_key().to_bytes()));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_key_files_are_private_and_complete() {
+        let dir = temp_keyring_dir("private");
+        let mut keyring = Keyring::open(&dir, None).unwrap();
+        let id = keyring.add_key(generate_keypair(), None).unwrap();
+
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| !["rotations", "revoked"].contains(&name.as_str()))
+            .collect();
+        assert_eq!(names, vec![format!("{}.key", key_id_hex(&id))]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = dir.join(&names[0]);
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir).ok();
+    }
+}
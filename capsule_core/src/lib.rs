@@ -10,7 +10,43 @@
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+pub mod attestation;
+pub mod cbor;
+pub mod chunked;
+pub mod envelope;
+pub mod hash;
+pub mod keyring;
+pub mod multisig;
+pub mod objects;
+pub mod store;
+
+pub use attestation::{
+    check_attestation, check_detached, create_attestation, create_attestation_with_clock,
+    sign_detached, verify_attestation, verify_detached, Attestation, DetachedSignature,
+};
+pub use cbor::{check_canonical, is_canonical};
+pub use chunked::{
+    verify_chunk, ChunkManifest, ChunkedReader, ChunkedWriter, MerkleProof, DEFAULT_CHUNK_SIZE,
+};
+pub use envelope::{
+    Envelope, Migration, MigrationRegistry, CURRENT_FORMAT_VERSION, ENVELOPE_MAGIC,
+};
+pub use hash::{
+    compute_content_hash_with, Blake3Hasher, Cid, ContentHasher, HashAlgorithm, Multihash,
+    Sha256Hasher,
+};
+pub use keyring::{
+    create_revocation_capsule, create_revocation_capsule_with_clock, create_rotation,
+    create_rotation_with_clock, decode_key_pem, encode_key_pem, key_id, verify_rotation,
+    KeyRotation, Keyring, RevocationStatement,
+};
+pub use multisig::{add_signature, verify_multisig, CoSignature, MultiSigResult, TrustPolicy};
+pub use objects::{Closure, ObjectDb};
+pub use store::{get_capsule, put_blob, put_capsule, CapsuleStore, FsStore, MemoryStore};
 
 // ============================================================================
 // Core Data Structures
@@ -39,6 +75,10 @@ pub struct SignatureBlock {
     #[serde(with = "serde_bytes")]
     pub signature: [u8; 64],
     pub content_hash: String,
+    /// Additional signatures over `content_hash`, sorted by public key.
+    /// Empty (and omitted on the wire) for single-signer capsules.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cosignatures: Vec<CoSignature>,
 }
 
 #[derive(Debug, Clone)]
@@ -46,18 +86,103 @@ pub struct ProofResult {
     pub crypto_valid: bool,
     pub content_hash_valid: bool,
     pub root_lineage: bool,
+    /// Set by [`Keyring::verify_capsule`] when the signing key has been revoked.
+    pub key_revoked: bool,
+}
+
+// ============================================================================
+// Error Types
+// ============================================================================
+
+#[derive(Error, Debug)]
+pub enum CapsuleError {
+    #[error("I/O error on {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("CBOR encoding failed: {0}")]
+    Encode(String),
+
+    #[error("CBOR decoding failed: {0}")]
+    Decode(String),
+
+    #[error("Non-canonical CBOR: {0}")]
+    NonCanonical(String),
+
+    #[error("Invalid signature by {key_id} over {content_hash}")]
+    SignatureInvalid {
+        key_id: String,
+        content_hash: String,
+    },
+
+    #[error("Hash mismatch: expected {expected}, got {actual}")]
+    HashMismatch { expected: String, actual: String },
+
+    #[error("Lineage broken: {0}")]
+    LineageBroken(#[from] ChainBreak),
+
+    #[error("Not a lineage id: {0}")]
+    InvalidLineageId(String),
+
+    #[error("Unknown key: {0}")]
+    UnknownKey(String),
+
+    #[error("Key revoked: {0}")]
+    KeyRevoked(String),
+
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid hash: {0}")]
+    InvalidHash(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Cryptographic operation failed: {0}")]
+    Crypto(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Unsupported capsule format version {0}")]
+    UnsupportedFormat(u16),
+
+    #[error("No migration registered from capsule format version {0}")]
+    NoMigration(u16),
+
+    #[error("Unknown hash algorithm 0x{0:x}")]
+    UnknownHashAlgorithm(u64),
+
+    #[error("Invalid CID: {0}")]
+    InvalidCid(String),
+
+    #[error("Ambiguous reference {reference}: matches {}", candidates.join(", "))]
+    AmbiguousRef {
+        reference: String,
+        candidates: Vec<String>,
+    },
+}
+
+impl CapsuleError {
+    pub(crate) fn io(path: impl AsRef<std::path::Path>, source: std::io::Error) -> Self {
+        CapsuleError::Io {
+            path: path.as_ref().display().to_string(),
+            source,
+        }
+    }
 }
 
 // ============================================================================
 // Canonical CBOR Serialization
 // ============================================================================
 
-/// Produces deterministic CBOR encoding per RFC 8949
-pub fn canonical_cbor<T: Serialize>(data: &T) -> Result<Vec<u8>, String> {
-    let mut buffer = Vec::new();
-    ciborium::into_writer(data, &mut buffer)
-        .map_err(|e| format!("CBOR serialization failed: {}", e))?;
-    Ok(buffer)
+/// Produces deterministic CBOR encoding per RFC 8949 §4.2.1
+pub fn canonical_cbor<T: Serialize>(data: &T) -> Result<Vec<u8>, CapsuleError> {
+    cbor::encode(data)
 }
 
 // ============================================================================
@@ -95,9 +220,31 @@ pub trait CanonicalSerialize {
 }
 
 /// Trait for computing content-addressable hash string for an object.
-pub trait ContentAddressable {
-    /// Return a prefixed content hash string (e.g. "GlyphV1:abcdef...").
-    fn content_hash(&self) -> String;
+pub trait ContentAddressable: CanonicalSerialize {
+    /// Type prefix mixed into the hash (e.g. "GlyphV1").
+    const HASH_PREFIX: &'static str;
+
+    /// Return a prefixed SHA-256 content hash string (e.g. "GlyphV1:abcdef...").
+    fn content_hash(&self) -> String {
+        self.content_hash_with::<Sha256Hasher>()
+    }
+
+    /// Content hash under the hash algorithm `H`.
+    fn content_hash_with<H: ContentHasher>(&self) -> String {
+        compute_content_hash_with::<H>(Self::HASH_PREFIX, &self.canonical_serialize())
+    }
+
+    /// Multihash of the content hash: the same digest, tagged with `H`.
+    fn multihash<H: ContentHasher>(&self) -> Multihash {
+        let digest =
+            H::ALGORITHM.digest(&[Self::HASH_PREFIX.as_bytes(), &self.canonical_serialize()]);
+        Multihash::new(H::ALGORITHM, digest.to_vec())
+    }
+
+    /// CIDv1 (dag-cbor) naming this object in the CAS.
+    fn cid<H: ContentHasher>(&self) -> Cid {
+        Cid::new_v1(hash::CODEC_DAG_CBOR, self.multihash::<H>())
+    }
 }
 
 // ============================================================================
@@ -145,31 +292,31 @@ pub struct ExpressionRef {
 
 impl CanonicalSerialize for Glyph {
     fn canonical_serialize(&self) -> Vec<u8> {
-        serde_cbor::to_vec(self).expect("Glyph serialization should not fail")
+        canonical_cbor(self).expect("Glyph serialization should not fail")
     }
 }
 
 impl CanonicalSerialize for Expression {
     fn canonical_serialize(&self) -> Vec<u8> {
-        serde_cbor::to_vec(self).expect("Expression serialization should not fail")
+        canonical_cbor(self).expect("Expression serialization should not fail")
     }
 }
 
 impl CanonicalSerialize for GraphNode {
     fn canonical_serialize(&self) -> Vec<u8> {
-        serde_cbor::to_vec(self).expect("GraphNode serialization should not fail")
+        canonical_cbor(self).expect("GraphNode serialization should not fail")
     }
 }
 
 impl CanonicalSerialize for GlyphRef {
     fn canonical_serialize(&self) -> Vec<u8> {
-        serde_cbor::to_vec(self).expect("GlyphRef serialization should not fail")
+        canonical_cbor(self).expect("GlyphRef serialization should not fail")
     }
 }
 
 impl CanonicalSerialize for ExpressionRef {
     fn canonical_serialize(&self) -> Vec<u8> {
-        serde_cbor::to_vec(self).expect("ExpressionRef serialization should not fail")
+        canonical_cbor(self).expect("ExpressionRef serialization should not fail")
     }
 }
 
@@ -178,38 +325,23 @@ impl CanonicalSerialize for ExpressionRef {
 // ============================================================================
 
 impl ContentAddressable for Glyph {
-    fn content_hash(&self) -> String {
-        let ser = self.canonical_serialize();
-        compute_content_hash_with_prefix("GlyphV1", &ser)
-    }
+    const HASH_PREFIX: &'static str = "GlyphV1";
 }
 
 impl ContentAddressable for Expression {
-    fn content_hash(&self) -> String {
-        let ser = self.canonical_serialize();
-        compute_content_hash_with_prefix("ExprV1", &ser)
-    }
+    const HASH_PREFIX: &'static str = "ExprV1";
 }
 
 impl ContentAddressable for GraphNode {
-    fn content_hash(&self) -> String {
-        let ser = self.canonical_serialize();
-        compute_content_hash_with_prefix("NodeV1", &ser)
-    }
+    const HASH_PREFIX: &'static str = "NodeV1";
 }
 
 impl ContentAddressable for GlyphRef {
-    fn content_hash(&self) -> String {
-        let ser = self.canonical_serialize();
-        compute_content_hash_with_prefix("GlyphV1", &ser)
-    }
+    const HASH_PREFIX: &'static str = "GlyphV1";
 }
 
 impl ContentAddressable for ExpressionRef {
-    fn content_hash(&self) -> String {
-        let ser = self.canonical_serialize();
-        compute_content_hash_with_prefix("ExprV1", &ser)
-    }
+    const HASH_PREFIX: &'static str = "ExprV1";
 }
 
 // ============================================================================
@@ -222,39 +354,92 @@ pub fn generate_keypair() -> SigningKey {
 }
 
 // ============================================================================
-// Root Capsule Creation (⊙₀)
+// Clocks
 // ============================================================================
 
-pub fn create_root_capsule(keypair: &SigningKey, claim: String) -> Result<Capsule, String> {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+/// Source of capsule timestamps (seconds since the Unix epoch).
+pub trait Clock {
+    fn now(&self) -> u64;
+}
 
-    let metadata = CapsuleMetadata {
-        id: "⊙₀".to_string(),
-        version: "1.0.0".to_string(),
-        timestamp,
-        parent_hash: None, // Root has no parent
-        claim,
-    };
+/// The wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
 
-    let content = b"Genesis Capsule - Root of Trust".to_vec();
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+}
 
-    // Serialize capsule data for signing (without signature block)
-    #[derive(Serialize)]
-    struct UnsignedCapsule<'a> {
-        metadata: &'a CapsuleMetadata,
-        content: &'a [u8],
+/// A clock frozen at one instant, for reproducible builds and tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
     }
+}
 
-    let unsigned = UnsignedCapsule {
-        metadata: &metadata,
-        content: &content,
-    };
+// ============================================================================
+// Capsule Sealing
+// ============================================================================
+
+/// Identifier of the root capsule.
+pub const ROOT_CAPSULE_ID: &str = "⊙₀";
 
+/// The signed portion of a capsule (everything except the signature block).
+#[derive(Serialize)]
+struct UnsignedCapsule<'a> {
+    metadata: &'a CapsuleMetadata,
+    content: &'a [u8],
+}
+
+/// Recompute the content hash that a capsule's signature block commits to.
+fn unsigned_capsule_hash(
+    metadata: &CapsuleMetadata,
+    content: &[u8],
+) -> Result<String, CapsuleError> {
+    let unsigned = UnsignedCapsule { metadata, content };
     let cbor_data = canonical_cbor(&unsigned)?;
-    let content_hash = compute_content_hash(&cbor_data);
+    Ok(compute_content_hash(&cbor_data))
+}
+
+/// The content hash as capsules sealed before deterministic CBOR computed
+/// it: the same unsigned capsule, encoded with map keys in field order.
+fn legacy_capsule_hash(metadata: &CapsuleMetadata, content: &[u8]) -> Result<String, CapsuleError> {
+    let unsigned = UnsignedCapsule { metadata, content };
+    let mut cbor_data = Vec::new();
+    ciborium::into_writer(&unsigned, &mut cbor_data)
+        .map_err(|e| CapsuleError::Encode(e.to_string()))?;
+    Ok(compute_content_hash(&cbor_data))
+}
+
+/// Recompute the hash a capsule's signature block should carry. Capsules
+/// sealed under the legacy encoding keep their legacy hash, so it is
+/// returned when it is the one the block commits to.
+pub(crate) fn recompute_content_hash(capsule: &Capsule) -> Result<String, CapsuleError> {
+    let computed = unsigned_capsule_hash(&capsule.metadata, &capsule.content)?;
+    if computed != capsule.signature_block.content_hash {
+        let legacy = legacy_capsule_hash(&capsule.metadata, &capsule.content)?;
+        if legacy == capsule.signature_block.content_hash {
+            return Ok(legacy);
+        }
+    }
+    Ok(computed)
+}
+
+/// Hash and sign metadata + content, producing a complete capsule.
+pub(crate) fn seal_capsule(
+    keypair: &SigningKey,
+    metadata: CapsuleMetadata,
+    content: Vec<u8>,
+) -> Result<Capsule, CapsuleError> {
+    let content_hash = unsigned_capsule_hash(&metadata, &content)?;
 
     // Sign the content hash
     let signature = keypair.sign(content_hash.as_bytes());
@@ -263,6 +448,7 @@ pub fn create_root_capsule(keypair: &SigningKey, claim: String) -> Result<Capsul
         public_key: keypair.verifying_key().to_bytes(),
         signature: signature.to_bytes(),
         content_hash,
+        cosignatures: Vec::new(),
     };
 
     Ok(Capsule {
@@ -272,35 +458,114 @@ pub fn create_root_capsule(keypair: &SigningKey, claim: String) -> Result<Capsul
     })
 }
 
+/// Lineage id for a capsule at the given depth below ⊙₀ (e.g. 2 → "⊙₂").
+pub fn lineage_id(depth: u32) -> String {
+    const SUBSCRIPTS: [char; 10] = ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'];
+    let digits: String = depth
+        .to_string()
+        .chars()
+        .map(|c| SUBSCRIPTS[c.to_digit(10).unwrap() as usize])
+        .collect();
+    format!("⊙{}", digits)
+}
+
+/// Parse the depth out of a lineage id produced by [`lineage_id`].
+pub fn lineage_depth(id: &str) -> Option<u32> {
+    let digits = id.strip_prefix('⊙')?;
+    if digits.is_empty() {
+        return None;
+    }
+    digits.chars().try_fold(0u32, |acc, c| {
+        let d = (c as u32).checked_sub('₀' as u32).filter(|d| *d < 10)?;
+        acc.checked_mul(10)?.checked_add(d)
+    })
+}
+
+// ============================================================================
+// Root Capsule Creation (⊙₀)
+// ============================================================================
+
+pub fn create_root_capsule(keypair: &SigningKey, claim: String) -> Result<Capsule, CapsuleError> {
+    create_root_capsule_with_clock(keypair, claim, &SystemClock)
+}
+
+/// [`create_root_capsule`] with the timestamp taken from `clock`. With a
+/// [`FixedClock`] the same key and claim always yield identical bytes.
+pub fn create_root_capsule_with_clock(
+    keypair: &SigningKey,
+    claim: String,
+    clock: &dyn Clock,
+) -> Result<Capsule, CapsuleError> {
+    let metadata = CapsuleMetadata {
+        id: ROOT_CAPSULE_ID.to_string(),
+        version: "1.0.0".to_string(),
+        timestamp: clock.now(),
+        parent_hash: None, // Root has no parent
+        claim,
+    };
+
+    let content = b"Genesis Capsule - Root of Trust".to_vec();
+
+    seal_capsule(keypair, metadata, content)
+}
+
+// ============================================================================
+// Child Capsule Creation (⊙ₙ)
+// ============================================================================
+
+/// Create a capsule one level below `parent`, linked by the parent's content hash.
+pub fn create_child_capsule(
+    parent: &Capsule,
+    keypair: &SigningKey,
+    claim: String,
+    content: Vec<u8>,
+) -> Result<Capsule, CapsuleError> {
+    create_child_capsule_with_clock(parent, keypair, claim, content, &SystemClock)
+}
+
+/// [`create_child_capsule`] with the timestamp taken from `clock`.
+pub fn create_child_capsule_with_clock(
+    parent: &Capsule,
+    keypair: &SigningKey,
+    claim: String,
+    content: Vec<u8>,
+    clock: &dyn Clock,
+) -> Result<Capsule, CapsuleError> {
+    let depth = lineage_depth(&parent.metadata.id)
+        .ok_or_else(|| CapsuleError::InvalidLineageId(parent.metadata.id.clone()))?;
+
+    let metadata = CapsuleMetadata {
+        id: lineage_id(depth + 1),
+        version: parent.metadata.version.clone(),
+        timestamp: clock.now(),
+        parent_hash: Some(parent.signature_block.content_hash.clone()),
+        claim,
+    };
+
+    seal_capsule(keypair, metadata, content)
+}
+
 // ============================================================================
 // Capsule Verification
 // ============================================================================
 
+/// Check a capsule's content hash, signature and root lineage. Revocations
+/// are not consulted, so `key_revoked` is always false here; use
+/// [`Keyring::verify_capsule`] to fail capsules signed by revoked keys.
 pub fn verify_capsule(capsule: &Capsule) -> ProofResult {
     let mut result = ProofResult {
         crypto_valid: false,
         content_hash_valid: false,
         root_lineage: false,
+        key_revoked: false,
     };
 
     // Step 1: Recompute content hash
-    #[derive(Serialize)]
-    struct UnsignedCapsule<'a> {
-        metadata: &'a CapsuleMetadata,
-        content: &'a [u8],
-    }
-
-    let unsigned = UnsignedCapsule {
-        metadata: &capsule.metadata,
-        content: &capsule.content,
-    };
-
-    let cbor_data = match canonical_cbor(&unsigned) {
-        Ok(data) => data,
+    let computed_hash = match recompute_content_hash(capsule) {
+        Ok(hash) => hash,
         Err(_) => return result,
     };
 
-    let computed_hash = compute_content_hash(&cbor_data);
     result.content_hash_valid = computed_hash == capsule.signature_block.content_hash;
 
     // Step 2: Verify cryptographic signature
@@ -319,31 +584,281 @@ pub fn verify_capsule(capsule: &Capsule) -> ProofResult {
         .is_ok();
 
     // Step 3: Verify root lineage (no parent hash for root capsule)
-    result.root_lineage = capsule.metadata.id == "⊙₀" && capsule.metadata.parent_hash.is_none();
+    result.root_lineage =
+        capsule.metadata.id == ROOT_CAPSULE_ID && capsule.metadata.parent_hash.is_none();
 
     result
 }
 
+/// Like [`verify_capsule`], but reports the first failure as an error
+/// carrying the offending hashes and key id. Lineage is not checked.
+pub fn check_capsule(capsule: &Capsule) -> Result<(), CapsuleError> {
+    let block = &capsule.signature_block;
+
+    let computed_hash = recompute_content_hash(capsule)?;
+    if computed_hash != block.content_hash {
+        return Err(CapsuleError::HashMismatch {
+            expected: block.content_hash.clone(),
+            actual: computed_hash,
+        });
+    }
+
+    let public_key = VerifyingKey::from_bytes(&block.public_key)
+        .map_err(|e| CapsuleError::InvalidKey(e.to_string()))?;
+    let signature = Signature::from_bytes(&block.signature);
+    public_key
+        .verify(block.content_hash.as_bytes(), &signature)
+        .map_err(|_| CapsuleError::SignatureInvalid {
+            key_id: key_id(&public_key),
+            content_hash: block.content_hash.clone(),
+        })
+}
+
+// ============================================================================
+// Chain Verification
+// ============================================================================
+
+/// The first broken link found by [`verify_chain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainBreak {
+    /// The chain contains no capsules.
+    Empty,
+    /// The capsule's signature does not verify against its content hash.
+    BadSignature { index: usize, id: String },
+    /// The recomputed content hash differs from the signed one.
+    HashMismatch {
+        index: usize,
+        id: String,
+        expected: String,
+        actual: String,
+    },
+    /// The capsule names a parent that is not part of the chain.
+    MissingParent {
+        index: usize,
+        id: String,
+        parent_hash: String,
+    },
+    /// The capsule has no parent but is not ⊙₀.
+    NotRooted { index: usize, id: String },
+    /// A second parentless capsule; the chain has exactly one genesis.
+    MultipleGenesis {
+        index: usize,
+        id: String,
+        first: usize,
+    },
+    /// The capsule shares its parent with an earlier capsule, forking the
+    /// chain.
+    Fork {
+        index: usize,
+        id: String,
+        parent_hash: String,
+        sibling: usize,
+    },
+    /// The capsule's lineage id is not one level below its parent's.
+    DepthMismatch {
+        index: usize,
+        id: String,
+        parent_id: String,
+    },
+    /// The capsule was signed by a key the keyring has revoked.
+    KeyRevoked {
+        index: usize,
+        id: String,
+        key_id: String,
+    },
+}
+
+impl std::fmt::Display for ChainBreak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainBreak::Empty => write!(f, "chain is empty"),
+            ChainBreak::BadSignature { index, id } => {
+                write!(f, "capsule #{} ({}): bad signature", index, id)
+            }
+            ChainBreak::HashMismatch {
+                index,
+                id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "capsule #{} ({}): hash mismatch, signed {} but computed {}",
+                index, id, expected, actual
+            ),
+            ChainBreak::MissingParent {
+                index,
+                id,
+                parent_hash,
+            } => write!(
+                f,
+                "capsule #{} ({}): missing parent {}",
+                index, id, parent_hash
+            ),
+            ChainBreak::NotRooted { index, id } => {
+                write!(
+                    f,
+                    "capsule #{} ({}): has no parent but is not {}",
+                    index, id, ROOT_CAPSULE_ID
+                )
+            }
+            ChainBreak::MultipleGenesis { index, id, first } => write!(
+                f,
+                "capsule #{} ({}): second genesis, capsule #{} is already the root",
+                index, id, first
+            ),
+            ChainBreak::Fork {
+                index,
+                id,
+                parent_hash,
+                sibling,
+            } => write!(
+                f,
+                "capsule #{} ({}): parent {} already has child #{}",
+                index, id, parent_hash, sibling
+            ),
+            ChainBreak::DepthMismatch {
+                index,
+                id,
+                parent_id,
+            } => write!(
+                f,
+                "capsule #{} ({}): lineage id does not follow parent {}",
+                index, id, parent_id
+            ),
+            ChainBreak::KeyRevoked { index, id, key_id } => {
+                write!(
+                    f,
+                    "capsule #{} ({}): signed by revoked key {}",
+                    index, id, key_id
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChainBreak {}
+
+/// Verify every capsule in `chain` and check that each `parent_hash` resolves,
+/// link by link, back to ⊙₀. Capsules may appear in any order, but must form
+/// a single line: one genesis, and no parent with two children.
+///
+/// Revocations are not consulted; use [`Keyring::verify_chain`] to also
+/// reject capsules signed by revoked keys.
+pub fn verify_chain(chain: &[Capsule]) -> Result<(), ChainBreak> {
+    if chain.is_empty() {
+        return Err(ChainBreak::Empty);
+    }
+
+    // Step 1: every capsule must be individually sound
+    for (index, capsule) in chain.iter().enumerate() {
+        let id = capsule.metadata.id.clone();
+        let computed = recompute_content_hash(capsule).map_err(|_| ChainBreak::HashMismatch {
+            index,
+            id: id.clone(),
+            expected: capsule.signature_block.content_hash.clone(),
+            actual: String::new(),
+        })?;
+        if computed != capsule.signature_block.content_hash {
+            return Err(ChainBreak::HashMismatch {
+                index,
+                id,
+                expected: capsule.signature_block.content_hash.clone(),
+                actual: computed,
+            });
+        }
+        if !verify_capsule(capsule).crypto_valid {
+            return Err(ChainBreak::BadSignature { index, id });
+        }
+    }
+
+    // Step 2: every parent link must resolve inside the chain. Since each
+    // parent_hash is covered by the child's signature, links cannot form a
+    // cycle, so resolving every link is enough to reach ⊙₀ from any capsule.
+    let by_hash: HashMap<&str, &Capsule> = chain
+        .iter()
+        .map(|c| (c.signature_block.content_hash.as_str(), c))
+        .collect();
+
+    let mut genesis: Option<usize> = None;
+    let mut children: HashMap<&str, usize> = HashMap::new();
+
+    for (index, capsule) in chain.iter().enumerate() {
+        let id = capsule.metadata.id.clone();
+        match &capsule.metadata.parent_hash {
+            None => {
+                if capsule.metadata.id != ROOT_CAPSULE_ID {
+                    return Err(ChainBreak::NotRooted { index, id });
+                }
+                if let Some(first) = genesis {
+                    return Err(ChainBreak::MultipleGenesis { index, id, first });
+                }
+                genesis = Some(index);
+            }
+            Some(parent_hash) => {
+                if let Some(&sibling) = children.get(parent_hash.as_str()) {
+                    return Err(ChainBreak::Fork {
+                        index,
+                        id,
+                        parent_hash: parent_hash.clone(),
+                        sibling,
+                    });
+                }
+                children.insert(parent_hash.as_str(), index);
+                let parent =
+                    by_hash
+                        .get(parent_hash.as_str())
+                        .ok_or_else(|| ChainBreak::MissingParent {
+                            index,
+                            id: id.clone(),
+                            parent_hash: parent_hash.clone(),
+                        })?;
+                let expected_depth = lineage_depth(&parent.metadata.id).map(|d| d + 1);
+                if expected_depth.is_none() || expected_depth != lineage_depth(&id) {
+                    return Err(ChainBreak::DepthMismatch {
+                        index,
+                        id,
+                        parent_id: parent.metadata.id.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 // ============================================================================
 // Persistence
 // ============================================================================
 
-pub fn serialize_capsule(capsule: &Capsule) -> Result<Vec<u8>, String> {
-    canonical_cbor(capsule)
+/// Encode `capsule` in a current-format [`Envelope`].
+pub fn serialize_capsule(capsule: &Capsule) -> Result<Vec<u8>, CapsuleError> {
+    Envelope::seal(capsule)?.to_bytes()
+}
+
+/// Decode bytes from [`serialize_capsule`], upgrading older formats with the
+/// default [`MigrationRegistry`].
+pub fn deserialize_capsule(data: &[u8]) -> Result<Capsule, CapsuleError> {
+    MigrationRegistry::default().load(data)
 }
 
-pub fn persist_capsule(capsule: &Capsule, path: &str) -> Result<(), String> {
+pub fn persist_capsule(capsule: &Capsule, path: &str) -> Result<(), CapsuleError> {
     let data = serialize_capsule(capsule)?;
-    std::fs::write(path, data)
-        .map_err(|e| format!("Failed to write capsule to disk: {}", e))?;
+    std::fs::write(path, data).map_err(|e| CapsuleError::io(path, e))?;
     Ok(())
 }
 
-pub fn load_capsule(path: &str) -> Result<Capsule, String> {
-    let data = std::fs::read(path)
-        .map_err(|e| format!("Failed to read capsule from disk: {}", e))?;
-    ciborium::from_reader(&data[..])
-        .map_err(|e| format!("Failed to deserialize capsule: {}", e))
+pub fn load_capsule(path: &str) -> Result<Capsule, CapsuleError> {
+    load_capsule_with_registry(path, &MigrationRegistry::default())
+}
+
+/// [`load_capsule`] with a caller-supplied set of format migrations.
+pub fn load_capsule_with_registry(
+    path: &str,
+    registry: &MigrationRegistry,
+) -> Result<Capsule, CapsuleError> {
+    let data = std::fs::read(path).map_err(|e| CapsuleError::io(path, e))?;
+    registry.load(&data)
 }
 
 // ============================================================================
@@ -410,6 +925,69 @@ mod tests {
         std::fs::remove_file(path).ok();
     }
 
+    #[test]
+    fn test_load_capsule_rejects_non_canonical_bytes() {
+        let keypair = generate_keypair();
+        let capsule = create_root_capsule(&keypair, "Canonical Test".to_string())
+            .expect("Failed to create capsule");
+
+        let mut envelope = Envelope::seal(&capsule).unwrap();
+        assert!(is_canonical(&envelope.payload));
+
+        // ciborium keeps struct fields in declaration order, which is not
+        // the deterministic key order
+        let mut plain = Vec::new();
+        ciborium::into_writer(&capsule, &mut plain).unwrap();
+        assert!(!is_canonical(&plain));
+
+        // Only headerless legacy bytes are re-encoded on load; a current
+        // format payload must already be canonical
+        envelope.payload = plain;
+        let path = "/tmp/test_capsule_non_canonical.capsule";
+        std::fs::write(path, envelope.to_bytes().unwrap()).unwrap();
+        assert!(matches!(
+            load_capsule(path),
+            Err(CapsuleError::NonCanonical(_))
+        ));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_load_capsule_missing_file_is_io_error() {
+        let path = "/tmp/test_capsule_does_not_exist.capsule";
+        match load_capsule(path) {
+            Err(CapsuleError::Io { path: p, source }) => {
+                assert_eq!(p, path);
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("expected Io error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_check_capsule_reports_specific_errors() {
+        let keypair = generate_keypair();
+        let capsule = create_root_capsule(&keypair, "Check Test".to_string()).unwrap();
+        check_capsule(&capsule).unwrap();
+
+        let mut tampered = capsule.clone();
+        tampered.content = b"Tampered content".to_vec();
+        assert!(matches!(
+            check_capsule(&tampered),
+            Err(CapsuleError::HashMismatch { expected, .. })
+                if expected == capsule.signature_block.content_hash
+        ));
+
+        let mut forged = capsule.clone();
+        forged.signature_block.signature[0] ^= 0x01;
+        match check_capsule(&forged) {
+            Err(CapsuleError::SignatureInvalid { key_id: id, .. }) => {
+                assert_eq!(id, key_id(&keypair.verifying_key()));
+            }
+            other => panic!("expected SignatureInvalid, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_signature_tampering_detection() {
         let keypair = generate_keypair();
@@ -442,6 +1020,209 @@ mod tests {
         println!("✓ CBOR encoding is deterministic");
     }
 
+    #[test]
+    fn test_capsules_sealed_with_legacy_hash_still_verify() {
+        let keypair = generate_keypair();
+        let mut capsule = create_root_capsule(&keypair, "Legacy".to_string()).unwrap();
+
+        // Re-seal the way capsules were sealed before deterministic CBOR
+        let legacy = legacy_capsule_hash(&capsule.metadata, &capsule.content).unwrap();
+        assert_ne!(legacy, capsule.signature_block.content_hash);
+        capsule.signature_block.content_hash = legacy.clone();
+        capsule.signature_block.signature = keypair.sign(legacy.as_bytes()).to_bytes();
+
+        let proof = verify_capsule(&capsule);
+        assert!(proof.crypto_valid && proof.content_hash_valid);
+        check_capsule(&capsule).unwrap();
+        assert_eq!(verify_chain(std::slice::from_ref(&capsule)), Ok(()));
+
+        // The legacy hash still covers the content
+        capsule.content.push(0);
+        assert!(!verify_capsule(&capsule).content_hash_valid);
+    }
+
+    // ========================================================================
+    // Lineage Chain Tests
+    // ========================================================================
+
+    fn build_chain(len: usize) -> (SigningKey, Vec<Capsule>) {
+        let keypair = generate_keypair();
+        let mut chain = vec![create_root_capsule(&keypair, "Root".to_string()).unwrap()];
+        for i in 1..len {
+            let child = create_child_capsule(
+                chain.last().unwrap(),
+                &keypair,
+                format!("Child {}", i),
+                format!("payload {}", i).into_bytes(),
+            )
+            .unwrap();
+            chain.push(child);
+        }
+        (keypair, chain)
+    }
+
+    #[test]
+    fn test_lineage_id_roundtrip() {
+        assert_eq!(lineage_id(0), ROOT_CAPSULE_ID);
+        assert_eq!(lineage_id(12), "⊙₁₂");
+        for depth in [0, 1, 9, 10, 305] {
+            assert_eq!(lineage_depth(&lineage_id(depth)), Some(depth));
+        }
+        assert_eq!(lineage_depth("⊙"), None);
+        assert_eq!(lineage_depth("node-1"), None);
+    }
+
+    #[test]
+    fn test_child_capsule_links_to_parent() {
+        let (_, chain) = build_chain(2);
+        let child = &chain[1];
+        assert_eq!(child.metadata.id, "⊙₁");
+        assert_eq!(
+            child.metadata.parent_hash.as_deref(),
+            Some(chain[0].signature_block.content_hash.as_str())
+        );
+
+        let proof = verify_capsule(child);
+        assert!(proof.crypto_valid);
+        assert!(proof.content_hash_valid);
+        assert!(!proof.root_lineage, "child is not a root capsule");
+    }
+
+    #[test]
+    fn test_verify_chain_accepts_valid_chain_in_any_order() {
+        let (_, mut chain) = build_chain(4);
+        assert_eq!(verify_chain(&chain), Ok(()));
+        chain.reverse();
+        assert_eq!(verify_chain(&chain), Ok(()));
+        assert_eq!(verify_chain(&[]), Err(ChainBreak::Empty));
+    }
+
+    #[test]
+    fn test_verify_chain_reports_missing_parent() {
+        let (_, mut chain) = build_chain(3);
+        let removed = chain.remove(1);
+        match verify_chain(&chain) {
+            Err(ChainBreak::MissingParent {
+                index, parent_hash, ..
+            }) => {
+                assert_eq!(index, 1);
+                assert_eq!(parent_hash, removed.signature_block.content_hash);
+            }
+            other => panic!("expected MissingParent, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_chain_reports_hash_mismatch() {
+        let (_, mut chain) = build_chain(3);
+        chain[2].content = b"tampered".to_vec();
+        assert!(matches!(
+            verify_chain(&chain),
+            Err(ChainBreak::HashMismatch { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_chain_reports_bad_signature() {
+        let (_, mut chain) = build_chain(3);
+        chain[1].signature_block.signature[0] ^= 0xff;
+        assert!(matches!(
+            verify_chain(&chain),
+            Err(ChainBreak::BadSignature { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_chain_rejects_forests_and_forks() {
+        let (keypair, chain) = build_chain(3);
+
+        // A second, independent genesis
+        let (_, other) = build_chain(1);
+        let mut forest = chain.clone();
+        forest.push(other[0].clone());
+        assert!(matches!(
+            verify_chain(&forest),
+            Err(ChainBreak::MultipleGenesis {
+                index: 3,
+                first: 0,
+                ..
+            })
+        ));
+
+        // Two children of ⊙₁
+        let mut forked = chain.clone();
+        forked.push(
+            create_child_capsule(&chain[1], &keypair, "Sibling".to_string(), b"x".to_vec())
+                .unwrap(),
+        );
+        assert!(matches!(
+            verify_chain(&forked),
+            Err(ChainBreak::Fork {
+                index: 3,
+                sibling: 2,
+                ..
+            })
+        ));
+
+        // The same capsule listed twice is a fork of its parent too
+        let mut duplicated = chain.clone();
+        duplicated.push(chain[2].clone());
+        assert!(matches!(
+            verify_chain(&duplicated),
+            Err(ChainBreak::Fork { index: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_chain_requires_root() {
+        let (_, chain) = build_chain(3);
+        assert!(matches!(
+            verify_chain(&chain[1..]),
+            Err(ChainBreak::MissingParent { .. })
+        ));
+    }
+
+    // ========================================================================
+    // Clock Tests
+    // ========================================================================
+
+    #[test]
+    fn test_fixed_clock_gives_reproducible_capsules() {
+        let secret = generate_keypair().to_bytes();
+        let clock = FixedClock(1_700_000_000);
+
+        let build = || {
+            let keypair = SigningKey::from_bytes(&secret);
+            let root =
+                create_root_capsule_with_clock(&keypair, "Release".to_string(), &clock).unwrap();
+            let child = create_child_capsule_with_clock(
+                &root,
+                &keypair,
+                "Blueprint".to_string(),
+                b"blueprint".to_vec(),
+                &clock,
+            )
+            .unwrap();
+            (
+                serialize_capsule(&root).unwrap(),
+                serialize_capsule(&child).unwrap(),
+            )
+        };
+
+        let first = build();
+        assert_eq!(
+            first,
+            build(),
+            "same inputs and clock must give identical bytes"
+        );
+
+        let keypair = SigningKey::from_bytes(&secret);
+        let other = create_root_capsule_with_clock(&keypair, "Release".to_string(), &FixedClock(1))
+            .unwrap();
+        assert_eq!(other.metadata.timestamp, 1);
+        assert_ne!(serialize_capsule(&other).unwrap(), first.0);
+    }
+
     // ========================================================================
     // Work Order 4 Tests
     // ========================================================================
@@ -0,0 +1,327 @@
+// ============================================================================
+// Multi-Signature Capsules (M-of-N)
+// ============================================================================
+//
+// Every signer signs the same `content_hash` as the primary signer in the
+// `SignatureBlock`. Co-signatures are kept sorted by public key so the
+// serialized block does not depend on the order signatures were collected.
+
+use crate::{verify_capsule, Capsule, CapsuleError, SigningKey};
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoSignature {
+    #[serde(with = "serde_bytes")]
+    pub public_key: [u8; 32],
+    #[serde(with = "serde_bytes")]
+    pub signature: [u8; 64],
+}
+
+/// The set of keys allowed to sign and how many of them must agree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustPolicy {
+    keys: BTreeSet<[u8; 32]>,
+    threshold: usize,
+}
+
+impl TrustPolicy {
+    /// Require `threshold` distinct signatures from `keys`.
+    pub fn new<I>(keys: I, threshold: usize) -> Result<Self, CapsuleError>
+    where
+        I: IntoIterator<Item = VerifyingKey>,
+    {
+        let keys: BTreeSet<[u8; 32]> = keys.into_iter().map(|k| k.to_bytes()).collect();
+        if threshold == 0 || threshold > keys.len() {
+            return Err(CapsuleError::InvalidArgument(format!(
+                "threshold {} for {} trusted keys",
+                threshold,
+                keys.len()
+            )));
+        }
+        Ok(Self { keys, threshold })
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn is_trusted(&self, public_key: &[u8; 32]) -> bool {
+        self.keys.contains(public_key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiSigResult {
+    pub content_hash_valid: bool,
+    /// Trusted signers whose signature verified, in public key order.
+    pub valid_signers: Vec<[u8; 32]>,
+    /// Signers that are untrusted or whose signature did not verify.
+    pub rejected_signers: Vec<[u8; 32]>,
+    /// Trusted signers with a valid signature that did not count because
+    /// their key is revoked. Set by [`crate::Keyring::verify_multisig`].
+    pub revoked_signers: Vec<[u8; 32]>,
+    pub threshold_met: bool,
+}
+
+/// Co-sign `capsule` with `keypair`. Each key may sign a capsule only once.
+pub fn add_signature(capsule: &mut Capsule, keypair: &SigningKey) -> Result<(), CapsuleError> {
+    let block = &mut capsule.signature_block;
+    let public_key = keypair.verifying_key().to_bytes();
+
+    if block.public_key == public_key
+        || block
+            .cosignatures
+            .iter()
+            .any(|c| c.public_key == public_key)
+    {
+        return Err(CapsuleError::InvalidArgument(format!(
+            "capsule already signed by {}",
+            hex::encode(public_key)
+        )));
+    }
+
+    let signature = keypair.sign(block.content_hash.as_bytes());
+    let position = block
+        .cosignatures
+        .partition_point(|c| c.public_key < public_key);
+    block.cosignatures.insert(
+        position,
+        CoSignature {
+            public_key,
+            signature: signature.to_bytes(),
+        },
+    );
+
+    Ok(())
+}
+
+/// Copy into `capsule` every signature on `other` that it lacks, provided
+/// the signature verifies against `capsule`'s content hash. Returns whether
+/// any signature was added.
+pub(crate) fn merge_signatures(capsule: &mut Capsule, other: &Capsule) -> bool {
+    let block = &mut capsule.signature_block;
+    let other = &other.signature_block;
+    let incoming = std::iter::once((other.public_key, other.signature)).chain(
+        other
+            .cosignatures
+            .iter()
+            .map(|c| (c.public_key, c.signature)),
+    );
+
+    let mut added = false;
+    for (public_key, signature) in incoming {
+        if block.public_key == public_key
+            || !signature_valid(&public_key, &signature, &block.content_hash)
+        {
+            continue;
+        }
+        if let Err(position) = block
+            .cosignatures
+            .binary_search_by(|c| c.public_key.cmp(&public_key))
+        {
+            block.cosignatures.insert(
+                position,
+                CoSignature {
+                    public_key,
+                    signature,
+                },
+            );
+            added = true;
+        }
+    }
+    added
+}
+
+fn signature_valid(public_key: &[u8; 32], signature: &[u8; 64], content_hash: &str) -> bool {
+    let Ok(public_key) = VerifyingKey::from_bytes(public_key) else {
+        return false;
+    };
+    let signature = Signature::from_bytes(signature);
+    public_key
+        .verify(content_hash.as_bytes(), &signature)
+        .is_ok()
+}
+
+/// Check the primary signature and all co-signatures against `policy`.
+/// Revocations are not consulted: a revoked but trusted key still counts
+/// towards the threshold. Use [`crate::Keyring::verify_multisig`] to
+/// exclude revoked keys.
+pub fn verify_multisig(capsule: &Capsule, policy: &TrustPolicy) -> MultiSigResult {
+    let block = &capsule.signature_block;
+    let content_hash_valid = verify_capsule(capsule).content_hash_valid;
+
+    let signers = std::iter::once((&block.public_key, &block.signature)).chain(
+        block
+            .cosignatures
+            .iter()
+            .map(|c| (&c.public_key, &c.signature)),
+    );
+
+    let mut valid = BTreeSet::new();
+    let mut rejected = BTreeSet::new();
+    for (public_key, signature) in signers {
+        if policy.is_trusted(public_key)
+            && signature_valid(public_key, signature, &block.content_hash)
+        {
+            valid.insert(*public_key);
+        } else {
+            rejected.insert(*public_key);
+        }
+    }
+
+    MultiSigResult {
+        content_hash_valid,
+        threshold_met: content_hash_valid && valid.len() >= policy.threshold,
+        valid_signers: valid.into_iter().collect(),
+        rejected_signers: rejected.into_iter().collect(),
+        revoked_signers: Vec::new(),
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        create_revocation_capsule, create_root_capsule, generate_keypair, get_capsule, put_capsule,
+        Keyring, MemoryStore,
+    };
+
+    fn maintainers(n: usize) -> Vec<SigningKey> {
+        (0..n).map(|_| generate_keypair()).collect()
+    }
+
+    fn policy(keys: &[SigningKey], threshold: usize) -> TrustPolicy {
+        TrustPolicy::new(keys.iter().map(|k| k.verifying_key()), threshold).unwrap()
+    }
+
+    #[test]
+    fn test_threshold_reached_incrementally() {
+        let keys = maintainers(3);
+        let policy = policy(&keys, 2);
+        let mut capsule = create_root_capsule(&keys[0], "Release".to_string()).unwrap();
+
+        let result = verify_multisig(&capsule, &policy);
+        assert_eq!(result.valid_signers.len(), 1);
+        assert!(!result.threshold_met);
+
+        add_signature(&mut capsule, &keys[2]).unwrap();
+        let result = verify_multisig(&capsule, &policy);
+        assert!(result.threshold_met);
+        assert!(result
+            .valid_signers
+            .contains(&keys[0].verifying_key().to_bytes()));
+        assert!(result
+            .valid_signers
+            .contains(&keys[2].verifying_key().to_bytes()));
+        assert!(result.rejected_signers.is_empty());
+
+        // Co-signing does not touch the signed content
+        assert!(verify_capsule(&capsule).crypto_valid);
+    }
+
+    #[test]
+    fn test_duplicate_signer_rejected() {
+        let keys = maintainers(2);
+        let mut capsule = create_root_capsule(&keys[0], "Dup".to_string()).unwrap();
+        assert!(add_signature(&mut capsule, &keys[0]).is_err());
+        add_signature(&mut capsule, &keys[1]).unwrap();
+        assert!(add_signature(&mut capsule, &keys[1]).is_err());
+    }
+
+    #[test]
+    fn test_untrusted_and_forged_signers_do_not_count() {
+        let keys = maintainers(3);
+        let outsider = generate_keypair();
+        let policy = policy(&keys[..2], 2);
+
+        let mut capsule = create_root_capsule(&keys[0], "Forged".to_string()).unwrap();
+        add_signature(&mut capsule, &outsider).unwrap();
+        add_signature(&mut capsule, &keys[1]).unwrap();
+
+        let forged = keys[1].verifying_key().to_bytes();
+        for cosig in &mut capsule.signature_block.cosignatures {
+            if cosig.public_key == forged {
+                cosig.signature[0] ^= 0xff;
+            }
+        }
+
+        let result = verify_multisig(&capsule, &policy);
+        assert!(!result.threshold_met);
+        assert_eq!(
+            result.valid_signers,
+            vec![keys[0].verifying_key().to_bytes()]
+        );
+        assert_eq!(result.rejected_signers.len(), 2);
+    }
+
+    #[test]
+    fn test_revoked_cosigners_do_not_count() {
+        let keys = maintainers(3);
+        let policy = policy(&keys, 2);
+        let mut capsule = create_root_capsule(&keys[0], "Revoked".to_string()).unwrap();
+        add_signature(&mut capsule, &keys[1]).unwrap();
+
+        let mut keyring = Keyring::in_memory();
+        assert!(keyring.verify_multisig(&capsule, &policy).threshold_met);
+
+        let leaked = keys[1].verifying_key();
+        keyring
+            .add_revocation(
+                create_revocation_capsule(&keys[1], &leaked, "leaked".to_string()).unwrap(),
+            )
+            .unwrap();
+
+        let result = keyring.verify_multisig(&capsule, &policy);
+        assert!(!result.threshold_met);
+        assert_eq!(
+            result.valid_signers,
+            vec![keys[0].verifying_key().to_bytes()]
+        );
+        assert_eq!(result.revoked_signers, vec![leaked.to_bytes()]);
+        assert!(result.rejected_signers.is_empty());
+
+        // The keyring-free check still counts the revoked key
+        assert!(verify_multisig(&capsule, &policy).threshold_met);
+
+        // A third, unrevoked maintainer restores the threshold
+        add_signature(&mut capsule, &keys[2]).unwrap();
+        assert!(keyring.verify_multisig(&capsule, &policy).threshold_met);
+    }
+
+    #[test]
+    fn test_signature_order_does_not_affect_encoding() {
+        let keys = maintainers(3);
+        let base = create_root_capsule(&keys[0], "Order".to_string()).unwrap();
+
+        let mut a = base.clone();
+        add_signature(&mut a, &keys[1]).unwrap();
+        add_signature(&mut a, &keys[2]).unwrap();
+        let mut b = base;
+        add_signature(&mut b, &keys[2]).unwrap();
+        add_signature(&mut b, &keys[1]).unwrap();
+
+        assert_eq!(
+            crate::serialize_capsule(&a).unwrap(),
+            crate::serialize_capsule(&b).unwrap()
+        );
+
+        // Co-signatures survive a round trip through a store
+        let store = MemoryStore::new();
+        let hash = put_capsule(&store, &a).unwrap();
+        let loaded = get_capsule(&store, &hash).unwrap().unwrap();
+        assert!(verify_multisig(&loaded, &policy(&keys, 3)).threshold_met);
+    }
+
+    #[test]
+    fn test_invalid_threshold() {
+        let keys = maintainers(2);
+        assert!(TrustPolicy::new(keys.iter().map(|k| k.verifying_key()), 0).is_err());
+        assert!(TrustPolicy::new(keys.iter().map(|k| k.verifying_key()), 3).is_err());
+    }
+}
@@ -0,0 +1,610 @@
+// ============================================================================
+// Object Database: Glyphs, Expressions and Graph Nodes
+// ============================================================================
+//
+// Objects are stored in a `CapsuleStore` as canonical CBOR under their
+// `GlyphV1`/`ExprV1`/`NodeV1` content hashes. References between them are
+// resolved as follows:
+//
+//   GlyphRef { name, version }      the stored glyph with that name and version
+//   ExpressionRef { short_digest }  the `ExprV1` hash starting with the digest,
+//                                   restricted to the ref's `expr_type`
+//   GraphNode::edges                an exact `NodeV1` hash, else a node id,
+//                                   else a unique prefix of a `NodeV1` hash
+//
+// Exact matches win over prefixes, so a node id such as "cafe" is never
+// mistaken for an abbreviated hash. A reference matching more than one
+// object is an error, never a guess.
+//
+// Lookups go through in-memory indexes rather than scanning the store. They
+// cover the keys present when the `ObjectDb` was opened plus every object
+// put through it since.
+
+use crate::store::CapsuleStore;
+use crate::{
+    canonical_cbor, cbor, CapsuleError, ContentAddressable, Expression, ExpressionRef, Glyph,
+    GlyphRef, GraphNode,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+const GLYPH_PREFIX: &str = <Glyph as ContentAddressable>::HASH_PREFIX;
+const EXPR_PREFIX: &str = <Expression as ContentAddressable>::HASH_PREFIX;
+const NODE_PREFIX: &str = <GraphNode as ContentAddressable>::HASH_PREFIX;
+
+/// Every object reachable from a node, keyed by content hash.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Closure {
+    pub nodes: BTreeMap<String, GraphNode>,
+    pub glyphs: BTreeMap<String, Glyph>,
+    pub expressions: BTreeMap<String, Expression>,
+}
+
+#[derive(Debug, Default)]
+struct Indexes {
+    glyphs: BTreeMap<(String, u32), BTreeSet<String>>,
+    node_ids: BTreeMap<String, BTreeSet<String>>,
+    /// `expr_type` of every stored expression, by hash.
+    expr_types: BTreeMap<String, String>,
+    /// Every known key as (hex digest, key), for prefix lookups.
+    digests: BTreeSet<(String, String)>,
+}
+
+impl Indexes {
+    fn insert_key(&mut self, key: &str) {
+        self.digests.insert((digest_of(key), key.to_string()));
+    }
+
+    fn has_key(&self, key: &str) -> bool {
+        self.digests.contains(&(digest_of(key), key.to_string()))
+    }
+
+    /// Keys whose digest starts with `digest`, which must be lowercase.
+    fn keys_with_digest<'a>(&'a self, digest: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.digests
+            .range((digest.to_string(), String::new())..)
+            .take_while(move |(d, _)| d.starts_with(digest))
+            .map(|(_, key)| key)
+    }
+}
+
+/// Content-addressed storage for glyphs, expressions and graph nodes.
+pub struct ObjectDb<S: CapsuleStore> {
+    store: S,
+    indexes: RwLock<Indexes>,
+}
+
+impl<S: CapsuleStore> ObjectDb<S> {
+    /// Wrap `store`, indexing the glyphs and nodes already in it.
+    pub fn new(store: S) -> Result<Self, CapsuleError> {
+        let db = Self {
+            store,
+            indexes: RwLock::new(Indexes::default()),
+        };
+        for key in db.store.list()? {
+            db.index_key(&key);
+            if key.starts_with(&format!("{}:", GLYPH_PREFIX)) {
+                if let Some(glyph) = db.get_glyph(&key)? {
+                    db.index_glyph(&key, &glyph);
+                }
+            } else if key.starts_with(&format!("{}:", NODE_PREFIX)) {
+                if let Some(node) = db.get_node(&key)? {
+                    db.index_node(&key, &node);
+                }
+            } else if key.starts_with(&format!("{}:", EXPR_PREFIX)) {
+                if let Some(expression) = db.get_expression(&key)? {
+                    db.index_expression(&key, &expression);
+                }
+            }
+        }
+        Ok(db)
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn put_glyph(&self, glyph: &Glyph) -> Result<String, CapsuleError> {
+        let hash = self.put(glyph)?;
+        self.index_glyph(&hash, glyph);
+        Ok(hash)
+    }
+
+    pub fn put_expression(&self, expression: &Expression) -> Result<String, CapsuleError> {
+        let hash = self.put(expression)?;
+        self.index_expression(&hash, expression);
+        Ok(hash)
+    }
+
+    pub fn put_node(&self, node: &GraphNode) -> Result<String, CapsuleError> {
+        let hash = self.put(node)?;
+        self.index_node(&hash, node);
+        Ok(hash)
+    }
+
+    pub fn get_glyph(&self, hash: &str) -> Result<Option<Glyph>, CapsuleError> {
+        self.get(hash)
+    }
+
+    pub fn get_expression(&self, hash: &str) -> Result<Option<Expression>, CapsuleError> {
+        self.get(hash)
+    }
+
+    pub fn get_node(&self, hash: &str) -> Result<Option<GraphNode>, CapsuleError> {
+        self.get(hash)
+    }
+
+    fn put<T: ContentAddressable + Serialize>(&self, object: &T) -> Result<String, CapsuleError> {
+        let hash = object.content_hash();
+        self.store.put(&hash, &canonical_cbor(object)?)?;
+        self.index_key(&hash);
+        Ok(hash)
+    }
+
+    /// Load and decode the object under `hash`, checking it hashes back to
+    /// its key.
+    fn get<T: ContentAddressable + DeserializeOwned>(
+        &self,
+        hash: &str,
+    ) -> Result<Option<T>, CapsuleError> {
+        let Some(data) = self.store.get(hash)? else {
+            return Ok(None);
+        };
+        let object: T = cbor::decode(&data)?;
+        let actual = object.content_hash();
+        if actual != hash {
+            return Err(CapsuleError::HashMismatch {
+                expected: hash.to_string(),
+                actual,
+            });
+        }
+        Ok(Some(object))
+    }
+
+    fn index_key(&self, key: &str) {
+        let mut indexes = self.indexes.write().unwrap_or_else(|e| e.into_inner());
+        indexes.insert_key(key);
+    }
+
+    fn index_expression(&self, hash: &str, expression: &Expression) {
+        let mut indexes = self.indexes.write().unwrap_or_else(|e| e.into_inner());
+        indexes
+            .expr_types
+            .insert(hash.to_string(), expression.expr_type.clone());
+    }
+
+    fn index_glyph(&self, hash: &str, glyph: &Glyph) {
+        let mut indexes = self.indexes.write().unwrap_or_else(|e| e.into_inner());
+        indexes
+            .glyphs
+            .entry((glyph.name.clone(), glyph.version))
+            .or_default()
+            .insert(hash.to_string());
+    }
+
+    fn index_node(&self, hash: &str, node: &GraphNode) {
+        let mut indexes = self.indexes.write().unwrap_or_else(|e| e.into_inner());
+        indexes
+            .node_ids
+            .entry(node.id.clone())
+            .or_default()
+            .insert(hash.to_string());
+    }
+
+    // ========================================================================
+    // Reference Resolution
+    // ========================================================================
+
+    /// Expand a short digest to the one stored hash it abbreviates. The
+    /// prefix may be bare hex or carry a type prefix (`ExprV1:ab12`).
+    pub fn resolve_prefix(&self, prefix: &str) -> Result<String, CapsuleError> {
+        let (type_prefix, digest) = match prefix.split_once(':') {
+            Some((type_prefix, digest)) => (Some(type_prefix), digest),
+            None => (None, prefix),
+        };
+        if digest.is_empty() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(CapsuleError::InvalidHash(prefix.to_string()));
+        }
+        let digest = digest.to_ascii_lowercase();
+
+        let candidates: Vec<String> = {
+            let indexes = self.indexes.read().unwrap_or_else(|e| e.into_inner());
+            indexes
+                .keys_with_digest(&digest)
+                .filter(|key| {
+                    let key_type = key.split_once(':').map_or("", |(t, _)| t);
+                    type_prefix.is_none_or(|t| t == key_type)
+                })
+                .cloned()
+                .collect()
+        };
+        unique(prefix, candidates)
+    }
+
+    /// The stored glyph named by `glyph_ref`.
+    pub fn resolve_glyph(&self, glyph_ref: &GlyphRef) -> Result<Glyph, CapsuleError> {
+        let reference = format!("{}@{}", glyph_ref.name, glyph_ref.version);
+        let candidates = {
+            let indexes = self.indexes.read().unwrap_or_else(|e| e.into_inner());
+            indexes
+                .glyphs
+                .get(&(glyph_ref.name.clone(), glyph_ref.version))
+                .map(|hashes| hashes.iter().cloned().collect())
+                .unwrap_or_default()
+        };
+        let hash = unique(&reference, candidates)?;
+        self.get_glyph(&hash)?.ok_or(CapsuleError::NotFound(hash))
+    }
+
+    /// The stored expression named by `expr_ref`. Without a short digest
+    /// the ref resolves only if a single expression has its `expr_type`.
+    pub fn resolve_expression(&self, expr_ref: &ExpressionRef) -> Result<Expression, CapsuleError> {
+        let digest = expr_ref.short_digest.as_deref().unwrap_or("");
+        let reference = format!("{}:{}", EXPR_PREFIX, digest);
+        if !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(CapsuleError::InvalidHash(reference));
+        }
+        let start = format!("{}:{}", EXPR_PREFIX, digest.to_ascii_lowercase());
+
+        let candidates = {
+            let indexes = self.indexes.read().unwrap_or_else(|e| e.into_inner());
+            indexes
+                .expr_types
+                .range(start.clone()..)
+                .take_while(|(key, _)| key.starts_with(&start))
+                .filter(|(_, expr_type)| **expr_type == expr_ref.expr_type)
+                .map(|(key, _)| key.clone())
+                .collect()
+        };
+        let hash = unique(&reference, candidates)?;
+        self.get_expression(&hash)?
+            .ok_or(CapsuleError::NotFound(hash))
+    }
+
+    /// The node an edge points at: a full `NodeV1` hash, else the id of a
+    /// stored node, else a unique prefix of a `NodeV1` hash.
+    pub fn resolve_edge(&self, edge: &str) -> Result<GraphNode, CapsuleError> {
+        let digest = match edge
+            .strip_prefix(NODE_PREFIX)
+            .and_then(|r| r.strip_prefix(':'))
+        {
+            Some(digest) => Some(digest),
+            None if !edge.is_empty() && edge.chars().all(|c| c.is_ascii_hexdigit()) => Some(edge),
+            None => None,
+        };
+        let by_hash = digest.map(|d| format!("{}:{}", NODE_PREFIX, d.to_ascii_lowercase()));
+
+        let (exact, by_id) = {
+            let indexes = self.indexes.read().unwrap_or_else(|e| e.into_inner());
+            let exact = by_hash.as_ref().filter(|hash| indexes.has_key(hash));
+            let by_id: Vec<String> = indexes
+                .node_ids
+                .get(edge)
+                .map(|hashes| hashes.iter().cloned().collect())
+                .unwrap_or_default();
+            (exact.cloned(), by_id)
+        };
+
+        let hash = match (exact, by_hash) {
+            (Some(hash), _) => hash,
+            (None, _) if !by_id.is_empty() => unique(edge, by_id)?,
+            (None, Some(prefix)) => match self.resolve_prefix(&prefix) {
+                Err(CapsuleError::NotFound(_)) => {
+                    return Err(CapsuleError::NotFound(edge.to_string()))
+                }
+                result => result?,
+            },
+            (None, None) => return Err(CapsuleError::NotFound(edge.to_string())),
+        };
+        self.get_node(&hash)?.ok_or(CapsuleError::NotFound(hash))
+    }
+
+    /// Fetch the node stored under `node_hash` and everything it reaches
+    /// through edges, glyph refs and expression children. Fails if any
+    /// reference cannot be resolved, so a returned closure is complete.
+    pub fn closure(&self, node_hash: &str) -> Result<Closure, CapsuleError> {
+        let root = self
+            .get_node(node_hash)?
+            .ok_or_else(|| CapsuleError::NotFound(node_hash.to_string()))?;
+
+        let mut closure = Closure::default();
+        let mut pending_nodes = vec![root];
+        let mut pending_exprs = Vec::new();
+
+        while let Some(node) = pending_nodes.pop() {
+            let hash = node.content_hash();
+            if closure.nodes.contains_key(&hash) {
+                continue;
+            }
+            if let Some(glyph_ref) = &node.glyph {
+                let glyph = self.resolve_glyph(glyph_ref)?;
+                closure.glyphs.insert(glyph.content_hash(), glyph);
+            }
+            if let Some(expr_ref) = &node.expression {
+                pending_exprs.push(self.resolve_expression(expr_ref)?);
+            }
+            for edge in &node.edges {
+                pending_nodes.push(self.resolve_edge(edge)?);
+            }
+            closure.nodes.insert(hash, node);
+        }
+
+        while let Some(expression) = pending_exprs.pop() {
+            let hash = expression.content_hash();
+            if closure.expressions.contains_key(&hash) {
+                continue;
+            }
+            for child in &expression.children {
+                pending_exprs.push(self.resolve_expression(child)?);
+            }
+            closure.expressions.insert(hash, expression);
+        }
+
+        Ok(closure)
+    }
+}
+
+/// The lowercase hex digest at the end of a store key.
+fn digest_of(key: &str) -> String {
+    key.rsplit(':').next().unwrap_or(key).to_ascii_lowercase()
+}
+
+/// The single candidate for `reference`, or the error explaining why there
+/// is not exactly one.
+fn unique(reference: &str, mut candidates: Vec<String>) -> Result<String, CapsuleError> {
+    match candidates.len() {
+        0 => Err(CapsuleError::NotFound(reference.to_string())),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(CapsuleError::AmbiguousRef {
+            reference: reference.to_string(),
+            candidates,
+        }),
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{FsStore, MemoryStore};
+
+    fn glyph(name: &str, version: u32, payload: &[u8]) -> Glyph {
+        Glyph {
+            name: name.to_string(),
+            version,
+            payload: payload.to_vec(),
+        }
+    }
+
+    fn expr(expr_type: &str, data: &[u8], children: Vec<ExpressionRef>) -> Expression {
+        Expression {
+            expr_type: expr_type.to_string(),
+            data: data.to_vec(),
+            children,
+        }
+    }
+
+    fn expr_ref(expression: &Expression, digest_len: usize) -> ExpressionRef {
+        let hash = expression.content_hash();
+        let digest = hash.split_once(':').unwrap().1;
+        ExpressionRef {
+            expr_type: expression.expr_type.clone(),
+            short_digest: Some(digest[..digest_len].to_string()),
+        }
+    }
+
+    fn node(
+        id: &str,
+        glyph: Option<&Glyph>,
+        expr: Option<ExpressionRef>,
+        edges: &[&str],
+    ) -> GraphNode {
+        GraphNode {
+            id: id.to_string(),
+            label: None,
+            glyph: glyph.map(|g| GlyphRef {
+                name: g.name.clone(),
+                version: g.version,
+            }),
+            expression: expr,
+            edges: edges.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_put_get_roundtrip_checks_hash() {
+        let db = ObjectDb::new(MemoryStore::new()).unwrap();
+        let g = glyph("add", 1, b"+");
+        let hash = db.put_glyph(&g).unwrap();
+        assert!(hash.starts_with("GlyphV1:"));
+        assert_eq!(db.get_glyph(&hash).unwrap(), Some(g));
+
+        // A blob cannot be filed under the wrong hash
+        let other = expr("lit", b"1", vec![]);
+        let wrong = format!("ExprV1:{}", "ab".repeat(32));
+        assert!(matches!(
+            db.store().put(&wrong, &canonical_cbor(&other).unwrap()),
+            Err(CapsuleError::HashMismatch { .. })
+        ));
+        assert_eq!(db.get_expression(&wrong).unwrap(), None);
+    }
+
+    #[test]
+    fn test_glyph_ref_resolution() {
+        let db = ObjectDb::new(MemoryStore::new()).unwrap();
+        let v1 = glyph("mul", 1, b"*");
+        db.put_glyph(&v1).unwrap();
+        db.put_glyph(&glyph("mul", 2, b"x")).unwrap();
+
+        let r = GlyphRef {
+            name: "mul".to_string(),
+            version: 1,
+        };
+        assert_eq!(db.resolve_glyph(&r).unwrap(), v1);
+
+        db.put_glyph(&glyph("mul", 1, b"times")).unwrap();
+        match db.resolve_glyph(&r) {
+            Err(CapsuleError::AmbiguousRef { candidates, .. }) => assert_eq!(candidates.len(), 2),
+            other => panic!("expected AmbiguousRef, got {:?}", other),
+        }
+
+        let missing = GlyphRef {
+            name: "div".to_string(),
+            version: 1,
+        };
+        assert!(matches!(
+            db.resolve_glyph(&missing),
+            Err(CapsuleError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_short_digest_prefix_lookup() {
+        let db = ObjectDb::new(MemoryStore::new()).unwrap();
+        // Find two expressions whose hashes share a first hex digit
+        let mut exprs: Vec<Expression> = Vec::new();
+        let mut i = 0u8;
+        let (a, b) = loop {
+            let e = expr("lit", &[i], vec![]);
+            i += 1;
+            let first = e.content_hash().as_bytes()[7];
+            if let Some(prev) = exprs
+                .iter()
+                .find(|p| p.content_hash().as_bytes()[7] == first)
+            {
+                break (prev.clone(), e);
+            }
+            exprs.push(e);
+        };
+        let hash_a = db.put_expression(&a).unwrap();
+        db.put_expression(&b).unwrap();
+
+        // One hex digit is shared, the full hash is not
+        assert!(matches!(
+            db.resolve_expression(&expr_ref(&a, 1)),
+            Err(CapsuleError::AmbiguousRef { .. })
+        ));
+        assert_eq!(db.resolve_expression(&expr_ref(&a, 64)).unwrap(), a);
+        assert_eq!(db.resolve_prefix(&hash_a[..20]).unwrap(), hash_a);
+        assert!(matches!(
+            db.resolve_prefix(&hash_a[..8]),
+            Err(CapsuleError::AmbiguousRef { .. })
+        ));
+
+        // The expr_type narrows the candidates
+        let typed = ExpressionRef {
+            expr_type: "other".to_string(),
+            ..expr_ref(&a, 64)
+        };
+        assert!(matches!(
+            db.resolve_expression(&typed),
+            Err(CapsuleError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_edges_prefer_exact_matches_over_hash_prefixes() {
+        let db = ObjectDb::new(MemoryStore::new()).unwrap();
+
+        // A node whose hash starts with "a", and a node whose id is "a"
+        let by_hash = (0..)
+            .map(|i| node(&format!("n{}", i), None, None, &[]))
+            .find(|n| n.content_hash().starts_with("NodeV1:a"))
+            .unwrap();
+        let hash = db.put_node(&by_hash).unwrap();
+        assert_eq!(db.resolve_edge("a").unwrap(), by_hash);
+
+        let by_id = node("a", None, None, &[]);
+        db.put_node(&by_id).unwrap();
+        assert_eq!(db.resolve_edge("a").unwrap(), by_id);
+
+        // Full hashes and longer prefixes still reach the other node
+        let digest = hash.split_once(':').unwrap().1;
+        assert_eq!(db.resolve_edge(&hash).unwrap(), by_hash);
+        assert_eq!(db.resolve_edge(digest).unwrap(), by_hash);
+        assert_eq!(db.resolve_edge(&digest[..12]).unwrap(), by_hash);
+
+        // A node whose id is another node's full digest loses to the hash
+        let impostor = node(digest, None, None, &[]);
+        db.put_node(&impostor).unwrap();
+        assert_eq!(db.resolve_edge(digest).unwrap(), by_hash);
+
+        assert!(matches!(
+            db.resolve_edge("missing"),
+            Err(CapsuleError::NotFound(e)) if e == "missing"
+        ));
+    }
+
+    #[test]
+    fn test_closure_walks_nodes_glyphs_and_expressions() {
+        let db = ObjectDb::new(MemoryStore::new()).unwrap();
+
+        let leaf_expr = expr("lit", b"2", vec![]);
+        let top_expr = expr("add", b"", vec![expr_ref(&leaf_expr, 12)]);
+        db.put_expression(&leaf_expr).unwrap();
+        db.put_expression(&top_expr).unwrap();
+        let g = glyph("add", 1, b"+");
+        db.put_glyph(&g).unwrap();
+
+        let leaf = node("leaf", Some(&g), None, &[]);
+        let leaf_hash = db.put_node(&leaf).unwrap();
+        // One edge by abbreviated hash, one by node id
+        let middle = node("middle", None, None, &[&leaf_hash[..16]]);
+        db.put_node(&middle).unwrap();
+        let root = node(
+            "root",
+            None,
+            Some(expr_ref(&top_expr, 12)),
+            &["middle", &leaf_hash],
+        );
+        let root_hash = db.put_node(&root).unwrap();
+
+        let closure = db.closure(&root_hash).unwrap();
+        assert_eq!(closure.nodes.len(), 3);
+        assert_eq!(closure.glyphs.len(), 1);
+        assert_eq!(closure.expressions.len(), 2);
+        assert_eq!(closure.nodes[&root_hash], root);
+
+        // A dangling edge makes the closure incomplete
+        let dangling = node("dangling", None, None, &["nowhere"]);
+        let dangling_hash = db.put_node(&dangling).unwrap();
+        assert!(matches!(
+            db.closure(&dangling_hash),
+            Err(CapsuleError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_indexes_rebuilt_from_store() {
+        let dir = std::env::temp_dir().join(format!("capsule_objects_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let g = glyph("neg", 1, b"-");
+        {
+            let db = ObjectDb::new(FsStore::open(&dir).unwrap()).unwrap();
+            db.put_glyph(&g).unwrap();
+            db.put_node(&node("n", Some(&g), None, &[])).unwrap();
+            db.put_expression(&expr("lit", b"7", vec![])).unwrap();
+        }
+
+        let db = ObjectDb::new(FsStore::open(&dir).unwrap()).unwrap();
+        let r = GlyphRef {
+            name: "neg".to_string(),
+            version: 1,
+        };
+        assert_eq!(db.resolve_glyph(&r).unwrap(), g);
+        assert_eq!(db.resolve_edge("n").unwrap().id, "n");
+        let lit = ExpressionRef {
+            expr_type: "lit".to_string(),
+            short_digest: None,
+        };
+        assert_eq!(db.resolve_expression(&lit).unwrap().data, b"7");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,625 @@
+// ============================================================================
+// Content-Addressed Capsule Store
+// ============================================================================
+//
+// A `CapsuleStore` maps content hashes to opaque byte blobs. Capsules, graph
+// snapshots and rendered artifacts all share the same keyspace: a key is
+// either a bare hex digest, a prefixed hash such as "GlyphV1:abcd...", or a
+// prefixed hash naming its algorithm such as "GlyphV1:blake3:abcd...".
+//
+// Every backend checks blobs against their keys on the way in and on the
+// way out. A prefixed key must be the hash of `prefix || data`; a bare key
+// names a serialized capsule and must be its signed content hash. Putting a
+// capsule that is already stored merges in any signatures the stored copy
+// lacks, so co-signatures collected separately are never dropped.
+
+use crate::multisig::merge_signatures;
+use crate::{
+    compute_content_hash_with_prefix, deserialize_capsule, recompute_content_hash,
+    serialize_capsule, Capsule, CapsuleError, HashAlgorithm,
+};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Key-value storage for content-addressed blobs.
+pub trait CapsuleStore {
+    /// Store `data` under `hash`, which must be its content hash. Storing
+    /// the same hash twice keeps the first blob, except that a capsule
+    /// gains the signatures of the second.
+    fn put(&self, hash: &str, data: &[u8]) -> Result<(), CapsuleError>;
+
+    /// Fetch the blob stored under `hash`, if any, checking that it still
+    /// hashes to `hash`.
+    fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, CapsuleError>;
+
+    /// Check whether a blob is stored under `hash`.
+    fn has(&self, hash: &str) -> Result<bool, CapsuleError>;
+
+    /// List every stored hash in lexicographic order.
+    fn list(&self) -> Result<Vec<String>, CapsuleError>;
+}
+
+/// A store key split into its parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Key<'a> {
+    pub prefix: Option<&'a str>,
+    pub algorithm: HashAlgorithm,
+    pub digest: &'a str,
+}
+
+/// Split a key into its optional prefix, hash algorithm and hex digest,
+/// rejecting anything that could not have come from `compute_content_hash*`.
+pub(crate) fn parse_key(hash: &str) -> Result<Key<'_>, CapsuleError> {
+    let invalid = || CapsuleError::InvalidHash(hash.to_string());
+    let (prefix, algorithm, digest) = match hash.split(':').collect::<Vec<_>>()[..] {
+        [digest] => (None, HashAlgorithm::Sha256, digest),
+        [prefix, digest] => (Some(prefix), HashAlgorithm::Sha256, digest),
+        // SHA-256 hashes never name their algorithm
+        [prefix, name, digest] => match HashAlgorithm::from_name(name) {
+            Some(algorithm) if algorithm != HashAlgorithm::Sha256 => {
+                (Some(prefix), algorithm, digest)
+            }
+            _ => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+
+    if let Some(prefix) = prefix {
+        if prefix.is_empty()
+            || !prefix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            || HashAlgorithm::from_name(prefix).is_some()
+        {
+            return Err(invalid());
+        }
+    }
+    if digest.len() < 4 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    Ok(Key {
+        prefix,
+        algorithm,
+        digest,
+    })
+}
+
+/// Check that `data` is the blob `hash` names.
+pub(crate) fn verify_blob(hash: &str, data: &[u8]) -> Result<(), CapsuleError> {
+    let key = parse_key(hash)?;
+    let actual = match key.prefix {
+        Some(prefix) => {
+            let digest = hex::encode(key.algorithm.digest(&[prefix.as_bytes(), data]));
+            match key.algorithm {
+                HashAlgorithm::Sha256 => format!("{}:{}", prefix, digest),
+                algorithm => format!("{}:{}:{}", prefix, algorithm.name(), digest),
+            }
+        }
+        None => {
+            let capsule = deserialize_capsule(data)?;
+            if capsule.signature_block.content_hash != hash {
+                return Err(CapsuleError::HashMismatch {
+                    expected: hash.to_string(),
+                    actual: capsule.signature_block.content_hash,
+                });
+            }
+            recompute_content_hash(&capsule)?
+        }
+    };
+
+    if actual != hash {
+        return Err(CapsuleError::HashMismatch {
+            expected: hash.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// The blob to store when `incoming` is put under a key already holding
+/// `existing`, or `None` to keep `existing` as it is. Both must already be
+/// verified against the key.
+fn merge_blob(
+    hash: &str,
+    existing: &[u8],
+    incoming: &[u8],
+) -> Result<Option<Vec<u8>>, CapsuleError> {
+    if existing == incoming || parse_key(hash)?.prefix.is_some() {
+        return Ok(None);
+    }
+    let mut capsule = deserialize_capsule(existing)?;
+    if merge_signatures(&mut capsule, &deserialize_capsule(incoming)?) {
+        Ok(Some(serialize_capsule(&capsule)?))
+    } else {
+        Ok(None)
+    }
+}
+
+// ============================================================================
+// In-Memory Store
+// ============================================================================
+
+/// A `CapsuleStore` held entirely in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    blobs: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CapsuleStore for MemoryStore {
+    fn put(&self, hash: &str, data: &[u8]) -> Result<(), CapsuleError> {
+        verify_blob(hash, data)?;
+        let mut blobs = self.blobs.write().unwrap_or_else(|e| e.into_inner());
+        match blobs.get_mut(hash) {
+            Some(existing) => {
+                if let Some(merged) = merge_blob(hash, existing, data)? {
+                    *existing = merged;
+                }
+            }
+            None => {
+                blobs.insert(hash.to_string(), data.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, CapsuleError> {
+        parse_key(hash)?;
+        let blobs = self.blobs.read().unwrap_or_else(|e| e.into_inner());
+        let Some(data) = blobs.get(hash).cloned() else {
+            return Ok(None);
+        };
+        verify_blob(hash, &data)?;
+        Ok(Some(data))
+    }
+
+    fn has(&self, hash: &str) -> Result<bool, CapsuleError> {
+        parse_key(hash)?;
+        let blobs = self.blobs.read().unwrap_or_else(|e| e.into_inner());
+        Ok(blobs.contains_key(hash))
+    }
+
+    fn list(&self) -> Result<Vec<String>, CapsuleError> {
+        let blobs = self.blobs.read().unwrap_or_else(|e| e.into_inner());
+        Ok(blobs.keys().cloned().collect())
+    }
+}
+
+// ============================================================================
+// Filesystem Store
+// ============================================================================
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A `CapsuleStore` backed by a directory on disk.
+///
+/// Blobs are sharded into subdirectories named after the first two hex
+/// characters of their digest, e.g. `GlyphV1:ab12...` lives at
+/// `<root>/ab/GlyphV1.ab12...` and `GlyphV1:blake3:ab12...` at
+/// `<root>/ab/GlyphV1.blake3.ab12...`. Writes go to a temporary file in the shard
+/// and are renamed into place, so readers never observe a partial blob.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    /// Open (and create if necessary) a store rooted at `root`.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self, CapsuleError> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).map_err(|e| CapsuleError::io(&root, e))?;
+        Ok(Self { root })
+    }
+
+    /// Directory the store lives in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path a blob with the given hash is (or would be) stored at.
+    pub fn blob_path(&self, hash: &str) -> Result<PathBuf, CapsuleError> {
+        let key = parse_key(hash)?;
+        let shard = key.digest[..2].to_ascii_lowercase();
+        let file_name = match (key.prefix, key.algorithm) {
+            (None, _) => key.digest.to_string(),
+            (Some(prefix), HashAlgorithm::Sha256) => format!("{}.{}", prefix, key.digest),
+            (Some(prefix), algorithm) => {
+                format!("{}.{}.{}", prefix, algorithm.name(), key.digest)
+            }
+        };
+        Ok(self.root.join(shard).join(file_name))
+    }
+}
+
+impl CapsuleStore for FsStore {
+    fn put(&self, hash: &str, data: &[u8]) -> Result<(), CapsuleError> {
+        verify_blob(hash, data)?;
+        let path = self.blob_path(hash)?;
+        let merged;
+        let data = match self.get(hash)? {
+            Some(existing) => match merge_blob(hash, &existing, data)? {
+                Some(blob) => {
+                    merged = blob;
+                    &merged[..]
+                }
+                None => return Ok(()),
+            },
+            None => data,
+        };
+
+        let shard = path
+            .parent()
+            .expect("blob path always has a shard directory");
+        fs::create_dir_all(shard).map_err(|e| CapsuleError::io(shard, e))?;
+
+        let tmp_path = shard.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let write = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(data)?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, &path)
+        };
+
+        write().map_err(|e| {
+            fs::remove_file(&tmp_path).ok();
+            CapsuleError::io(&path, e)
+        })
+    }
+
+    fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, CapsuleError> {
+        let path = self.blob_path(hash)?;
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(CapsuleError::io(&path, e)),
+        };
+        verify_blob(hash, &data)?;
+        Ok(Some(data))
+    }
+
+    fn has(&self, hash: &str) -> Result<bool, CapsuleError> {
+        Ok(self.blob_path(hash)?.is_file())
+    }
+
+    fn list(&self) -> Result<Vec<String>, CapsuleError> {
+        let read_dir = |dir: &Path| fs::read_dir(dir).map_err(|e| CapsuleError::io(dir, e));
+
+        let mut hashes = Vec::new();
+        for shard in read_dir(&self.root)? {
+            let shard = shard.map_err(|e| CapsuleError::io(&self.root, e))?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for entry in read_dir(&shard)? {
+                let name = entry.map_err(|e| CapsuleError::io(&shard, e))?.file_name();
+                let name = name.to_string_lossy();
+                if name.starts_with(".tmp-") {
+                    continue;
+                }
+                let hash = name.replace('.', ":");
+                if parse_key(&hash).is_ok() {
+                    hashes.push(hash);
+                }
+            }
+        }
+
+        hashes.sort();
+        Ok(hashes)
+    }
+}
+
+// ============================================================================
+// Typed Helpers
+// ============================================================================
+
+/// Hash `data` with `prefix` and store it, returning the key.
+pub fn put_blob<S: CapsuleStore + ?Sized>(
+    store: &S,
+    prefix: &str,
+    data: &[u8],
+) -> Result<String, CapsuleError> {
+    let hash = compute_content_hash_with_prefix(prefix, data);
+    store.put(&hash, data)?;
+    Ok(hash)
+}
+
+/// Store a capsule under its signed content hash, returning the key.
+pub fn put_capsule<S: CapsuleStore + ?Sized>(
+    store: &S,
+    capsule: &Capsule,
+) -> Result<String, CapsuleError> {
+    let data = serialize_capsule(capsule)?;
+    let hash = capsule.signature_block.content_hash.clone();
+    store.put(&hash, &data)?;
+    Ok(hash)
+}
+
+/// Load the capsule stored under `hash`, checking that it is the capsule the
+/// key claims it is. The content hash is recomputed here as well, so a
+/// store that does not verify blobs itself cannot pass off another capsule.
+pub fn get_capsule<S: CapsuleStore + ?Sized>(
+    store: &S,
+    hash: &str,
+) -> Result<Option<Capsule>, CapsuleError> {
+    let data = match store.get(hash)? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+
+    let capsule = deserialize_capsule(&data)?;
+
+    if capsule.signature_block.content_hash != hash {
+        return Err(CapsuleError::HashMismatch {
+            expected: hash.to_string(),
+            actual: capsule.signature_block.content_hash,
+        });
+    }
+    let actual = recompute_content_hash(&capsule)?;
+    if actual != hash {
+        return Err(CapsuleError::HashMismatch {
+            expected: hash.to_string(),
+            actual,
+        });
+    }
+
+    Ok(Some(capsule))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        add_signature, create_child_capsule, create_root_capsule, generate_keypair, verify_chain,
+    };
+
+    fn temp_store_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("capsule_store_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    fn exercise_store<S: CapsuleStore>(store: &S) {
+        let hash = put_blob(store, "RenderV1", b"framebuffer").unwrap();
+        assert!(hash.starts_with("RenderV1:"));
+        assert!(store.has(&hash).unwrap());
+        assert_eq!(
+            store.get(&hash).unwrap().as_deref(),
+            Some(&b"framebuffer"[..])
+        );
+
+        // Idempotent put
+        store.put(&hash, b"framebuffer").unwrap();
+
+        let missing = format!("RenderV1:{}", "0".repeat(64));
+        assert!(!store.has(&missing).unwrap());
+        assert_eq!(store.get(&missing).unwrap(), None);
+
+        let keypair = generate_keypair();
+        let root = create_root_capsule(&keypair, "Store".to_string()).unwrap();
+        let child =
+            create_child_capsule(&root, &keypair, "Child".to_string(), vec![1, 2, 3]).unwrap();
+        let root_hash = put_capsule(store, &root).unwrap();
+        let child_hash = put_capsule(store, &child).unwrap();
+
+        let loaded = vec![
+            get_capsule(store, &root_hash).unwrap().unwrap(),
+            get_capsule(store, &child_hash).unwrap().unwrap(),
+        ];
+        assert_eq!(verify_chain(&loaded), Ok(()));
+
+        let mut expected = vec![hash, root_hash, child_hash];
+        expected.sort();
+        assert_eq!(store.list().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        exercise_store(&MemoryStore::new());
+    }
+
+    #[test]
+    fn test_fs_store() {
+        let dir = temp_store_dir("roundtrip");
+        let store = FsStore::open(&dir).unwrap();
+        exercise_store(&store);
+
+        // A second handle on the same directory sees the same blobs
+        let reopened = FsStore::open(&dir).unwrap();
+        assert_eq!(reopened.list().unwrap(), store.list().unwrap());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_fs_store_shards_by_digest_prefix() {
+        let dir = temp_store_dir("shard");
+        let store = FsStore::open(&dir).unwrap();
+        let hash = put_blob(&store, "GlyphV1", b"payload").unwrap();
+        let digest = hash.split_once(':').unwrap().1;
+
+        let path = store.blob_path(&hash).unwrap();
+        assert_eq!(
+            path,
+            dir.join(&digest[..2]).join(format!("GlyphV1.{}", digest))
+        );
+        assert!(path.is_file());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_blake3_keys() {
+        let data = b"payload";
+        let hash = crate::compute_content_hash_with::<crate::Blake3Hasher>("GlyphV1", data);
+        let key = parse_key(&hash).unwrap();
+        assert_eq!(key.prefix, Some("GlyphV1"));
+        assert_eq!(key.algorithm, HashAlgorithm::Blake3);
+
+        let dir = temp_store_dir("blake3");
+        let store = FsStore::open(&dir).unwrap();
+        store.put(&hash, data).unwrap();
+        let path = store.blob_path(&hash).unwrap();
+        assert_eq!(
+            path.file_name().unwrap().to_string_lossy(),
+            format!("GlyphV1.blake3.{}", key.digest)
+        );
+        assert_eq!(store.get(&hash).unwrap().as_deref(), Some(&data[..]));
+        assert_eq!(store.list().unwrap(), vec![hash]);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rejects_malformed_keys() {
+        let store = MemoryStore::new();
+        assert!(store.put("../../etc/passwd", b"x").is_err());
+        assert!(store.put("GlyphV1:xyz!", b"x").is_err());
+        assert!(store.put(":abcd", b"x").is_err());
+        assert!(store.put("GlyphV1:md5:abcd", b"x").is_err());
+        assert!(store.put("GlyphV1:sha2-256:abcd", b"x").is_err());
+        assert!(store.put("blake3:abcd", b"x").is_err());
+        assert!(store.put("a:b:c:abcd", b"x").is_err());
+
+        let dir = temp_store_dir("malformed");
+        let fs_store = FsStore::open(&dir).unwrap();
+        assert!(fs_store.get("../secret").is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_get_capsule_rejects_mislabelled_blob() {
+        let keypair = generate_keypair();
+        let capsule = create_root_capsule(&keypair, "Mislabel".to_string()).unwrap();
+        let data = serialize_capsule(&capsule).unwrap();
+        let wrong = "ab".repeat(32);
+
+        let store = MemoryStore::new();
+        assert!(matches!(
+            store.put(&wrong, &data),
+            Err(CapsuleError::HashMismatch { .. })
+        ));
+
+        // A blob planted on disk is caught on the way out
+        let dir = temp_store_dir("mislabel");
+        let fs_store = FsStore::open(&dir).unwrap();
+        let path = fs_store.blob_path(&wrong).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &data).unwrap();
+        assert!(fs_store.has(&wrong).unwrap());
+        assert!(get_capsule(&fs_store, &wrong).is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_put_and_get_verify_blobs() {
+        let dir = temp_store_dir("verify");
+        let fs_store = FsStore::open(&dir).unwrap();
+        let memory = MemoryStore::new();
+        let stores: [&dyn CapsuleStore; 2] = [&memory, &fs_store];
+
+        let hash = compute_content_hash_with_prefix("RenderV1", b"frame");
+        for store in stores {
+            assert!(matches!(
+                store.put(&hash, b"other frame"),
+                Err(CapsuleError::HashMismatch { .. })
+            ));
+            assert!(!store.has(&hash).unwrap());
+            assert!(store.get("../x").is_err());
+            assert!(store.has("../x").is_err());
+        }
+
+        // A capsule whose signed hash no longer covers its content
+        let keypair = generate_keypair();
+        let mut capsule = create_root_capsule(&keypair, "Verify".to_string()).unwrap();
+        capsule.content.push(0);
+        let data = serialize_capsule(&capsule).unwrap();
+        for store in stores {
+            assert!(matches!(
+                put_capsule(store, &capsule),
+                Err(CapsuleError::HashMismatch { .. })
+            ));
+        }
+
+        // Bytes corrupted on disk are rejected by get
+        fs_store.put(&hash, b"frame").unwrap();
+        fs::write(fs_store.blob_path(&hash).unwrap(), b"frame!").unwrap();
+        assert!(matches!(
+            fs_store.get(&hash),
+            Err(CapsuleError::HashMismatch { .. })
+        ));
+        let bare = capsule.signature_block.content_hash.clone();
+        let path = fs_store.blob_path(&bare).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &data).unwrap();
+        assert!(fs_store.get(&bare).is_err());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_put_merges_cosignatures() {
+        let dir = temp_store_dir("merge");
+        let fs_store = FsStore::open(&dir).unwrap();
+        let memory = MemoryStore::new();
+        let stores: [&dyn CapsuleStore; 2] = [&memory, &fs_store];
+
+        let (alice, bob, carol) = (generate_keypair(), generate_keypair(), generate_keypair());
+        let capsule = create_root_capsule(&alice, "Merge".to_string()).unwrap();
+        let mut with_bob = capsule.clone();
+        add_signature(&mut with_bob, &bob).unwrap();
+        let mut with_carol = capsule.clone();
+        add_signature(&mut with_carol, &carol).unwrap();
+
+        // A signature that does not verify is not merged
+        let mut forged = capsule.clone();
+        add_signature(&mut forged, &generate_keypair()).unwrap();
+        forged.signature_block.cosignatures[0].signature[0] ^= 1;
+
+        for store in stores {
+            let hash = put_capsule(store, &with_bob).unwrap();
+            put_capsule(store, &with_carol).unwrap();
+            put_capsule(store, &forged).unwrap();
+            put_capsule(store, &capsule).unwrap();
+
+            let stored = get_capsule(store, &hash).unwrap().unwrap();
+            let mut expected: Vec<_> = [&bob, &carol]
+                .iter()
+                .map(|k| k.verifying_key().to_bytes())
+                .collect();
+            expected.sort();
+            let signers: Vec<_> = stored
+                .signature_block
+                .cosignatures
+                .iter()
+                .map(|c| c.public_key)
+                .collect();
+            assert_eq!(signers, expected);
+            assert_eq!(
+                stored.signature_block.public_key,
+                alice.verifying_key().to_bytes()
+            );
+            assert_eq!(store.list().unwrap(), vec![hash]);
+        }
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
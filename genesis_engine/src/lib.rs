@@ -10,6 +10,9 @@
 // thiserror = "1.0"
 // parking_lot = "0.12"
 // rayon = "1.8"
+// genesis_graph = { path = "../genesis_graph" }
+// glyph_ast = { path = "../glyph_ast" }
+// glyph_engine = { path = "../glyph_engine" }
 //
 // [dev-dependencies]
 // proptest = "1.4"
@@ -21,8 +24,16 @@ use std::sync::Arc;
 use parking_lot::{RwLock, RwLockReadGuard};
 use rayon::prelude::*;
 use thiserror::Error;
+use genesis_graph::merkle::{graph_tree, node_leaf, MerkleTree, NodeProof};
+use glyph_engine::condition::{self, ConditionError};
+use glyph_engine::pattern::Bindings;
+use glyph_engine::substitute::substitute_many;
 use std::time::{Duration, Instant};
 
+pub mod strategy;
+
+pub use strategy::{Position, RewriteStrategy};
+
 // ============================================================================
 // Core Types (from previous modules)
 // ============================================================================
@@ -30,77 +41,7 @@ use std::time::{Duration, Instant};
 pub type Hash = String;
 pub type NodeId = String;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub enum Expression {
-    Literal(Literal),
-    Var(String),
-    Lambda {
-        param: String,
-        body: Box<Expression>,
-    },
-    Apply {
-        func: Box<Expression>,
-        arg: Box<Expression>,
-    },
-    LinearApply {
-        func: Box<Expression>,
-        arg: Box<Expression>,
-    },
-    Let {
-        name: String,
-        value: Box<Expression>,
-        body: Box<Expression>,
-    },
-    Match {
-        expr: Box<Expression>,
-        arms: Vec<MatchArm>,
-    },
-    Tuple(Vec<Expression>),
-    List(Vec<Expression>),
-    Record(Vec<(String, Expression)>),
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub enum Literal {
-    Int(i64),
-    Float(String),
-    String(String),
-    Bool(bool),
-    Unit,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub struct MatchArm {
-    pub pattern: Pattern,
-    pub guard: Option<Box<Expression>>,
-    pub body: Box<Expression>,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub enum Pattern {
-    Wildcard,
-    Var(String),
-    Literal(Literal),
-    Bind {
-        name: String,
-        pattern: Box<Pattern>,
-    },
-    Tuple(Vec<Pattern>),
-    List(Vec<Pattern>),
-    Constructor {
-        name: String,
-        args: Vec<Pattern>,
-    },
-    Record(Vec<(String, Pattern)>),
-    Lambda {
-        param_pattern: Box<Pattern>,
-        body_pattern: Box<Pattern>,
-    },
-    Apply {
-        func_pattern: Box<Pattern>,
-        arg_pattern: Box<Pattern>,
-    },
-}
+pub use glyph_ast::{Expression, Literal, MatchArm, Pattern};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GraphNode {
@@ -131,6 +72,31 @@ pub enum EdgeType {
     Reference,
 }
 
+impl From<&GraphNode> for genesis_graph::GraphNode {
+    fn from(node: &GraphNode) -> Self {
+        genesis_graph::GraphNode {
+            id: node.id.clone(),
+            root_ref: node.root_ref.clone(),
+            data: node.data.clone(),
+            metadata: genesis_graph::NodeMetadata {
+                timestamp: node.metadata.timestamp,
+                lineage_depth: node.metadata.lineage_depth,
+                tags: node.metadata.tags.clone(),
+            },
+        }
+    }
+}
+
+impl From<EdgeType> for genesis_graph::EdgeType {
+    fn from(edge_type: EdgeType) -> Self {
+        match edge_type {
+            EdgeType::Dependency => genesis_graph::EdgeType::Dependency,
+            EdgeType::Derivation => genesis_graph::EdgeType::Derivation,
+            EdgeType::Reference => genesis_graph::EdgeType::Reference,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenesisGraph {
     nodes: HashMap<Hash, GraphNode>,
@@ -180,6 +146,76 @@ impl GenesisGraph {
         nodes
     }
 
+    /// The Merkle root `genesis_graph` would commit to for a graph with the
+    /// same content. Rewrites update nodes in place under their original
+    /// hash, so leaves use each node's current content hash instead.
+    pub fn merkle_root(&self) -> Hash {
+        self.merkle_tree().0.root_hex()
+    }
+
+    /// Prove that the node stored under `hash` is part of the graph at
+    /// [`Self::merkle_root`]. Check the proof with
+    /// `genesis_graph::verify_node_proof` on [`Self::committed_node`].
+    pub fn prove_node(&self, hash: &Hash) -> Result<NodeProof, RuntimeError> {
+        let (tree, committed) = self.merkle_tree();
+        let leaf = committed
+            .get(hash)
+            .map(node_leaf)
+            .ok_or_else(|| RuntimeError::NodeNotFound(hash.clone()))?;
+        Ok(tree.proof(&leaf).expect("every node has a leaf"))
+    }
+
+    /// The node stored under `hash` as `genesis_graph` holds it: the root
+    /// has an empty `root_ref`, and other nodes refer to the root by its
+    /// `genesis_graph` hash.
+    pub fn committed_node(&self, hash: &Hash) -> Option<genesis_graph::GraphNode> {
+        self.nodes.get(hash)?;
+        Some(self.commit_node(hash, &self.committed_root_hash()))
+    }
+
+    fn commit_node(&self, hash: &Hash, committed_root_hash: &Hash) -> genesis_graph::GraphNode {
+        let mut node = genesis_graph::GraphNode::from(&self.nodes[hash]);
+        if *hash == self.root_hash {
+            node.root_ref = String::new();
+        } else if node.root_ref == self.root_hash {
+            node.root_ref = committed_root_hash.clone();
+        }
+        node
+    }
+
+    fn committed_root_hash(&self) -> Hash {
+        genesis_graph::compute_root_hash(&self.commit_node(&self.root_hash, &self.root_hash))
+    }
+
+    /// The graph's Merkle tree, and the content hash of each stored node.
+    fn merkle_tree(&self) -> (MerkleTree, HashMap<&Hash, Hash>) {
+        let root_hash = self.committed_root_hash();
+        let committed: HashMap<&Hash, Hash> = self
+            .nodes
+            .keys()
+            .map(|hash| {
+                let content_hash = if *hash == self.root_hash {
+                    root_hash.clone()
+                } else {
+                    genesis_graph::compute_node_hash(&self.commit_node(hash, &root_hash))
+                };
+                (hash, content_hash)
+            })
+            .collect();
+        let current = |hash: &Hash| committed.get(hash).cloned().unwrap_or_else(|| hash.clone());
+        let edges: Vec<genesis_graph::GraphEdge> = self
+            .edges
+            .iter()
+            .map(|edge| genesis_graph::GraphEdge {
+                from: current(&edge.from),
+                to: current(&edge.to),
+                edge_type: edge.edge_type.clone().into(),
+            })
+            .collect();
+        let tree = graph_tree(committed.values(), &edges);
+        (tree, committed)
+    }
+
     fn update_node(&mut self, hash: &Hash, node: GraphNode) -> Result<(), RuntimeError> {
         if !self.nodes.contains_key(hash) {
             return Err(RuntimeError::NodeNotFound(hash.clone()));
@@ -315,6 +351,13 @@ pub enum RuntimeError {
     #[error("Rule application failed: {0}")]
     RuleApplicationFailed(String),
 
+    #[error("Condition of rule {rule_id} failed: {source}")]
+    ConditionFailed {
+        rule_id: String,
+        #[source]
+        source: ConditionError,
+    },
+
     #[error("Lock acquisition failed")]
     LockError,
 
@@ -358,6 +401,10 @@ pub struct RuleApplication {
     pub node_hash: Hash,
     pub iteration: usize,
     pub timestamp: u64,
+    /// Where in the node's expression the rule applied; empty for the
+    /// whole term
+    #[serde(default)]
+    pub position: Position,
 }
 
 #[derive(Debug, Clone)]
@@ -406,6 +453,7 @@ pub struct RuntimeConfig {
     pub parallel_matching: bool,
     pub deterministic_ordering: bool,
     pub enable_logging: bool,
+    pub strategy: RewriteStrategy,
 }
 
 impl Default for RuntimeConfig {
@@ -416,6 +464,7 @@ impl Default for RuntimeConfig {
             parallel_matching: true,
             deterministic_ordering: true,
             enable_logging: true,
+            strategy: RewriteStrategy::Root,
         }
     }
 }
@@ -474,7 +523,7 @@ impl GenesisEngine {
 
         let mut previous_hash = {
             let graph = self.graph.read();
-            compute_graph_hash(&graph)
+            graph.merkle_root()
         };
 
         // Evaluation loop
@@ -501,7 +550,7 @@ impl GenesisEngine {
             // Compute current graph hash
             let current_hash = {
                 let graph = self.graph.read();
-                compute_graph_hash(&graph)
+                graph.merkle_root()
             };
 
             state.graph_hash = current_hash.clone();
@@ -552,91 +601,27 @@ impl GenesisEngine {
 
         // Process each node
         for (node_hash, node) in nodes_to_process {
-            // Find matching rules using parallel matching if enabled
-            let matching_rules = if self.config.parallel_matching {
-                self.find_matching_rules_parallel(&node, rules)
-            } else {
-                self.find_matching_rules_sequential(&node, rules)
-            };
-
-            if let Some(rule) = matching_rules.first() {
-                // Apply the first matching rule (highest priority)
-                match self.apply_rule_to_node(&node_hash, &node, rule, iteration) {
-                    Ok(true) => {
-                        total_fired += 1;
-                    }
-                    Ok(false) => {}
-                    Err(e) => return Err(e),
-                }
-            }
+            total_fired += self.rewrite_node(&node_hash, &node, rules, iteration)?;
         }
 
         Ok(total_fired)
     }
 
-    /// Find matching rules using parallel pattern matching
-    fn find_matching_rules_parallel<'a>(&self, node: &GraphNode, rules: &[&'a Rule]) -> Vec<&'a Rule> {
-        let mut matching: Vec<&'a Rule> = rules.par_iter()
-            .filter(|rule| self.rule_matches_node(node, rule))
-            .copied()
-            .collect();
-        
-        matching.sort_by(|a, b| {
-            match b.priority.cmp(&a.priority) {
-                std::cmp::Ordering::Equal => a.id.cmp(&b.id),
-                other => other,
-            }
-        });
-        matching
-    }
-
-    /// Find matching rules sequentially
-    fn find_matching_rules_sequential<'a>(&self, node: &GraphNode, rules: &[&'a Rule]) -> Vec<&'a Rule> {
-        rules.iter()
-            .filter(|rule| self.rule_matches_node(node, rule))
-            .copied()
-            .collect()
-    }
-
-    /// Check if a rule matches a node
-    fn rule_matches_node(&self, node: &GraphNode, rule: &Rule) -> bool {
-        let bindings = match_pattern(&node.data, &rule.pattern);
-
-        if bindings.is_empty() {
-            return false;
-        }
-
-        // Check condition if present
-        if let Some(condition) = &rule.condition {
-            if !evaluate_condition(condition, &bindings[0]) {
-                return false;
-            }
-        }
-
-        true
-    }
-
-    /// Apply a specific rule to a node
-    fn apply_rule_to_node(
+    /// Take one step of the configured strategy over a node's expression
+    fn rewrite_node(
         &self,
         node_hash: &Hash,
         node: &GraphNode,
-        rule: &Rule,
+        rules: &[&Rule],
         iteration: usize,
-    ) -> Result<bool, RuntimeError> {
-        // Match pattern and get bindings
-        let bindings = match_pattern(&node.data, &rule.pattern);
-
-        if bindings.is_empty() {
-            return Ok(false);
-        }
-
-        // Apply substitutions to replacement
-        let new_data = apply_bindings(&rule.replacement, &bindings[0]);
+    ) -> Result<usize, RuntimeError> {
+        let (new_data, rewrites) = strategy::rewrite(&node.data, self.config.strategy, |term| {
+            self.rewrite_subterm(term, rules)
+        })?;
 
         // Skip update if data hasn't changed (ΔG = 0)
-        if new_data == node.data {
-            return Ok(false);
+        if rewrites.is_empty() || new_data == node.data {
+            return Ok(0);
         }
 
         // Create updated node
@@ -657,21 +642,105 @@ impl GenesisEngine {
             graph.update_node(node_hash, new_node)?;
         }
 
-        // Log the application
+        // Log the applications
         if self.config.enable_logging {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
             let mut log = self.log.write();
-            log.record_application(RuleApplication {
-                rule_id: rule.id.clone(),
-                node_hash: node_hash.clone(),
-                iteration,
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-            });
+            for rewrite in &rewrites {
+                log.record_application(RuleApplication {
+                    rule_id: rewrite.tag.clone(),
+                    node_hash: node_hash.clone(),
+                    iteration,
+                    timestamp,
+                    position: rewrite.position.clone(),
+                });
+            }
         }
 
-        Ok(true)
+        Ok(rewrites.len())
+    }
+
+    /// Rewrite a single subterm with the first matching rule (highest
+    /// priority), or `None` if no rule matches or the rule changes nothing
+    fn rewrite_subterm(
+        &self,
+        term: &Expression,
+        rules: &[&Rule],
+    ) -> Result<Option<(String, Expression)>, RuntimeError> {
+        // Find matching rules using parallel matching if enabled
+        let matching_rules = if self.config.parallel_matching {
+            self.find_matching_rules_parallel(term, rules)?
+        } else {
+            self.find_matching_rules_sequential(term, rules)?
+        };
+
+        let Some(rule) = matching_rules.first() else {
+            return Ok(None);
+        };
+
+        // Match pattern and apply substitutions to replacement
+        let bindings = match_pattern(term, &rule.pattern);
+        let Some(bindings) = bindings.first() else {
+            return Ok(None);
+        };
+        let new_term = apply_bindings(&rule.replacement, bindings);
+
+        if new_term == *term {
+            return Ok(None);
+        }
+        Ok(Some((rule.id.clone(), new_term)))
+    }
+
+    /// Find matching rules using parallel pattern matching
+    fn find_matching_rules_parallel<'a>(
+        &self,
+        term: &Expression,
+        rules: &[&'a Rule],
+    ) -> Result<Vec<&'a Rule>, RuntimeError> {
+        let matched = rules.par_iter()
+            .map(|rule| Ok(self.rule_matches(term, rule)?.then_some(*rule)))
+            .collect::<Result<Vec<_>, RuntimeError>>()?;
+        let mut matching: Vec<&'a Rule> = matched.into_iter().flatten().collect();
+        
+        matching.sort_by(|a, b| {
+            match b.priority.cmp(&a.priority) {
+                std::cmp::Ordering::Equal => a.id.cmp(&b.id),
+                other => other,
+            }
+        });
+        Ok(matching)
+    }
+
+    /// Find matching rules sequentially
+    fn find_matching_rules_sequential<'a>(
+        &self,
+        term: &Expression,
+        rules: &[&'a Rule],
+    ) -> Result<Vec<&'a Rule>, RuntimeError> {
+        let mut matching = Vec::new();
+        for rule in rules {
+            if self.rule_matches(term, rule)? {
+                matching.push(*rule);
+            }
+        }
+        Ok(matching)
+    }
+
+    /// Check if a rule matches a term and its condition, if any, holds
+    fn rule_matches(&self, term: &Expression, rule: &Rule) -> Result<bool, RuntimeError> {
+        let bindings = match_pattern(term, &rule.pattern);
+
+        if bindings.is_empty() {
+            return Ok(false);
+        }
+
+        match &rule.condition {
+            Some(condition) => evaluate_condition(&rule.id, condition, &bindings[0]),
+            None => Ok(true),
+        }
     }
 
     /// Evaluate until a specific condition is met
@@ -708,7 +777,7 @@ impl GenesisEngine {
                 let graph = self.graph.read();
                 if predicate(&graph) {
                     state.is_idle = true;
-                    state.graph_hash = compute_graph_hash(&graph);
+                    state.graph_hash = graph.merkle_root();
                     state.elapsed_time = start_time.elapsed();
                     break;
                 }
@@ -723,7 +792,7 @@ impl GenesisEngine {
     /// Get current graph hash
     pub fn current_hash(&self) -> Hash {
         let graph = self.graph.read();
-        compute_graph_hash(&graph)
+        graph.merkle_root()
     }
 
     /// Reset the transaction log
@@ -737,23 +806,6 @@ impl GenesisEngine {
 // Helper Functions
 // ============================================================================
 
-fn compute_graph_hash(graph: &GenesisGraph) -> Hash {
-    let mut hasher = Sha256::new();
-    hasher.update(b"GlyphV1:Graph:");
-    
-    let sorted_nodes = graph.nodes_sorted_by_id();
-    for (hash, node) in sorted_nodes {
-        hasher.update(hash.as_bytes());
-        let mut buffer = Vec::new();
-        ciborium::into_writer(node, &mut buffer).expect("Node serialization failed");
-        hasher.update(&buffer);
-    }
-    
-    hasher.update(graph.root_hash().as_bytes());
-
-    hex::encode(hasher.finalize())
-}
-
 fn compute_node_hash(node: &GraphNode) -> Hash {
     let mut buffer = Vec::new();
     ciborium::into_writer(node, &mut buffer).expect("Node serialization failed");
@@ -765,96 +817,34 @@ fn compute_node_hash(node: &GraphNode) -> Hash {
     hex::encode(hasher.finalize())
 }
 
-// Pattern matching (simplified)
-fn match_pattern(expr: &Expression, pattern: &Pattern) -> Vec<HashMap<String, Expression>> {
-    let mut bindings = HashMap::new();
-    if match_pattern_internal(expr, pattern, &mut bindings) {
-        vec![bindings]
-    } else {
-        vec![]
-    }
-}
-
-fn match_pattern_internal(
-    expr: &Expression,
-    pattern: &Pattern,
-    bindings: &mut HashMap<String, Expression>,
-) -> bool {
-    match pattern {
-        Pattern::Wildcard => true,
-        Pattern::Var(name) => {
-            if let Some(existing) = bindings.get(name) {
-                existing == expr
-            } else {
-                bindings.insert(name.clone(), expr.clone());
-                true
-            }
-        }
-        Pattern::Literal(pat_lit) => {
-            matches!(expr, Expression::Literal(expr_lit) if expr_lit == pat_lit)
-        }
-        Pattern::Tuple(patterns) => {
-            if let Expression::Tuple(exprs) = expr {
-                if patterns.len() != exprs.len() {
-                    return false;
-                }
-                patterns.iter().zip(exprs.iter()).all(|(p, e)| {
-                    match_pattern_internal(e, p, bindings)
-                })
-            } else {
-                false
-            }
-        }
-        Pattern::List(patterns) => {
-            if let Expression::List(exprs) = expr {
-                if patterns.len() != exprs.len() {
-                    return false;
-                }
-                patterns.iter().zip(exprs.iter()).all(|(p, e)| {
-                    match_pattern_internal(e, p, bindings)
-                })
-            } else {
-                false
-            }
-        }
-        _ => false, // Other patterns not implemented
-    }
+// Pattern matching
+fn match_pattern(expr: &Expression, pattern: &Pattern) -> Vec<Bindings> {
+    glyph_engine::pattern::match_pattern(expr, pattern)
 }
 
-// Substitution
-fn apply_bindings(expr: &Expression, bindings: &HashMap<String, Expression>) -> Expression {
-    match expr {
-        Expression::Var(name) => {
-            bindings.get(name).cloned().unwrap_or_else(|| expr.clone())
-        }
-        Expression::Literal(_) => expr.clone(),
-        Expression::Apply { func, arg } => Expression::Apply {
-            func: Box::new(apply_bindings(func, bindings)),
-            arg: Box::new(apply_bindings(arg, bindings)),
-        },
-        Expression::Lambda { param, body } => {
-            if bindings.contains_key(param) {
-                expr.clone() // Don't substitute bound variables
-            } else {
-                Expression::Lambda {
-                    param: param.clone(),
-                    body: Box::new(apply_bindings(body, bindings)),
-                }
-            }
-        }
-        Expression::Tuple(exprs) => Expression::Tuple(
-            exprs.iter().map(|e| apply_bindings(e, bindings)).collect()
-        ),
-        Expression::List(exprs) => Expression::List(
-            exprs.iter().map(|e| apply_bindings(e, bindings)).collect()
-        ),
-        _ => expr.clone(),
-    }
+// Capture-avoiding instantiation of a rule's replacement. Pattern variables
+// are replaced where they occur as expressions; binder names in the
+// replacement are taken literally.
+fn apply_bindings(expr: &Expression, bindings: &Bindings) -> Expression {
+    let substitutions: HashMap<String, Expression> = bindings
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    substitute_many(expr, &substitutions)
 }
 
 // Condition evaluation
-fn evaluate_condition(_condition: &Expression, _bindings: &HashMap<String, Expression>) -> bool {
-    true // Simplified - always pass
+fn evaluate_condition(
+    rule_id: &str,
+    condition: &Expression,
+    bindings: &Bindings,
+) -> Result<bool, RuntimeError> {
+    condition::evaluate_condition(condition, bindings).map_err(|source| {
+        RuntimeError::ConditionFailed {
+            rule_id: rule_id.to_string(),
+            source,
+        }
+    })
 }
 
 // ============================================================================
@@ -956,6 +946,54 @@ mod tests {
         assert!(state.rules_fired > 0);
     }
 
+    #[test]
+    fn test_graph_hash_is_the_genesis_graph_merkle_root() {
+        let mut graph = GenesisGraph::new(create_test_root()).unwrap();
+        let node = GraphNode {
+            id: "counter".to_string(),
+            root_ref: graph.root_hash().clone(),
+            data: int(0),
+            metadata: NodeMetadata {
+                timestamp: 1,
+                lineage_depth: 1,
+                tags: vec![],
+            },
+        };
+        let hash = graph.insert_node(node).unwrap();
+        let engine = GenesisEngine::new(graph);
+
+        let rule = Rule::new(
+            "zero_to_one".to_string(),
+            10,
+            Pattern::Literal(Literal::Int(0)),
+            int(1),
+        );
+        let state = engine.evaluate(&[rule]).unwrap();
+        let graph = engine.graph();
+        let root = graph.merkle_root();
+        assert_eq!(state.graph_hash, root);
+
+        // The same content held by genesis_graph commits to the same root
+        let mut committed =
+            genesis_graph::GenesisGraph::new((&create_test_root()).into()).unwrap();
+        let rewritten_node = graph.committed_node(&hash).unwrap();
+        assert_eq!(rewritten_node.data, int(1));
+        assert_eq!(&rewritten_node.root_ref, committed.root_hash());
+        committed.insert_node(rewritten_node.clone()).unwrap();
+        assert_eq!(committed.merkle_root(), root);
+
+        // Proofs are for the node's current content, under its stored hash
+        let proof = graph.prove_node(&hash).unwrap();
+        assert!(genesis_graph::verify_node_proof(&root, &rewritten_node, &proof));
+        let mut stale = rewritten_node.clone();
+        stale.data = int(0);
+        assert!(!genesis_graph::verify_node_proof(&root, &stale, &proof));
+        assert!(matches!(
+            graph.prove_node(&"00".repeat(32)),
+            Err(RuntimeError::NodeNotFound(_))
+        ));
+    }
+
     #[test]
     fn test_iterative_evaluation() {
         let root = create_test_root();
@@ -1590,6 +1628,277 @@ mod tests {
         assert!(state.is_idle);
     }
 
+    fn call(name: &str, args: Vec<Expression>) -> Expression {
+        args.into_iter().fold(var(name), |func, arg| Expression::Apply {
+            func: Box::new(func),
+            arg: Box::new(arg),
+        })
+    }
+
+    fn graph_with_data(data: Vec<Expression>) -> GenesisGraph {
+        let mut graph = GenesisGraph::new(create_test_root()).unwrap();
+        for (i, data) in data.into_iter().enumerate() {
+            let node = GraphNode {
+                id: format!("node_{}", i),
+                root_ref: graph.root_hash().clone(),
+                data,
+                metadata: NodeMetadata {
+                    timestamp: current_timestamp(),
+                    lineage_depth: 1,
+                    tags: vec![],
+                },
+            };
+            graph.insert_node(node).unwrap();
+        }
+        graph
+    }
+
+    #[test]
+    fn test_conditional_rule() {
+        let engine = GenesisEngine::new(graph_with_data(vec![
+            Expression::Tuple(vec![int(3), int(3)]),
+            Expression::Tuple(vec![int(3), int(4)]),
+        ]));
+
+        // (x, y) -> x, but only when both sides are equal and small
+        let rule = Rule::new(
+            "collapse_pair".to_string(),
+            10,
+            Pattern::Tuple(vec![
+                Pattern::Var("x".to_string()),
+                Pattern::Var("y".to_string()),
+            ]),
+            var("x"),
+        )
+        .with_condition(call(
+            "and",
+            vec![
+                call("eq", vec![var("x"), var("y")]),
+                call("lt", vec![var("x"), int(10)]),
+            ],
+        ));
+
+        let state = engine.evaluate(&[rule]).unwrap();
+        assert_eq!(state.rules_fired, 1);
+
+        let graph = engine.graph();
+        let data: Vec<_> = graph.nodes_sorted_by_id().into_iter()
+            .filter(|(_, n)| n.id.starts_with("node_"))
+            .map(|(_, n)| n.data.clone())
+            .collect();
+        assert_eq!(data, vec![int(3), Expression::Tuple(vec![int(3), int(4)])]);
+    }
+
+    fn run_one(data: Expression, rule: Rule) -> Expression {
+        let engine = GenesisEngine::new(graph_with_data(vec![data]));
+        engine.evaluate(&[rule]).unwrap();
+        let graph = engine.graph();
+        let (_, node) = graph.nodes_sorted_by_id().into_iter()
+            .find(|(_, n)| n.id == "node_0")
+            .unwrap();
+        node.data.clone()
+    }
+
+    fn lambda(param: &str, body: Expression) -> Expression {
+        Expression::Lambda {
+            param: param.to_string(),
+            body: Box::new(body),
+        }
+    }
+
+    #[test]
+    fn test_full_pattern_language_fires() {
+        // Some(x) -> x, through a constructor pattern
+        let rule = Rule::new(
+            "unwrap".to_string(),
+            10,
+            Pattern::Constructor {
+                name: "Some".to_string(),
+                args: vec![Pattern::Var("x".to_string())],
+            },
+            var("x"),
+        );
+        assert_eq!(run_one(call("Some", vec![int(4)]), rule), int(4));
+
+        // { a: x, .. } -> x, binding the whole record too
+        let rule = Rule::new(
+            "field".to_string(),
+            10,
+            Pattern::Bind {
+                name: "r".to_string(),
+                pattern: Box::new(Pattern::Record(vec![(
+                    "a".to_string(),
+                    Pattern::Var("x".to_string()),
+                )])),
+            },
+            Expression::Tuple(vec![var("x"), var("r")]),
+        );
+        let record = Expression::Record(vec![
+            ("a".to_string(), int(1)),
+            ("b".to_string(), int(2)),
+        ]);
+        assert_eq!(
+            run_one(record.clone(), rule),
+            Expression::Tuple(vec![int(1), record])
+        );
+
+        // (λp. b) a -> let g = λp. b in g a, through lambda, apply and bind
+        // patterns. Instantiation substitutes pattern variables only where
+        // they occur as expressions, never as binders, so a template binds
+        // its own names rather than reusing a matched parameter
+        let rule = Rule::new(
+            "name_redex".to_string(),
+            10,
+            Pattern::Apply {
+                func_pattern: Box::new(Pattern::Bind {
+                    name: "f".to_string(),
+                    pattern: Box::new(Pattern::Lambda {
+                        param_pattern: Box::new(Pattern::Var("p".to_string())),
+                        body_pattern: Box::new(Pattern::Var("b".to_string())),
+                    }),
+                }),
+                arg_pattern: Box::new(Pattern::Var("a".to_string())),
+            },
+            Expression::Let {
+                name: "g".to_string(),
+                value: Box::new(var("f")),
+                body: Box::new(call("g", vec![var("a")])),
+            },
+        );
+        let redex = Expression::Apply {
+            func: Box::new(lambda("y", var("y"))),
+            arg: Box::new(int(3)),
+        };
+        assert_eq!(
+            run_one(redex, rule),
+            Expression::Let {
+                name: "g".to_string(),
+                value: Box::new(lambda("y", var("y"))),
+                body: Box::new(call("g", vec![int(3)])),
+            }
+        );
+    }
+
+    #[test]
+    fn test_substitution_avoids_capture() {
+        // f a -> λy. f a, where `a` is bound to the free variable y
+        let rule = Rule::new(
+            "eta_expand".to_string(),
+            10,
+            Pattern::Apply {
+                func_pattern: Box::new(Pattern::Var("f".to_string())),
+                arg_pattern: Box::new(Pattern::Var("a".to_string())),
+            },
+            lambda("y", call("f", vec![var("a")])),
+        );
+        let result = run_one(call("g", vec![var("y")]), rule);
+
+        match result {
+            Expression::Lambda { param, body } => {
+                assert_ne!(param, "y");
+                assert_eq!(*body, call("g", vec![var("y")]));
+            }
+            other => panic!("expected a lambda, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_strategy_rewrites_nested_redexes() {
+        let negation = |p: Pattern| Pattern::Constructor { name: "not".to_string(), args: vec![p] };
+        let rules = vec![
+            Rule::new(
+                "double_negation".to_string(),
+                10,
+                negation(negation(Pattern::Var("x".to_string()))),
+                var("x"),
+            ),
+            Rule::new(
+                "and_true".to_string(),
+                10,
+                Pattern::Constructor {
+                    name: "and".to_string(),
+                    args: vec![Pattern::Literal(Literal::Bool(true)), Pattern::Var("b".to_string())],
+                },
+                var("b"),
+            ),
+        ];
+        let data = lambda("x", Expression::List(vec![
+            call("not", vec![call("not", vec![var("x")])]),
+            call("and", vec![Expression::Literal(Literal::Bool(true)), var("y")]),
+        ]));
+
+        let run = |strategy: RewriteStrategy| {
+            let engine = GenesisEngine::with_config(
+                graph_with_data(vec![data.clone()]),
+                RuntimeConfig { strategy, ..RuntimeConfig::default() },
+            );
+            engine.evaluate(&rules).unwrap();
+            let result = engine.graph().nodes_sorted_by_id().into_iter()
+                .find(|(_, n)| n.id == "node_0")
+                .map(|(_, n)| n.data.clone())
+                .unwrap();
+            let applications: Vec<(String, Position, usize)> = engine.transaction_log()
+                .applications()
+                .iter()
+                .map(|a| (a.rule_id.clone(), a.position.clone(), a.iteration))
+                .collect();
+            (result, applications)
+        };
+
+        // The default only ever matches the whole term
+        assert_eq!(run(RewriteStrategy::Root), (data.clone(), vec![]));
+
+        let normal_form = lambda("x", Expression::List(vec![var("x"), var("y")]));
+        assert_eq!(
+            run(RewriteStrategy::LeftmostInnermost),
+            (normal_form.clone(), vec![
+                ("double_negation".to_string(), vec![0, 0], 1),
+                ("and_true".to_string(), vec![0, 1], 2),
+            ])
+        );
+        assert_eq!(
+            run(RewriteStrategy::ParallelOutermost),
+            (normal_form, vec![
+                ("double_negation".to_string(), vec![0, 0], 1),
+                ("and_true".to_string(), vec![0, 1], 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_failing_condition_is_an_error() {
+        for parallel_matching in [false, true] {
+            let engine = GenesisEngine::with_config(
+                graph_with_data(vec![int(5)]),
+                RuntimeConfig {
+                    parallel_matching,
+                    ..RuntimeConfig::default()
+                },
+            );
+
+            // Conditions must produce a boolean
+            let rule = Rule::new("non_bool".to_string(), 10, Pattern::Var("n".to_string()), int(0))
+                .with_condition(call("add", vec![var("n"), int(1)]));
+            match engine.evaluate(&[rule]) {
+                Err(RuntimeError::ConditionFailed { rule_id, source }) => {
+                    assert_eq!(rule_id, "non_bool");
+                    assert_eq!(source, ConditionError::NotBoolean(int(6)));
+                }
+                other => panic!("expected a condition failure, got {:?}", other),
+            }
+
+            let rule = Rule::new("unbound".to_string(), 10, Pattern::Var("n".to_string()), int(0))
+                .with_condition(call("eq", vec![var("m"), int(1)]));
+            assert!(matches!(
+                engine.evaluate(&[rule]),
+                Err(RuntimeError::ConditionFailed {
+                    source: ConditionError::UnboundVariable(_),
+                    ..
+                })
+            ));
+        }
+    }
+
     #[test]
     fn test_comprehensive_runtime_loop() {
         println!("\n=== Comprehensive Runtime Loop Test ===");
@@ -0,0 +1,480 @@
+// ============================================================================
+// Rewriting Strategies
+// ============================================================================
+//
+// Where in a node's expression the engine looks for redexes. A strategy
+// walks the term and asks a step function to rewrite each subterm it
+// visits; the step answers `None` when no rule makes progress there.
+//
+// Subterms are addressed by position paths: the child indices taken from
+// the root, so `[]` is the whole term and `[1, 0]` the first child of its
+// second child. Children are numbered in source order:
+//
+//   Lambda                 body
+//   Apply, LinearApply     func, arg
+//   Let                    value, body
+//   Match                  scrutinee, then each arm's guard (if any) and body
+//   Tuple, List            elements
+//   Record                 field values
+
+use glyph_ast::{Expression, MatchArm};
+use serde::{Deserialize, Serialize};
+
+pub type Position = Vec<usize>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RewriteStrategy {
+    /// Rewrite the whole term only; subterms are never visited.
+    #[default]
+    Root,
+    /// One rewrite at the leftmost redex that contains no other redex.
+    LeftmostInnermost,
+    /// One rewrite at the leftmost redex not contained in another redex.
+    LeftmostOutermost,
+    /// Rewrite every outermost redex at once.
+    ParallelOutermost,
+    /// One pre-order pass: try each subterm once, then descend into the
+    /// result's children.
+    TopDownOnce,
+    /// One post-order pass: rewrite the children first, then try the
+    /// rebuilt term once.
+    BottomUp,
+}
+
+/// A step taken by `rewrite`: what the step function reported, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rewrite<T> {
+    pub position: Position,
+    pub tag: T,
+}
+
+/// Apply one step of `strategy` to `term`. `step` returns the replacement
+/// for a subterm together with a tag (such as the rule that fired), or
+/// `None` if the subterm is not a redex. Returns the new term and the
+/// rewrites performed, in the order they were made.
+pub fn rewrite<T, E, F>(
+    term: &Expression,
+    strategy: RewriteStrategy,
+    mut step: F,
+) -> Result<(Expression, Vec<Rewrite<T>>), E>
+where
+    F: FnMut(&Expression) -> Result<Option<(T, Expression)>, E>,
+{
+    let mut path = Vec::new();
+    let mut rewrites = Vec::new();
+    let result = match strategy {
+        RewriteStrategy::Root => match step(term)? {
+            Some((tag, new_term)) => {
+                rewrites.push(Rewrite {
+                    position: Vec::new(),
+                    tag,
+                });
+                new_term
+            }
+            None => term.clone(),
+        },
+        RewriteStrategy::LeftmostInnermost | RewriteStrategy::LeftmostOutermost => {
+            let innermost = strategy == RewriteStrategy::LeftmostInnermost;
+            match rewrite_once(term, innermost, &mut path, &mut step)? {
+                Some((new_term, rewrite)) => {
+                    rewrites.push(rewrite);
+                    new_term
+                }
+                None => term.clone(),
+            }
+        }
+        RewriteStrategy::ParallelOutermost => {
+            parallel_outermost(term, &mut path, &mut step, &mut rewrites)?
+        }
+        RewriteStrategy::TopDownOnce => top_down(term, &mut path, &mut step, &mut rewrites)?,
+        RewriteStrategy::BottomUp => bottom_up(term, &mut path, &mut step, &mut rewrites)?,
+    };
+    Ok((result, rewrites))
+}
+
+/// The immediate subterms of `expr`, in position order.
+pub fn children(expr: &Expression) -> Vec<&Expression> {
+    match expr {
+        Expression::Literal(_) | Expression::Var(_) => Vec::new(),
+        Expression::Lambda { body, .. } => vec![body],
+        Expression::Apply { func, arg } | Expression::LinearApply { func, arg } => {
+            vec![func, arg]
+        }
+        Expression::Let { value, body, .. } => vec![value, body],
+        Expression::Match { expr, arms } => {
+            let mut children = vec![expr.as_ref()];
+            for arm in arms {
+                children.extend(arm.guard.as_deref());
+                children.push(&arm.body);
+            }
+            children
+        }
+        Expression::Tuple(items) | Expression::List(items) => items.iter().collect(),
+        Expression::Record(fields) => fields.iter().map(|(_, v)| v).collect(),
+    }
+}
+
+/// The subterm of `expr` at `position`, if the path exists.
+pub fn subterm_at<'a>(expr: &'a Expression, position: &[usize]) -> Option<&'a Expression> {
+    position.iter().try_fold(expr, |current, &index| {
+        children(current).get(index).copied()
+    })
+}
+
+// ============================================================================
+// Traversals
+// ============================================================================
+
+fn rewrite_once<T, E, F>(
+    term: &Expression,
+    innermost: bool,
+    path: &mut Position,
+    step: &mut F,
+) -> Result<Option<(Expression, Rewrite<T>)>, E>
+where
+    F: FnMut(&Expression) -> Result<Option<(T, Expression)>, E>,
+{
+    if !innermost {
+        if let Some(found) = step_at(term, path, step)? {
+            return Ok(Some(found));
+        }
+    }
+
+    for (index, child) in children(term).into_iter().enumerate() {
+        path.push(index);
+        let found = rewrite_once(child, innermost, path, step)?;
+        path.pop();
+        if let Some((new_child, rewrite)) = found {
+            let rebuilt = map_children(term, |i, c| {
+                Ok::<_, E>(if i == index {
+                    new_child.clone()
+                } else {
+                    c.clone()
+                })
+            })?;
+            return Ok(Some((rebuilt, rewrite)));
+        }
+    }
+
+    if innermost {
+        return step_at(term, path, step);
+    }
+    Ok(None)
+}
+
+fn step_at<T, E, F>(
+    term: &Expression,
+    path: &Position,
+    step: &mut F,
+) -> Result<Option<(Expression, Rewrite<T>)>, E>
+where
+    F: FnMut(&Expression) -> Result<Option<(T, Expression)>, E>,
+{
+    Ok(step(term)?.map(|(tag, new_term)| {
+        let rewrite = Rewrite {
+            position: path.clone(),
+            tag,
+        };
+        (new_term, rewrite)
+    }))
+}
+
+fn parallel_outermost<T, E, F>(
+    term: &Expression,
+    path: &mut Position,
+    step: &mut F,
+    rewrites: &mut Vec<Rewrite<T>>,
+) -> Result<Expression, E>
+where
+    F: FnMut(&Expression) -> Result<Option<(T, Expression)>, E>,
+{
+    if let Some(new_term) = try_step(term, path, step, rewrites)? {
+        return Ok(new_term);
+    }
+    map_children(term, |index, child| {
+        path.push(index);
+        let result = parallel_outermost(child, path, step, rewrites);
+        path.pop();
+        result
+    })
+}
+
+fn top_down<T, E, F>(
+    term: &Expression,
+    path: &mut Position,
+    step: &mut F,
+    rewrites: &mut Vec<Rewrite<T>>,
+) -> Result<Expression, E>
+where
+    F: FnMut(&Expression) -> Result<Option<(T, Expression)>, E>,
+{
+    let term = try_step(term, path, step, rewrites)?.unwrap_or_else(|| term.clone());
+    map_children(&term, |index, child| {
+        path.push(index);
+        let result = top_down(child, path, step, rewrites);
+        path.pop();
+        result
+    })
+}
+
+fn bottom_up<T, E, F>(
+    term: &Expression,
+    path: &mut Position,
+    step: &mut F,
+    rewrites: &mut Vec<Rewrite<T>>,
+) -> Result<Expression, E>
+where
+    F: FnMut(&Expression) -> Result<Option<(T, Expression)>, E>,
+{
+    let term = map_children(term, |index, child| {
+        path.push(index);
+        let result = bottom_up(child, path, step, rewrites);
+        path.pop();
+        result
+    })?;
+    Ok(try_step(&term, path, step, rewrites)?.unwrap_or(term))
+}
+
+fn try_step<T, E, F>(
+    term: &Expression,
+    path: &Position,
+    step: &mut F,
+    rewrites: &mut Vec<Rewrite<T>>,
+) -> Result<Option<Expression>, E>
+where
+    F: FnMut(&Expression) -> Result<Option<(T, Expression)>, E>,
+{
+    Ok(step(term)?.map(|(tag, new_term)| {
+        rewrites.push(Rewrite {
+            position: path.clone(),
+            tag,
+        });
+        new_term
+    }))
+}
+
+/// Rebuild `expr` with each child replaced by `f(index, child)`.
+fn map_children<E>(
+    expr: &Expression,
+    mut f: impl FnMut(usize, &Expression) -> Result<Expression, E>,
+) -> Result<Expression, E> {
+    let mut index = 0;
+    let mut next = |child: &Expression| {
+        let result = f(index, child).map(Box::new);
+        index += 1;
+        result
+    };
+
+    Ok(match expr {
+        Expression::Literal(_) | Expression::Var(_) => expr.clone(),
+        Expression::Lambda { param, body } => Expression::Lambda {
+            param: param.clone(),
+            body: next(body)?,
+        },
+        Expression::Apply { func, arg } => Expression::Apply {
+            func: next(func)?,
+            arg: next(arg)?,
+        },
+        Expression::LinearApply { func, arg } => Expression::LinearApply {
+            func: next(func)?,
+            arg: next(arg)?,
+        },
+        Expression::Let { name, value, body } => Expression::Let {
+            name: name.clone(),
+            value: next(value)?,
+            body: next(body)?,
+        },
+        Expression::Match { expr, arms } => {
+            let expr = next(expr)?;
+            let mut new_arms = Vec::with_capacity(arms.len());
+            for arm in arms {
+                new_arms.push(MatchArm {
+                    pattern: arm.pattern.clone(),
+                    guard: arm.guard.as_deref().map(&mut next).transpose()?,
+                    body: next(&arm.body)?,
+                });
+            }
+            Expression::Match {
+                expr,
+                arms: new_arms,
+            }
+        }
+        Expression::Tuple(items) => Expression::Tuple(
+            items
+                .iter()
+                .map(|item| next(item).map(|b| *b))
+                .collect::<Result<_, E>>()?,
+        ),
+        Expression::List(items) => Expression::List(
+            items
+                .iter()
+                .map(|item| next(item).map(|b| *b))
+                .collect::<Result<_, E>>()?,
+        ),
+        Expression::Record(fields) => Expression::Record(
+            fields
+                .iter()
+                .map(|(k, v)| next(v).map(|b| (k.clone(), *b)))
+                .collect::<Result<_, E>>()?,
+        ),
+    })
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glyph_ast::Literal;
+
+    fn int(n: i64) -> Expression {
+        Expression::Literal(Literal::Int(n))
+    }
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(name.to_string())
+    }
+
+    fn call(name: &str, args: Vec<Expression>) -> Expression {
+        args.into_iter()
+            .fold(var(name), |func, arg| Expression::Apply {
+                func: Box::new(func),
+                arg: Box::new(arg),
+            })
+    }
+
+    /// `add` on two literals folds, `fst [a, ..]` projects, and `pair`
+    /// unfolds into a list of two redexes.
+    fn step(expr: &Expression) -> Result<Option<(&'static str, Expression)>, ()> {
+        let pair = || {
+            Expression::List(vec![
+                call("add", vec![int(1), int(2)]),
+                call("add", vec![int(3), int(4)]),
+            ])
+        };
+        Ok(match expr {
+            Expression::Var(name) if name == "pair" => Some(("pair", pair())),
+            Expression::Apply { func, arg } => match (func.as_ref(), arg.as_ref()) {
+                (Expression::Var(f), Expression::List(items)) if f == "fst" => {
+                    items.first().map(|first| ("fst", first.clone()))
+                }
+                (Expression::Apply { func, arg: lhs }, Expression::Literal(Literal::Int(b))) => {
+                    match (func.as_ref(), lhs.as_ref()) {
+                        (Expression::Var(f), Expression::Literal(Literal::Int(a)))
+                            if f == "add" =>
+                        {
+                            Some(("add", int(a + b)))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    fn run(term: &Expression, strategy: RewriteStrategy) -> (Expression, Vec<Position>) {
+        let (result, rewrites) = rewrite(term, strategy, step).unwrap();
+        (result, rewrites.into_iter().map(|r| r.position).collect())
+    }
+
+    #[test]
+    fn test_innermost_and_outermost_pick_different_redexes() {
+        let term = call(
+            "fst",
+            vec![Expression::List(vec![
+                call("add", vec![int(1), int(2)]),
+                int(9),
+            ])],
+        );
+
+        assert_eq!(
+            run(&term, RewriteStrategy::LeftmostOutermost),
+            (call("add", vec![int(1), int(2)]), vec![vec![]])
+        );
+        assert_eq!(
+            run(&term, RewriteStrategy::Root),
+            (call("add", vec![int(1), int(2)]), vec![vec![]])
+        );
+        assert_eq!(
+            run(&term, RewriteStrategy::LeftmostInnermost),
+            (
+                call("fst", vec![Expression::List(vec![int(3), int(9)])]),
+                vec![vec![1, 0]]
+            )
+        );
+        assert_eq!(
+            subterm_at(&term, &[1, 0]),
+            Some(&call("add", vec![int(1), int(2)]))
+        );
+        assert_eq!(subterm_at(&term, &[2]), None);
+    }
+
+    #[test]
+    fn test_traversals_rewrite_each_position_once() {
+        let nested = call("add", vec![call("add", vec![int(1), int(1)]), int(5)]);
+        let term = Expression::List(vec![nested, var("pair")]);
+        let unfolded = || {
+            Expression::List(vec![
+                call("add", vec![int(1), int(2)]),
+                call("add", vec![int(3), int(4)]),
+            ])
+        };
+
+        // No redex at the root, so the root strategy leaves the term alone
+        assert_eq!(run(&term, RewriteStrategy::Root), (term.clone(), vec![]));
+
+        assert_eq!(
+            run(&term, RewriteStrategy::ParallelOutermost),
+            (
+                Expression::List(vec![call("add", vec![int(2), int(5)]), unfolded()]),
+                vec![vec![0, 0, 1], vec![1]]
+            )
+        );
+        // Top-down descends into what `pair` unfolded to
+        assert_eq!(
+            run(&term, RewriteStrategy::TopDownOnce),
+            (
+                Expression::List(vec![
+                    call("add", vec![int(2), int(5)]),
+                    Expression::List(vec![int(3), int(7)]),
+                ]),
+                vec![vec![0, 0, 1], vec![1], vec![1, 0], vec![1, 1]]
+            )
+        );
+        // Bottom-up revisits the parent of a folded argument
+        assert_eq!(
+            run(&term, RewriteStrategy::BottomUp),
+            (
+                Expression::List(vec![int(7), unfolded()]),
+                vec![vec![0, 0, 1], vec![0], vec![1]]
+            )
+        );
+    }
+
+    #[test]
+    fn test_match_positions_include_guards() {
+        let term = Expression::Match {
+            expr: Box::new(var("pair")),
+            arms: vec![
+                MatchArm {
+                    pattern: glyph_ast::Pattern::Wildcard,
+                    guard: Some(Box::new(call("add", vec![int(1), int(1)]))),
+                    body: Box::new(int(0)),
+                },
+                MatchArm {
+                    pattern: glyph_ast::Pattern::Wildcard,
+                    guard: None,
+                    body: Box::new(call("add", vec![int(2), int(2)])),
+                },
+            ],
+        };
+
+        let (result, positions) = run(&term, RewriteStrategy::ParallelOutermost);
+        assert_eq!(positions, vec![vec![0], vec![1], vec![3]]);
+        assert_eq!(children(&result)[1..], [&int(2), &int(0), &int(4)]);
+    }
+}
@@ -0,0 +1,259 @@
+// ============================================================================
+// Adjacency Indexes
+// ============================================================================
+//
+// Forward and reverse edge indexes keyed by node hash, then by edge type.
+// They are derived entirely from an edge list and are never serialized;
+// `GenesisGraph` and `GraphStore` rebuild them when they load and keep them
+// in step with every link and delete.
+//
+// Parallel edges (same endpoints and type) are allowed by the graph, so each
+// edge contributes its own entry and degrees count edges, not neighbours.
+// Every entry also records the edge's position in the edge list, so the edges
+// around a node can be dropped from that list without scanning it.
+
+use crate::{EdgeType, GraphEdge, Hash};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// Node -> edge type -> (neighbour, position of the edge in the edge list).
+type Index = HashMap<Hash, BTreeMap<EdgeType, Vec<(Hash, usize)>>>;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Adjacency {
+    outgoing: Index,
+    incoming: Index,
+}
+
+impl Adjacency {
+    pub(crate) fn from_edges(edges: &[GraphEdge]) -> Self {
+        let mut adjacency = Self::default();
+        for (position, edge) in edges.iter().enumerate() {
+            adjacency.insert(edge, position);
+        }
+        adjacency
+    }
+
+    /// Append `edge` to `edges` and index it.
+    pub(crate) fn link(&mut self, edges: &mut Vec<GraphEdge>, edge: GraphEdge) {
+        self.insert(&edge, edges.len());
+        edges.push(edge);
+    }
+
+    /// Drop every edge into or out of `hash` from the indexes and from
+    /// `edges`, returning how many there were. Removed edges are
+    /// swap-removed, so this costs time proportional to the edges around
+    /// `hash` rather than to `edges`, and does not keep `edges` in order.
+    pub(crate) fn unlink_node(&mut self, edges: &mut Vec<GraphEdge>, hash: &Hash) -> usize {
+        let positions = self.remove_node(hash);
+        // Highest first, so the edge moved into each hole is one that stays
+        for &position in positions.iter().rev() {
+            edges.swap_remove(position);
+            if let Some(moved) = edges.get(position) {
+                self.relocate(moved, edges.len(), position);
+            }
+        }
+        positions.len()
+    }
+
+    fn insert(&mut self, edge: &GraphEdge, position: usize) {
+        push(
+            &mut self.outgoing,
+            &edge.from,
+            edge.edge_type,
+            &edge.to,
+            position,
+        );
+        push(
+            &mut self.incoming,
+            &edge.to,
+            edge.edge_type,
+            &edge.from,
+            position,
+        );
+    }
+
+    /// Unindex every edge into or out of `hash`, returning their positions
+    /// in ascending order. A self-loop is both, but is listed once.
+    fn remove_node(&mut self, hash: &Hash) -> Vec<usize> {
+        let outgoing = self.outgoing.remove(hash).unwrap_or_default();
+        let incoming = self.incoming.remove(hash).unwrap_or_default();
+        let mut positions = Vec::new();
+
+        for (edge_type, targets) in outgoing {
+            for (target, position) in targets {
+                remove_position(&mut self.incoming, &target, edge_type, position);
+                positions.push(position);
+            }
+        }
+        for (edge_type, sources) in incoming {
+            for (source, position) in sources {
+                remove_position(&mut self.outgoing, &source, edge_type, position);
+                positions.push(position);
+            }
+        }
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    }
+
+    /// Record that `edge` moved from position `from` to `to`.
+    fn relocate(&mut self, edge: &GraphEdge, from: usize, to: usize) {
+        for (index, key) in [
+            (&mut self.outgoing, &edge.from),
+            (&mut self.incoming, &edge.to),
+        ] {
+            let entry = index
+                .get_mut(key)
+                .and_then(|by_type| by_type.get_mut(&edge.edge_type))
+                .and_then(|list| list.iter_mut().find(|(_, p)| *p == from))
+                .expect("every edge is indexed");
+            entry.1 = to;
+        }
+    }
+
+    pub(crate) fn successors<'a>(
+        &'a self,
+        hash: &Hash,
+        edge_type: Option<EdgeType>,
+    ) -> impl Iterator<Item = &'a Hash> + 'a {
+        neighbours(&self.outgoing, hash, edge_type)
+    }
+
+    pub(crate) fn predecessors<'a>(
+        &'a self,
+        hash: &Hash,
+        edge_type: Option<EdgeType>,
+    ) -> impl Iterator<Item = &'a Hash> + 'a {
+        neighbours(&self.incoming, hash, edge_type)
+    }
+
+    pub(crate) fn out_degree(&self, hash: &Hash, edge_type: Option<EdgeType>) -> usize {
+        degree(&self.outgoing, hash, edge_type)
+    }
+
+    pub(crate) fn in_degree(&self, hash: &Hash, edge_type: Option<EdgeType>) -> usize {
+        degree(&self.incoming, hash, edge_type)
+    }
+
+    /// The cycle an edge `from -> to` would close in an acyclic graph, if
+    /// any: `from`, then a shortest path from `to` back to `from`.
+    pub(crate) fn cycle_through(&self, from: &Hash, to: &Hash) -> Option<Vec<Hash>> {
+        let mut cycle = vec![from.clone()];
+        cycle.extend(self.path(to, from)?);
+        Some(cycle)
+    }
+
+    /// A shortest path from `start` to `target` along edges of any type,
+    /// both ends included. Only nodes reachable from `start` are visited.
+    pub(crate) fn path(&self, start: &Hash, target: &Hash) -> Option<Vec<Hash>> {
+        let mut parent: HashMap<&Hash, &Hash> = HashMap::new();
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            if current == target {
+                let mut path = vec![current.clone()];
+                let mut node = current;
+                while let Some(&p) = parent.get(node) {
+                    path.push(p.clone());
+                    node = p;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for next in self.successors(current, None) {
+                if visited.insert(next) {
+                    parent.insert(next, current);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+fn push(index: &mut Index, key: &Hash, edge_type: EdgeType, value: &Hash, position: usize) {
+    index
+        .entry(key.clone())
+        .or_default()
+        .entry(edge_type)
+        .or_default()
+        .push((value.clone(), position));
+}
+
+fn remove_position(index: &mut Index, key: &Hash, edge_type: EdgeType, position: usize) {
+    remove_where(index, key, edge_type, |list| {
+        list.retain(|(_, p)| *p != position)
+    });
+}
+
+/// Edit one list in place, pruning the list and the node's entry once empty.
+fn remove_where(
+    index: &mut Index,
+    key: &Hash,
+    edge_type: EdgeType,
+    edit: impl FnOnce(&mut Vec<(Hash, usize)>),
+) {
+    let Some(by_type) = index.get_mut(key) else {
+        return;
+    };
+    if let Some(list) = by_type.get_mut(&edge_type) {
+        edit(list);
+        if list.is_empty() {
+            by_type.remove(&edge_type);
+        }
+    }
+    if by_type.is_empty() {
+        index.remove(key);
+    }
+}
+
+fn neighbours<'a>(
+    index: &'a Index,
+    hash: &Hash,
+    edge_type: Option<EdgeType>,
+) -> impl Iterator<Item = &'a Hash> + 'a {
+    index
+        .get(hash)
+        .into_iter()
+        .flat_map(move |by_type| {
+            by_type
+                .iter()
+                .filter(move |(t, _)| edge_type.is_none_or(|wanted| **t == wanted))
+        })
+        .flat_map(|(_, entries)| entries.iter().map(|(hash, _)| hash))
+}
+
+fn degree(index: &Index, hash: &Hash, edge_type: Option<EdgeType>) -> usize {
+    neighbours(index, hash, edge_type).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(from: &str, to: &str) -> GraphEdge {
+        GraphEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            edge_type: EdgeType::Dependency,
+        }
+    }
+
+    #[test]
+    fn test_unlink_node_with_self_loop() {
+        let mut edges = vec![edge("a", "b"), edge("b", "b"), edge("c", "d")];
+        let mut adjacency = Adjacency::from_edges(&edges);
+
+        assert_eq!(adjacency.unlink_node(&mut edges, &"b".to_string()), 2);
+        assert_eq!(edges, vec![edge("c", "d")]);
+        assert_eq!(adjacency.out_degree(&"a".to_string(), None), 0);
+        assert_eq!(
+            adjacency
+                .successors(&"c".to_string(), None)
+                .collect::<Vec<_>>(),
+            vec!["d"]
+        );
+        assert_eq!(adjacency.unlink_node(&mut edges, &"c".to_string()), 1);
+        assert!(edges.is_empty());
+    }
+}
@@ -0,0 +1,539 @@
+// ============================================================================
+// Signed Subgraph Bundles
+// ============================================================================
+//
+// A bundle carries part of a graph between machines: the closure of some
+// entry nodes, the edges among them, and a manifest listing the hash of
+// every node and edge. The manifest's content hash is signed with a
+// capsule_core detached signature, so one signature covers the whole bundle
+// and each node and edge is then checked against the manifest. A manifest
+// lists each hash once, in order, so parallel edges (which hash alike)
+// cannot be bundled.
+//
+// Importing grafts the bundle onto another graph. Nodes are re-rooted onto
+// the target's root, which gives them new hashes there; the bundle's own
+// root node, if exported, is identified with the target's root. The import
+// is all-or-nothing: a cycle or a bad reference leaves the target unchanged.
+
+use crate::merkle::edge_leaf;
+use crate::{
+    compute_node_hash, compute_root_hash, EdgeType, GenesisGraph, GraphEdge, GraphError, GraphNode,
+    Hash,
+};
+use capsule_core::{
+    canonical_cbor, check_detached, compute_content_hash_with_prefix, key_id, sign_detached,
+    DetachedSignature,
+};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+const MANIFEST_PREFIX: &str = "BundleV1";
+
+/// Which nodes around the entries an export takes along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClosureKind {
+    /// Everything reachable along outgoing edges.
+    Edges,
+    /// Every ancestor, back to the root.
+    Lineage,
+    Both,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// Root of the graph the bundle was exported from.
+    pub source_root: Hash,
+    pub entries: Vec<Hash>,
+    /// Sorted node hashes.
+    pub nodes: Vec<Hash>,
+    /// Sorted edge hashes, see [`edge_hash`].
+    pub edges: Vec<Hash>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubgraphBundle {
+    pub manifest: BundleManifest,
+    /// In manifest order.
+    pub nodes: Vec<GraphNode>,
+    /// In manifest order.
+    pub edges: Vec<GraphEdge>,
+    pub signature: DetachedSignature,
+}
+
+/// What an import did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Key id of the bundle's signer.
+    pub signer: String,
+    /// Hash of each bundle node in the bundle -> its hash in the target.
+    pub mapping: HashMap<Hash, Hash>,
+    pub nodes_added: usize,
+    pub edges_added: usize,
+}
+
+/// An edge's leaf in the graph's Merkle tree, hex encoded.
+pub fn edge_hash(edge: &GraphEdge) -> Hash {
+    hex::encode(edge_leaf(edge))
+}
+
+impl BundleManifest {
+    /// The content hash the bundle signature covers.
+    pub fn content_hash(&self) -> Result<String, GraphError> {
+        Ok(compute_content_hash_with_prefix(
+            MANIFEST_PREFIX,
+            &canonical_cbor(self)?,
+        ))
+    }
+}
+
+impl SubgraphBundle {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GraphError> {
+        Ok(canonical_cbor(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, GraphError> {
+        ciborium::from_reader(bytes).map_err(|e| GraphError::SerializationError(e.to_string()))
+    }
+
+    /// Check the signature, then every node and edge against the manifest.
+    /// Returns the signer's public key.
+    pub fn verify(&self) -> Result<VerifyingKey, GraphError> {
+        let manifest = &self.manifest;
+        if self.signature.content_hash != manifest.content_hash()? {
+            return Err(GraphError::InvalidBundle(
+                "signature does not cover this manifest".to_string(),
+            ));
+        }
+        check_detached(&self.signature)?;
+
+        for (what, hashes) in [("node", &manifest.nodes), ("edge", &manifest.edges)] {
+            if !hashes.windows(2).all(|pair| pair[0] < pair[1]) {
+                return Err(GraphError::InvalidBundle(format!(
+                    "manifest {} hashes are not sorted and unique",
+                    what
+                )));
+            }
+        }
+
+        if self.nodes.len() != manifest.nodes.len() || self.edges.len() != manifest.edges.len() {
+            return Err(GraphError::InvalidBundle(
+                "bundle contents do not match the manifest".to_string(),
+            ));
+        }
+        for (node, expected) in self.nodes.iter().zip(&manifest.nodes) {
+            let actual = if node.root_ref.is_empty() {
+                compute_root_hash(node)
+            } else {
+                compute_node_hash(node)
+            };
+            if actual != *expected {
+                return Err(GraphError::InvalidBundle(format!(
+                    "node {} does not hash to {}",
+                    node.id, expected
+                )));
+            }
+            let root_ok = if node.root_ref.is_empty() {
+                actual == manifest.source_root
+            } else {
+                node.root_ref == manifest.source_root
+            };
+            if !root_ok {
+                return Err(GraphError::RootRefMismatch {
+                    expected: manifest.source_root.clone(),
+                    actual: node.root_ref.clone(),
+                });
+            }
+        }
+        for (edge, expected) in self.edges.iter().zip(&manifest.edges) {
+            if edge_hash(edge) != *expected {
+                return Err(GraphError::InvalidBundle(format!(
+                    "edge {} -> {} does not hash to {}",
+                    edge.from, edge.to, expected
+                )));
+            }
+        }
+
+        // Every reference must resolve inside the bundle
+        let nodes: HashSet<&Hash> = manifest.nodes.iter().collect();
+        for hash in manifest
+            .entries
+            .iter()
+            .chain(self.edges.iter().flat_map(|e| [&e.from, &e.to]))
+        {
+            if !nodes.contains(hash) {
+                return Err(GraphError::DanglingReference(hash.clone()));
+            }
+        }
+
+        VerifyingKey::from_bytes(&self.signature.public_key)
+            .map_err(|e| GraphError::InvalidBundle(e.to_string()))
+    }
+}
+
+// ============================================================================
+// Export and Import
+// ============================================================================
+
+impl GenesisGraph {
+    /// Bundle the closure of `entries`, signed with `keypair`.
+    pub fn export_bundle(
+        &self,
+        entries: &[Hash],
+        closure: ClosureKind,
+        keypair: &SigningKey,
+    ) -> Result<SubgraphBundle, GraphError> {
+        let mut included: BTreeSet<&Hash> = BTreeSet::new();
+        for entry in entries {
+            let entry = self
+                .nodes
+                .get_key_value(entry)
+                .map(|(hash, _)| hash)
+                .ok_or_else(|| GraphError::NodeNotFound(entry.clone()))?;
+            if matches!(closure, ClosureKind::Edges | ClosureKind::Both) {
+                self.collect_closure(
+                    entry,
+                    |hash| self.adjacency.successors(hash, None),
+                    &mut included,
+                );
+            }
+            if matches!(closure, ClosureKind::Lineage | ClosureKind::Both) {
+                self.collect_closure(
+                    entry,
+                    |hash| self.adjacency.predecessors(hash, None),
+                    &mut included,
+                );
+            }
+        }
+
+        let mut edges: Vec<(Hash, &GraphEdge)> = self
+            .edges
+            .iter()
+            .filter(|e| included.contains(&e.from) && included.contains(&e.to))
+            .map(|e| (edge_hash(e), e))
+            .collect();
+        edges.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some(pair) = edges.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            let edge = pair[0].1;
+            return Err(GraphError::InvalidBundle(format!(
+                "parallel edges {} -> {} cannot be bundled",
+                edge.from, edge.to
+            )));
+        }
+
+        let manifest = BundleManifest {
+            source_root: self.root_hash.clone(),
+            entries: entries.to_vec(),
+            nodes: included.iter().map(|h| (*h).clone()).collect(),
+            edges: edges.iter().map(|(h, _)| h.clone()).collect(),
+        };
+        let signature = sign_detached(keypair, &manifest.content_hash()?)?;
+
+        Ok(SubgraphBundle {
+            nodes: included.iter().map(|h| self.nodes[*h].clone()).collect(),
+            edges: edges.into_iter().map(|(_, e)| e.clone()).collect(),
+            manifest,
+            signature,
+        })
+    }
+
+    fn collect_closure<'a, I>(
+        &'a self,
+        start: &'a Hash,
+        next: impl Fn(&'a Hash) -> I,
+        into: &mut BTreeSet<&'a Hash>,
+    ) where
+        I: Iterator<Item = &'a Hash>,
+    {
+        let mut stack = vec![start];
+        let mut seen = HashSet::new();
+        while let Some(hash) = stack.pop() {
+            if seen.insert(hash) {
+                into.insert(hash);
+                stack.extend(next(hash));
+            }
+        }
+    }
+
+    /// Verify `bundle`, check its signer is one of `trusted`, and graft it
+    /// onto this graph.
+    pub fn import_bundle(
+        &mut self,
+        bundle: &SubgraphBundle,
+        trusted: &[VerifyingKey],
+    ) -> Result<ImportReport, GraphError> {
+        let signer = bundle.verify()?;
+        if !trusted.contains(&signer) {
+            return Err(GraphError::UntrustedSigner(key_id(&signer)));
+        }
+
+        let mut graph = self.clone();
+        let mut mapping = HashMap::new();
+        let mut nodes_added = 0;
+        for (node, hash) in bundle.nodes.iter().zip(&bundle.manifest.nodes) {
+            let target = if *hash == bundle.manifest.source_root {
+                graph.root_hash.clone()
+            } else {
+                let mut node = node.clone();
+                node.root_ref = graph.root_hash.clone();
+                let target = compute_node_hash(&node);
+                if !graph.nodes.contains_key(&target) {
+                    graph.insert_node(node)?;
+                    nodes_added += 1;
+                }
+                target
+            };
+            mapping.insert(hash.clone(), target);
+        }
+
+        let mut edges_added = 0;
+        for edge in &bundle.edges {
+            let (from, to) = (&mapping[&edge.from], &mapping[&edge.to]);
+            // Bundles hold no parallel edges, so this only skips edges the
+            // target already had, e.g. from an earlier import
+            if graph.has_edge(from, to, edge.edge_type) {
+                continue;
+            }
+            graph.link_nodes(from.clone(), to.clone(), edge.edge_type)?;
+            edges_added += 1;
+        }
+
+        *self = graph;
+        Ok(ImportReport {
+            signer: key_id(&signer),
+            mapping,
+            nodes_added,
+            edges_added,
+        })
+    }
+
+    fn has_edge(&self, from: &Hash, to: &Hash, edge_type: EdgeType) -> bool {
+        self.adjacency
+            .successors(from, Some(edge_type))
+            .any(|h| h == to)
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_root_node, Expression, Literal, NodeMetadata};
+    use capsule_core::generate_keypair;
+
+    fn node(root: &Hash, id: &str, value: i64) -> GraphNode {
+        GraphNode {
+            id: id.to_string(),
+            root_ref: root.clone(),
+            data: Expression::Literal(Literal::Int(value)),
+            metadata: NodeMetadata {
+                timestamp: 1,
+                lineage_depth: 1,
+                tags: vec![],
+            },
+        }
+    }
+
+    /// root -> a -> b -> c, plus root -> other
+    fn source() -> (GenesisGraph, HashMap<&'static str, Hash>) {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root = graph.root_hash().clone();
+        let mut hashes = HashMap::from([("root", root.clone())]);
+        for (i, id) in ["a", "b", "c", "other"].into_iter().enumerate() {
+            hashes.insert(id, graph.insert_node(node(&root, id, i as i64)).unwrap());
+        }
+        for (from, to) in [("root", "a"), ("a", "b"), ("b", "c"), ("root", "other")] {
+            graph
+                .link_nodes(
+                    hashes[from].clone(),
+                    hashes[to].clone(),
+                    EdgeType::Derivation,
+                )
+                .unwrap();
+        }
+        (graph, hashes)
+    }
+
+    fn ids(graph: &GenesisGraph) -> BTreeSet<String> {
+        graph.nodes().values().map(|n| n.id.clone()).collect()
+    }
+
+    #[test]
+    fn test_export_closures() {
+        let (graph, h) = source();
+        let keypair = generate_keypair();
+        let count = |closure| {
+            graph
+                .export_bundle(&[h["b"].clone()], closure, &keypair)
+                .unwrap()
+                .nodes
+                .len()
+        };
+        assert_eq!(count(ClosureKind::Edges), 2); // b, c
+        assert_eq!(count(ClosureKind::Lineage), 3); // root, a, b
+        assert_eq!(count(ClosureKind::Both), 4);
+    }
+
+    #[test]
+    fn test_round_trip_into_another_graph() {
+        let (graph, h) = source();
+        let keypair = generate_keypair();
+        let bundle = graph
+            .export_bundle(&[h["a"].clone()], ClosureKind::Edges, &keypair)
+            .unwrap();
+        let bundle = SubgraphBundle::from_bytes(&bundle.to_bytes().unwrap()).unwrap();
+
+        let mut target = GenesisGraph::new(GraphNode {
+            id: "elsewhere".to_string(),
+            ..create_root_node()
+        })
+        .unwrap();
+        let report = target
+            .import_bundle(&bundle, &[keypair.verifying_key()])
+            .unwrap();
+        assert_eq!(report.nodes_added, 3);
+        assert_eq!(report.edges_added, 2);
+        assert_eq!(report.signer, key_id(&keypair.verifying_key()));
+        assert_eq!(
+            ids(&target),
+            BTreeSet::from(["elsewhere", "a", "b", "c"].map(String::from))
+        );
+
+        // Re-rooted nodes get new hashes; the lineage follows the mapping
+        let c = &report.mapping[&h["c"]];
+        assert_ne!(c, &h["c"]);
+        assert_eq!(target.predecessors(c, None), vec![&report.mapping[&h["b"]]]);
+
+        // Importing again changes nothing
+        let again = target
+            .import_bundle(&bundle, &[keypair.verifying_key()])
+            .unwrap();
+        assert_eq!((again.nodes_added, again.edges_added), (0, 0));
+    }
+
+    #[test]
+    fn test_import_rejects_tampering_and_untrusted_signers() {
+        let (graph, h) = source();
+        let keypair = generate_keypair();
+        let bundle = graph
+            .export_bundle(&[h["b"].clone()], ClosureKind::Edges, &keypair)
+            .unwrap();
+        let trusted = [keypair.verifying_key()];
+        let mut target = GenesisGraph::new(create_root_node()).unwrap();
+
+        assert!(matches!(
+            target.import_bundle(&bundle, &[generate_keypair().verifying_key()]),
+            Err(GraphError::UntrustedSigner(_))
+        ));
+
+        let mut node = bundle.clone();
+        node.nodes[0].data = Expression::Literal(Literal::Int(99));
+        assert!(matches!(
+            target.import_bundle(&node, &trusted),
+            Err(GraphError::InvalidBundle(_))
+        ));
+
+        let mut manifest = bundle.clone();
+        manifest.manifest.entries.push(h["a"].clone());
+        assert!(matches!(
+            target.import_bundle(&manifest, &trusted),
+            Err(GraphError::InvalidBundle(_))
+        ));
+
+        // A correctly signed bundle whose edge points outside it
+        let mut dangling = bundle.clone();
+        dangling.edges[0].to = h["other"].clone();
+        dangling.manifest.edges[0] = edge_hash(&dangling.edges[0]);
+        dangling.signature =
+            sign_detached(&keypair, &dangling.manifest.content_hash().unwrap()).unwrap();
+        assert!(matches!(
+            target.import_bundle(&dangling, &trusted),
+            Err(GraphError::DanglingReference(_))
+        ));
+
+        assert_eq!(target.nodes().len(), 1);
+    }
+
+    #[test]
+    fn test_verify_rejects_non_canonical_manifests() {
+        let (graph, h) = source();
+        let keypair = generate_keypair();
+        let bundle = graph
+            .export_bundle(&[h["a"].clone()], ClosureKind::Edges, &keypair)
+            .unwrap();
+        let resign = |mut bundle: SubgraphBundle| {
+            bundle.signature =
+                sign_detached(&keypair, &bundle.manifest.content_hash().unwrap()).unwrap();
+            bundle
+        };
+
+        let mut unsorted = bundle.clone();
+        unsorted.manifest.nodes.swap(0, 1);
+        unsorted.nodes.swap(0, 1);
+        assert!(matches!(
+            resign(unsorted).verify(),
+            Err(GraphError::InvalidBundle(_))
+        ));
+
+        let mut duplicated = bundle.clone();
+        duplicated
+            .manifest
+            .edges
+            .insert(0, bundle.manifest.edges[0].clone());
+        duplicated.edges.insert(0, bundle.edges[0].clone());
+        assert!(matches!(
+            resign(duplicated).verify(),
+            Err(GraphError::InvalidBundle(_))
+        ));
+
+        assert!(resign(bundle).verify().is_ok());
+    }
+
+    #[test]
+    fn test_export_rejects_parallel_edges() {
+        let (mut graph, h) = source();
+        graph
+            .link_nodes(h["a"].clone(), h["b"].clone(), EdgeType::Derivation)
+            .unwrap();
+        let keypair = generate_keypair();
+        assert!(matches!(
+            graph.export_bundle(&[h["a"].clone()], ClosureKind::Edges, &keypair),
+            Err(GraphError::InvalidBundle(_))
+        ));
+        // A parallel edge of another type hashes differently
+        let (mut graph, h) = source();
+        graph
+            .link_nodes(h["a"].clone(), h["b"].clone(), EdgeType::Reference)
+            .unwrap();
+        let bundle = graph
+            .export_bundle(&[h["a"].clone()], ClosureKind::Edges, &keypair)
+            .unwrap();
+        assert_eq!(bundle.edges.len(), 3);
+    }
+
+    #[test]
+    fn test_import_rejecting_cycle_leaves_graph_unchanged() {
+        let (graph, h) = source();
+        let keypair = generate_keypair();
+        let bundle = graph
+            .export_bundle(&[h["a"].clone()], ClosureKind::Edges, &keypair)
+            .unwrap();
+
+        // Same root, so bundle nodes keep their hashes; c -> a closes a cycle
+        let mut target = graph.clone();
+        target.delete_node(&h["b"]).unwrap();
+        target
+            .link_nodes(h["c"].clone(), h["a"].clone(), EdgeType::Reference)
+            .unwrap();
+        let before = target.canonical_serialize().unwrap();
+
+        assert!(matches!(
+            target.import_bundle(&bundle, &[keypair.verifying_key()]),
+            Err(GraphError::CycleDetected { .. })
+        ));
+        assert_eq!(target.canonical_serialize().unwrap(), before);
+    }
+}
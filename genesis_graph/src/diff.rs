@@ -0,0 +1,699 @@
+// ============================================================================
+// Structural Diff and Three-Way Merge
+// ============================================================================
+//
+// Node hashes cover a node's whole content, so a change gives a node a new
+// hash. To line up versions across graphs, nodes are matched by `NodeKey`:
+// their id, or their hash when several nodes in a graph share an id. Edges
+// are compared by the keys of their endpoints, so modifying a node does not
+// make every edge touching it look replaced.
+//
+// A node whose hash appears in both graphs is unchanged and is skipped
+// without looking at its content.
+
+use crate::{EdgeType, Expression, GenesisGraph, GraphError, GraphNode, Hash, NodeId};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// How a node is identified across versions of a graph.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NodeKey {
+    Id(NodeId),
+    /// Used for nodes whose id is not unique within their graph.
+    Hash(Hash),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EdgeKey {
+    pub from: NodeKey,
+    pub to: NodeKey,
+    pub edge_type: EdgeType,
+}
+
+/// A step from an expression into one of its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExprStep {
+    /// The body of a `Lambda` or `Let`.
+    Body,
+    Func,
+    Arg,
+    Value,
+    /// The expression a `Match` inspects.
+    Scrutinee,
+    /// The body of the `Match` arm at this index.
+    Arm(usize),
+    /// The `Tuple` or `List` element at this index.
+    Element(usize),
+    /// The value of the `Record` field at this index.
+    Field(usize),
+}
+
+/// A subexpression at `path` that was replaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprChange {
+    pub path: Vec<ExprStep>,
+    pub old: Expression,
+    pub new: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeModification {
+    pub key: NodeKey,
+    pub old_hash: Hash,
+    pub new_hash: Hash,
+    pub metadata_changed: bool,
+    pub data_changes: Vec<ExprChange>,
+}
+
+/// Changes that turn one graph into another. Entries are sorted by key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphDiff {
+    /// Hashes in the newer graph.
+    pub added_nodes: Vec<Hash>,
+    /// Hashes in the older graph.
+    pub removed_nodes: Vec<Hash>,
+    pub modified_nodes: Vec<NodeModification>,
+    /// One entry per edge, so parallel edges may repeat.
+    pub added_edges: Vec<EdgeKey>,
+    pub removed_edges: Vec<EdgeKey>,
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.modified_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+    }
+}
+
+// ============================================================================
+// Keys
+// ============================================================================
+
+struct Keyed<'a> {
+    graph: &'a GenesisGraph,
+    hashes: BTreeMap<NodeKey, &'a Hash>,
+    edges: BTreeMap<EdgeKey, usize>,
+}
+
+impl<'a> Keyed<'a> {
+    fn new(graph: &'a GenesisGraph) -> Self {
+        let mut id_counts: HashMap<&NodeId, usize> = HashMap::new();
+        for node in graph.nodes.values() {
+            *id_counts.entry(&node.id).or_default() += 1;
+        }
+
+        let mut hashes = BTreeMap::new();
+        let mut keys = HashMap::new();
+        for (hash, node) in &graph.nodes {
+            let key = if id_counts[&node.id] == 1 {
+                NodeKey::Id(node.id.clone())
+            } else {
+                NodeKey::Hash(hash.clone())
+            };
+            hashes.insert(key.clone(), hash);
+            keys.insert(hash, key);
+        }
+
+        let mut edges = BTreeMap::new();
+        for edge in &graph.edges {
+            let key = EdgeKey {
+                from: keys[&edge.from].clone(),
+                to: keys[&edge.to].clone(),
+                edge_type: edge.edge_type,
+            };
+            *edges.entry(key).or_default() += 1;
+        }
+
+        Keyed {
+            graph,
+            hashes,
+            edges,
+        }
+    }
+
+    fn node(&self, hash: &Hash) -> &'a GraphNode {
+        &self.graph.nodes[hash]
+    }
+}
+
+// ============================================================================
+// Diff
+// ============================================================================
+
+/// Compute the changes from `old` to `new`.
+pub fn diff_graphs(old: &GenesisGraph, new: &GenesisGraph) -> GraphDiff {
+    let old = Keyed::new(old);
+    let new = Keyed::new(new);
+    let mut diff = GraphDiff::default();
+
+    for (key, &old_hash) in &old.hashes {
+        if new.graph.nodes.contains_key(old_hash) {
+            continue;
+        }
+        match new.hashes.get(key) {
+            Some(&new_hash) => {
+                let (before, after) = (old.node(old_hash), new.node(new_hash));
+                let mut data_changes = Vec::new();
+                diff_expressions(
+                    &before.data,
+                    &after.data,
+                    &mut Vec::new(),
+                    &mut data_changes,
+                );
+                diff.modified_nodes.push(NodeModification {
+                    key: key.clone(),
+                    old_hash: old_hash.clone(),
+                    new_hash: new_hash.clone(),
+                    metadata_changed: before.metadata != after.metadata,
+                    data_changes,
+                });
+            }
+            None => diff.removed_nodes.push(old_hash.clone()),
+        }
+    }
+    for (key, &new_hash) in &new.hashes {
+        if !old.graph.nodes.contains_key(new_hash) && !old.hashes.contains_key(key) {
+            diff.added_nodes.push(new_hash.clone());
+        }
+    }
+
+    let edge_keys: BTreeSet<&EdgeKey> = old.edges.keys().chain(new.edges.keys()).collect();
+    for key in edge_keys {
+        let before = old.edges.get(key).copied().unwrap_or(0);
+        let after = new.edges.get(key).copied().unwrap_or(0);
+        let target = if after > before {
+            &mut diff.added_edges
+        } else {
+            &mut diff.removed_edges
+        };
+        target.extend(std::iter::repeat_n(key.clone(), before.abs_diff(after)));
+    }
+
+    diff
+}
+
+/// Record the smallest replaced subexpressions between `old` and `new`.
+fn diff_expressions(
+    old: &Expression,
+    new: &Expression,
+    path: &mut Vec<ExprStep>,
+    changes: &mut Vec<ExprChange>,
+) {
+    if old == new {
+        return;
+    }
+    let children: Vec<(ExprStep, &Expression, &Expression)> = match (old, new) {
+        (
+            Expression::Lambda {
+                param: p1,
+                body: b1,
+            },
+            Expression::Lambda {
+                param: p2,
+                body: b2,
+            },
+        ) if p1 == p2 => vec![(ExprStep::Body, b1, b2)],
+        (Expression::Apply { func: f1, arg: a1 }, Expression::Apply { func: f2, arg: a2 })
+        | (
+            Expression::LinearApply { func: f1, arg: a1 },
+            Expression::LinearApply { func: f2, arg: a2 },
+        ) => vec![(ExprStep::Func, f1, f2), (ExprStep::Arg, a1, a2)],
+        (
+            Expression::Let {
+                name: n1,
+                value: v1,
+                body: b1,
+            },
+            Expression::Let {
+                name: n2,
+                value: v2,
+                body: b2,
+            },
+        ) if n1 == n2 => vec![(ExprStep::Value, v1, v2), (ExprStep::Body, b1, b2)],
+        (
+            Expression::Match {
+                expr: e1,
+                arms: arms1,
+            },
+            Expression::Match {
+                expr: e2,
+                arms: arms2,
+            },
+        ) if arms1.len() == arms2.len()
+            && arms1
+                .iter()
+                .zip(arms2)
+                .all(|(x, y)| x.pattern == y.pattern && x.guard == y.guard) =>
+        {
+            let arms = arms1.iter().zip(arms2).enumerate();
+            std::iter::once((ExprStep::Scrutinee, &**e1, &**e2))
+                .chain(arms.map(|(i, (x, y))| (ExprStep::Arm(i), &*x.body, &*y.body)))
+                .collect()
+        }
+        (Expression::Tuple(xs), Expression::Tuple(ys))
+        | (Expression::List(xs), Expression::List(ys))
+            if xs.len() == ys.len() =>
+        {
+            let items = xs.iter().zip(ys).enumerate();
+            items
+                .map(|(i, (x, y))| (ExprStep::Element(i), x, y))
+                .collect()
+        }
+        (Expression::Record(xs), Expression::Record(ys))
+            if xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| x.0 == y.0) =>
+        {
+            let fields = xs.iter().zip(ys).enumerate();
+            fields
+                .map(|(i, (x, y))| (ExprStep::Field(i), &x.1, &y.1))
+                .collect()
+        }
+        _ => {
+            changes.push(ExprChange {
+                path: path.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            });
+            return;
+        }
+    };
+    for (step, a, b) in children {
+        path.push(step);
+        diff_expressions(a, b, path, changes);
+        path.pop();
+    }
+}
+
+// ============================================================================
+// Three-Way Merge
+// ============================================================================
+
+/// A change the merge could not reconcile. Each is resolved in favour of
+/// `ours` where that is possible, and dropped otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeConflict {
+    /// Both sides changed or removed the node, differently.
+    Node {
+        key: NodeKey,
+        base: Option<Hash>,
+        ours: Option<Hash>,
+        theirs: Option<Hash>,
+    },
+    /// Both sides changed how many copies of the edge there are.
+    Edge {
+        edge: EdgeKey,
+        base: usize,
+        ours: usize,
+        theirs: usize,
+    },
+    /// The edge's endpoint is not in the merged graph.
+    DanglingEdge(EdgeKey),
+    /// The edge is acyclic on its own side but closes a cycle once merged.
+    Cycle { edge: EdgeKey, cycle: Vec<Hash> },
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    pub graph: GenesisGraph,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergeResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge the changes `ours` and `theirs` each made to `base`.
+/// All three graphs must share a root.
+pub fn merge_graphs(
+    base: &GenesisGraph,
+    ours: &GenesisGraph,
+    theirs: &GenesisGraph,
+) -> Result<MergeResult, GraphError> {
+    for side in [ours, theirs] {
+        if side.root_hash != base.root_hash {
+            return Err(GraphError::RootRefMismatch {
+                expected: base.root_hash.clone(),
+                actual: side.root_hash.clone(),
+            });
+        }
+    }
+
+    let (base, ours, theirs) = (Keyed::new(base), Keyed::new(ours), Keyed::new(theirs));
+    let mut conflicts = Vec::new();
+    let mut graph = GenesisGraph::new(base.node(&base.graph.root_hash).clone())?;
+
+    // Nodes
+    let node_keys: BTreeSet<&NodeKey> = base
+        .hashes
+        .keys()
+        .chain(ours.hashes.keys())
+        .chain(theirs.hashes.keys())
+        .collect();
+    let mut merged: HashMap<&NodeKey, &Hash> = HashMap::new();
+    for key in node_keys {
+        let b = base.hashes.get(key).copied();
+        let o = ours.hashes.get(key).copied();
+        let t = theirs.hashes.get(key).copied();
+        let (chosen, source) = if o == t || t == b {
+            (o, &ours)
+        } else if o == b {
+            (t, &theirs)
+        } else {
+            conflicts.push(MergeConflict::Node {
+                key: key.clone(),
+                base: b.cloned(),
+                ours: o.cloned(),
+                theirs: t.cloned(),
+            });
+            (o, &ours)
+        };
+
+        let Some(hash) = chosen else { continue };
+        if *hash != graph.root_hash && !graph.nodes.contains_key(hash) {
+            graph.insert_node(source.node(hash).clone())?;
+        }
+        merged.insert(key, hash);
+    }
+
+    // Edges
+    let edge_keys: BTreeSet<&EdgeKey> = base
+        .edges
+        .keys()
+        .chain(ours.edges.keys())
+        .chain(theirs.edges.keys())
+        .collect();
+    for key in edge_keys {
+        let count = |side: &Keyed| side.edges.get(key).copied().unwrap_or(0);
+        let (b, o, t) = (count(&base), count(&ours), count(&theirs));
+        let copies = if o == t || t == b {
+            o
+        } else if o == b {
+            t
+        } else {
+            conflicts.push(MergeConflict::Edge {
+                edge: key.clone(),
+                base: b,
+                ours: o,
+                theirs: t,
+            });
+            o
+        };
+        if copies == 0 {
+            continue;
+        }
+
+        let (Some(&from), Some(&to)) = (merged.get(&key.from), merged.get(&key.to)) else {
+            conflicts.push(MergeConflict::DanglingEdge(key.clone()));
+            continue;
+        };
+        for _ in 0..copies {
+            match graph.link_nodes(from.clone(), to.clone(), key.edge_type) {
+                Ok(()) => {}
+                Err(GraphError::CycleDetected { cycle }) => {
+                    conflicts.push(MergeConflict::Cycle {
+                        edge: key.clone(),
+                        cycle,
+                    });
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    Ok(MergeResult { graph, conflicts })
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_root_node, Literal, NodeMetadata};
+
+    fn node(graph: &GenesisGraph, id: &str, data: Expression) -> GraphNode {
+        GraphNode {
+            id: id.to_string(),
+            root_ref: graph.root_hash().clone(),
+            data,
+            metadata: NodeMetadata {
+                timestamp: 1,
+                lineage_depth: 1,
+                tags: vec![],
+            },
+        }
+    }
+
+    fn lit(n: i64) -> Expression {
+        Expression::Literal(Literal::Int(n))
+    }
+
+    fn key(id: &str) -> NodeKey {
+        NodeKey::Id(id.to_string())
+    }
+
+    fn edge(from: &str, to: &str, edge_type: EdgeType) -> EdgeKey {
+        EdgeKey {
+            from: key(from),
+            to: key(to),
+            edge_type,
+        }
+    }
+
+    /// Replace the node with id `id`, keeping its edges.
+    fn replace(graph: &mut GenesisGraph, id: &str, data: Expression) -> Hash {
+        let (old_hash, old) = graph
+            .nodes()
+            .iter()
+            .find(|(_, n)| n.id == id)
+            .map(|(h, n)| (h.clone(), n.clone()))
+            .unwrap();
+        let edges: Vec<_> = graph
+            .edges()
+            .iter()
+            .filter(|e| e.from == old_hash || e.to == old_hash)
+            .cloned()
+            .collect();
+        graph.delete_node(&old_hash).unwrap();
+        let new_hash = graph.insert_node(GraphNode { data, ..old }).unwrap();
+        for e in edges {
+            let from = if e.from == old_hash {
+                new_hash.clone()
+            } else {
+                e.from
+            };
+            let to = if e.to == old_hash {
+                new_hash.clone()
+            } else {
+                e.to
+            };
+            graph.link_nodes(from, to, e.edge_type).unwrap();
+        }
+        new_hash
+    }
+
+    /// root -> a -> b, all Derivation.
+    fn base() -> GenesisGraph {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root = graph.root_hash().clone();
+        let a = graph.insert_node(node(&graph, "a", lit(1))).unwrap();
+        let b = graph
+            .insert_node(node(
+                &graph,
+                "b",
+                Expression::Apply {
+                    func: Box::new(Expression::Var("f".to_string())),
+                    arg: Box::new(lit(2)),
+                },
+            ))
+            .unwrap();
+        graph
+            .link_nodes(root, a.clone(), EdgeType::Derivation)
+            .unwrap();
+        graph.link_nodes(a, b, EdgeType::Derivation).unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_diff_of_identical_graphs_is_empty() {
+        let graph = base();
+        assert!(diff_graphs(&graph, &graph.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_reports_nodes_edges_and_expression_changes() {
+        let old = base();
+        let mut new = old.clone();
+        let b = replace(
+            &mut new,
+            "b",
+            Expression::Apply {
+                func: Box::new(Expression::Var("f".to_string())),
+                arg: Box::new(lit(3)),
+            },
+        );
+        let c = new.insert_node(node(&new, "c", lit(9))).unwrap();
+        new.link_nodes(b.clone(), c.clone(), EdgeType::Reference)
+            .unwrap();
+        let a = new
+            .nodes()
+            .iter()
+            .find(|(_, n)| n.id == "a")
+            .map(|(h, _)| h.clone())
+            .unwrap();
+        new.delete_node(&a).unwrap();
+
+        let diff = diff_graphs(&old, &new);
+        assert_eq!(diff.added_nodes, vec![c]);
+        assert_eq!(diff.removed_nodes, vec![a]);
+        assert_eq!(diff.modified_nodes.len(), 1);
+        let modified = &diff.modified_nodes[0];
+        assert_eq!(modified.key, key("b"));
+        assert_eq!(modified.new_hash, b);
+        assert!(!modified.metadata_changed);
+        assert_eq!(
+            modified.data_changes,
+            vec![ExprChange {
+                path: vec![ExprStep::Arg],
+                old: lit(2),
+                new: lit(3),
+            }]
+        );
+        assert_eq!(diff.added_edges, vec![edge("b", "c", EdgeType::Reference)]);
+        assert_eq!(
+            diff.removed_edges,
+            vec![
+                edge("a", "b", EdgeType::Derivation),
+                edge("⊙₀", "a", EdgeType::Derivation)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expression_diff_descends_into_collections() {
+        let record = |v: Expression| {
+            Expression::Record(vec![("k".to_string(), Expression::Tuple(vec![lit(0), v]))])
+        };
+        let mut changes = Vec::new();
+        diff_expressions(
+            &record(lit(1)),
+            &record(lit(2)),
+            &mut Vec::new(),
+            &mut changes,
+        );
+        assert_eq!(
+            changes,
+            vec![ExprChange {
+                path: vec![ExprStep::Field(0), ExprStep::Element(1)],
+                old: lit(1),
+                new: lit(2),
+            }]
+        );
+
+        // Differently keyed records are replaced whole
+        let other = Expression::Record(vec![("j".to_string(), lit(1))]);
+        changes.clear();
+        diff_expressions(&record(lit(1)), &other, &mut Vec::new(), &mut changes);
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].path.is_empty());
+    }
+
+    #[test]
+    fn test_clean_merge_combines_both_sides() {
+        let base = base();
+        let mut ours = base.clone();
+        replace(&mut ours, "a", lit(10));
+        let mut theirs = base.clone();
+        let c = theirs.insert_node(node(&theirs, "c", lit(5))).unwrap();
+        let root = theirs.root_hash().clone();
+        theirs.link_nodes(root, c, EdgeType::Reference).unwrap();
+
+        let result = merge_graphs(&base, &ours, &theirs).unwrap();
+        assert!(result.is_clean(), "{:?}", result.conflicts);
+        assert!(diff_graphs(&ours, &result.graph).modified_nodes.is_empty());
+
+        let merged = Keyed::new(&result.graph);
+        assert_eq!(merged.node(merged.hashes[&key("a")]).data, lit(10));
+        assert!(merged.hashes.contains_key(&key("c")));
+        assert_eq!(merged.edges.len(), 3);
+        assert_eq!(merged.edges[&edge("a", "b", EdgeType::Derivation)], 1);
+    }
+
+    #[test]
+    fn test_merge_reports_conflicts() {
+        let base = base();
+
+        // Both sides rewrite `a`; ours deletes `b`, which theirs links from
+        let mut ours = base.clone();
+        replace(&mut ours, "a", lit(10));
+        let b = Keyed::new(&ours).hashes[&key("b")].clone();
+        ours.delete_node(&b).unwrap();
+
+        let mut theirs = base.clone();
+        replace(&mut theirs, "a", lit(20));
+        let d = theirs.insert_node(node(&theirs, "d", lit(4))).unwrap();
+        let b = Keyed::new(&theirs).hashes[&key("b")].clone();
+        theirs.link_nodes(b, d, EdgeType::Dependency).unwrap();
+
+        let result = merge_graphs(&base, &ours, &theirs).unwrap();
+        let a_conflict = result.conflicts.iter().find_map(|c| match c {
+            MergeConflict::Node {
+                key: k,
+                ours,
+                theirs,
+                ..
+            } if *k == key("a") => Some((ours.clone(), theirs.clone())),
+            _ => None,
+        });
+        let (ours_a, theirs_a) = a_conflict.expect("conflict on a");
+        assert_ne!(ours_a, theirs_a);
+        assert!(result.conflicts.contains(&MergeConflict::DanglingEdge(edge(
+            "b",
+            "d",
+            EdgeType::Dependency
+        ))));
+
+        // Ours wins the conflict
+        let merged = Keyed::new(&result.graph);
+        assert_eq!(merged.node(merged.hashes[&key("a")]).data, lit(10));
+        assert!(!merged.hashes.contains_key(&key("b")));
+    }
+
+    #[test]
+    fn test_merge_detects_cross_branch_cycle() {
+        let mut base = GenesisGraph::new(create_root_node()).unwrap();
+        let x = base.insert_node(node(&base, "x", lit(1))).unwrap();
+        let y = base.insert_node(node(&base, "y", lit(2))).unwrap();
+
+        let mut ours = base.clone();
+        ours.link_nodes(x.clone(), y.clone(), EdgeType::Dependency)
+            .unwrap();
+        let mut theirs = base.clone();
+        theirs
+            .link_nodes(y.clone(), x.clone(), EdgeType::Dependency)
+            .unwrap();
+
+        let result = merge_graphs(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts.len(), 1);
+        assert!(matches!(
+            &result.conflicts[0],
+            MergeConflict::Cycle { edge: e, .. } if *e == edge("y", "x", EdgeType::Dependency)
+        ));
+        assert!(result.graph.topological_sort().is_some());
+
+        let other = GenesisGraph::new(GraphNode {
+            id: "elsewhere".to_string(),
+            ..create_root_node()
+        })
+        .unwrap();
+        assert!(matches!(
+            merge_graphs(&base, &ours, &other),
+            Err(GraphError::RootRefMismatch { .. })
+        ));
+    }
+}
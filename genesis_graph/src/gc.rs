@@ -0,0 +1,330 @@
+// ============================================================================
+// Garbage Collection
+// ============================================================================
+//
+// Mark-and-sweep over the graph. Marking starts at the root and every
+// pinned node and follows outgoing edges of all types, plus each marked
+// node's `root_ref`; whatever is left unmarked is an orphan and is swept
+// along with its edges.
+//
+// Each swept node leaves a tombstone recording what it was and which nodes
+// linked to it, so `lineage_status` can still account for it afterwards.
+
+use crate::{GenesisGraph, GraphError, Hash, NodeId};
+use capsule_core::{Clock, SystemClock};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What remains of a node removed by garbage collection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub id: NodeId,
+    pub lineage_depth: u32,
+    pub collected_at: u64,
+    /// Sources of the edges into the node when it was collected.
+    pub predecessors: Vec<Hash>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub live_nodes: usize,
+    pub reclaimed_nodes: usize,
+    pub reclaimed_edges: usize,
+    /// Serialized size of the reclaimed nodes.
+    pub reclaimed_bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcReport {
+    /// Whether the graph was left untouched.
+    pub dry_run: bool,
+    /// Unreachable nodes, sorted; removed unless `dry_run`.
+    pub unreachable: Vec<Hash>,
+    pub stats: GcStats,
+}
+
+/// Where a hash stands with respect to the graph's lineage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineageStatus<'a> {
+    /// Live and reachable from the root along this path.
+    Live(Vec<Hash>),
+    /// Live but not reachable from the root.
+    Detached,
+    Collected(&'a Tombstone),
+    Unknown,
+}
+
+impl GenesisGraph {
+    /// Keep `hash`, and everything reachable from it, alive across
+    /// collections even when it is not reachable from the root.
+    pub fn pin(&mut self, hash: &Hash) -> Result<(), GraphError> {
+        if !self.nodes.contains_key(hash) {
+            return Err(GraphError::NodeNotFound(hash.clone()));
+        }
+        self.pinned.insert(hash.clone());
+        Ok(())
+    }
+
+    /// Returns whether `hash` was pinned.
+    pub fn unpin(&mut self, hash: &Hash) -> bool {
+        self.pinned.remove(hash)
+    }
+
+    pub fn pinned(&self) -> impl Iterator<Item = &Hash> {
+        self.pinned.iter()
+    }
+
+    pub fn tombstone(&self, hash: &Hash) -> Option<&Tombstone> {
+        self.tombstones.get(hash)
+    }
+
+    /// Forget all tombstones.
+    pub fn clear_tombstones(&mut self) {
+        self.tombstones.clear();
+    }
+
+    pub fn lineage_status(&self, hash: &Hash) -> LineageStatus<'_> {
+        if self.nodes.contains_key(hash) {
+            match self.get_lineage(hash) {
+                Some(path) => LineageStatus::Live(path),
+                None => LineageStatus::Detached,
+            }
+        } else if let Some(tombstone) = self.tombstones.get(hash) {
+            LineageStatus::Collected(tombstone)
+        } else {
+            LineageStatus::Unknown
+        }
+    }
+
+    /// Report what a collection would remove, without removing it.
+    pub fn gc_dry_run(&self) -> GcReport {
+        let unreachable = self.unreachable();
+        let stats = self.sweep_stats(&unreachable);
+        GcReport {
+            dry_run: true,
+            unreachable,
+            stats,
+        }
+    }
+
+    /// Remove every node not reachable from the root or a pinned node.
+    pub fn collect_garbage(&mut self) -> GcReport {
+        self.collect_garbage_with_clock(&SystemClock)
+    }
+
+    /// [`Self::collect_garbage`] with tombstone times taken from `clock`.
+    pub fn collect_garbage_with_clock(&mut self, clock: &dyn Clock) -> GcReport {
+        let unreachable = self.unreachable();
+        let stats = self.sweep_stats(&unreachable);
+        let now = clock.now();
+
+        // Read every predecessor list before unlinking anything, so a
+        // tombstone still names swept nodes that linked to it
+        let predecessors: Vec<Vec<Hash>> = unreachable
+            .iter()
+            .map(|hash| {
+                let mut predecessors: Vec<Hash> =
+                    self.adjacency.predecessors(hash, None).cloned().collect();
+                predecessors.sort();
+                predecessors.dedup();
+                predecessors
+            })
+            .collect();
+
+        for (hash, predecessors) in unreachable.iter().zip(predecessors) {
+            let node = self.nodes.remove(hash).expect("unreachable nodes are live");
+            self.adjacency.unlink_node(&mut self.edges, hash);
+            self.tombstones.insert(
+                hash.clone(),
+                Tombstone {
+                    id: node.id,
+                    lineage_depth: node.metadata.lineage_depth,
+                    collected_at: now,
+                    predecessors,
+                },
+            );
+        }
+
+        GcReport {
+            dry_run: false,
+            unreachable,
+            stats,
+        }
+    }
+
+    /// Mark from the root and pins; return the unmarked nodes, sorted.
+    fn unreachable(&self) -> Vec<Hash> {
+        let mut marked: HashSet<&Hash> = HashSet::new();
+        let mut stack: Vec<&Hash> = vec![&self.root_hash];
+        stack.extend(self.pinned.iter());
+
+        while let Some(hash) = stack.pop() {
+            let Some(node) = self.nodes.get(hash) else {
+                continue;
+            };
+            if !marked.insert(hash) {
+                continue;
+            }
+            stack.extend(self.adjacency.successors(hash, None));
+            if !node.root_ref.is_empty() {
+                stack.push(&node.root_ref);
+            }
+        }
+
+        let mut unreachable: Vec<Hash> = self
+            .nodes
+            .keys()
+            .filter(|h| !marked.contains(h))
+            .cloned()
+            .collect();
+        unreachable.sort();
+        unreachable
+    }
+
+    fn sweep_stats(&self, unreachable: &[Hash]) -> GcStats {
+        let swept: HashSet<&Hash> = unreachable.iter().collect();
+        let reclaimed_bytes = unreachable
+            .iter()
+            .map(|hash| {
+                let mut buffer = Vec::new();
+                ciborium::into_writer(&self.nodes[hash], &mut buffer)
+                    .expect("Node serialization failed");
+                buffer.len()
+            })
+            .sum();
+        // Counted from the swept side, without scanning the edge list
+        let reclaimed_edges = unreachable
+            .iter()
+            .map(|hash| {
+                self.adjacency.out_degree(hash, None)
+                    + self
+                        .adjacency
+                        .predecessors(hash, None)
+                        .filter(|p| !swept.contains(p))
+                        .count()
+            })
+            .sum();
+
+        GcStats {
+            live_nodes: self.nodes.len() - unreachable.len(),
+            reclaimed_nodes: unreachable.len(),
+            reclaimed_edges,
+            reclaimed_bytes,
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_root_node, EdgeType, Expression, GraphNode, NodeMetadata};
+    use capsule_core::FixedClock;
+
+    fn add(graph: &mut GenesisGraph, id: &str) -> Hash {
+        let node = GraphNode {
+            id: id.to_string(),
+            root_ref: graph.root_hash().clone(),
+            data: Expression::Var(id.to_string()),
+            metadata: NodeMetadata {
+                timestamp: 1,
+                lineage_depth: 1,
+                tags: vec![],
+            },
+        };
+        graph.insert_node(node).unwrap()
+    }
+
+    fn link(graph: &mut GenesisGraph, from: &Hash, to: &Hash) {
+        graph
+            .link_nodes(from.clone(), to.clone(), EdgeType::Derivation)
+            .unwrap();
+    }
+
+    /// root -> live; orphan -> child; pinned -> kept
+    fn fixture() -> (GenesisGraph, [Hash; 5]) {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root = graph.root_hash().clone();
+        let live = add(&mut graph, "live");
+        let orphan = add(&mut graph, "orphan");
+        let child = add(&mut graph, "child");
+        let kept = add(&mut graph, "kept");
+        link(&mut graph, &root, &live);
+        link(&mut graph, &orphan, &child);
+        graph.pin(&kept).unwrap();
+        (graph, [root, live, orphan, child, kept])
+    }
+
+    #[test]
+    fn test_dry_run_leaves_graph_untouched() {
+        let (graph, [_, _, orphan, child, _]) = fixture();
+        let before = graph.canonical_serialize().unwrap();
+
+        let report = graph.gc_dry_run();
+        assert!(report.dry_run);
+        let mut expected = vec![orphan, child];
+        expected.sort();
+        assert_eq!(report.unreachable, expected);
+        assert_eq!(report.stats.live_nodes, 3);
+        assert_eq!(report.stats.reclaimed_nodes, 2);
+        assert_eq!(report.stats.reclaimed_edges, 1);
+        assert!(report.stats.reclaimed_bytes > 0);
+        assert_eq!(graph.canonical_serialize().unwrap(), before);
+    }
+
+    #[test]
+    fn test_collect_sweeps_orphans_and_leaves_tombstones() {
+        let (mut graph, [root, live, orphan, child, kept]) = fixture();
+        let dry = graph.gc_dry_run();
+
+        let report = graph.collect_garbage_with_clock(&FixedClock(42));
+        assert!(!report.dry_run);
+        assert_eq!(report.stats, dry.stats);
+        assert_eq!(graph.nodes().len(), 3);
+        assert_eq!(graph.edges().len(), 1);
+        assert_eq!(graph.in_degree(&live, None), 1);
+
+        match graph.lineage_status(&child) {
+            LineageStatus::Collected(tombstone) => {
+                assert_eq!(tombstone.id, "child");
+                assert_eq!(tombstone.collected_at, 42);
+                assert_eq!(tombstone.predecessors, vec![orphan.clone()]);
+            }
+            other => panic!("expected a tombstone, got {:?}", other),
+        }
+        assert_eq!(
+            graph.lineage_status(&live),
+            LineageStatus::Live(vec![root, live.clone()])
+        );
+        assert_eq!(graph.lineage_status(&kept), LineageStatus::Detached);
+        assert_eq!(
+            graph.lineage_status(&"ab".repeat(32)),
+            LineageStatus::Unknown
+        );
+
+        // A second pass finds nothing; unpinning frees the pinned node
+        assert!(graph.collect_garbage().unreachable.is_empty());
+        assert!(graph.unpin(&kept));
+        assert_eq!(graph.collect_garbage().unreachable, vec![kept]);
+    }
+
+    #[test]
+    fn test_pins_and_tombstones_survive_serde() {
+        let (mut graph, [_, _, _, child, kept]) = fixture();
+        graph.collect_garbage_with_clock(&FixedClock(7));
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&graph, &mut bytes).unwrap();
+        let restored: GenesisGraph = ciborium::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(restored.pinned().collect::<Vec<_>>(), vec![&kept]);
+        assert_eq!(restored.tombstone(&child), graph.tombstone(&child));
+
+        assert!(matches!(
+            graph.pin(&child),
+            Err(GraphError::NodeNotFound(_))
+        ));
+    }
+}
@@ -1,8 +1,27 @@
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet, BTreeMap};
+use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet};
 use thiserror::Error;
 
+mod adjacency;
+pub mod bundle;
+pub mod diff;
+pub mod gc;
+pub mod merkle;
+pub mod query;
+pub mod storage;
+
+use adjacency::Adjacency;
+pub use bundle::{BundleManifest, ClosureKind, ImportReport, SubgraphBundle};
+pub use diff::{diff_graphs, merge_graphs, GraphDiff, MergeConflict, MergeResult};
+pub use gc::{GcReport, GcStats, LineageStatus, Tombstone};
+pub use glyph_ast::{Expression, Literal, MatchArm, Pattern};
+use glyph_ast::legacy;
+pub use merkle::{verify_node_proof, NodeProof};
+pub use query::{Query, QueryResult};
+
+pub use storage::GraphStore;
+
 // ============================================================================
 // Type Aliases
 // ============================================================================
@@ -10,19 +29,6 @@ use thiserror::Error;
 pub type Hash = String;
 pub type NodeId = String;
 
-// ============================================================================
-// Expression (simplified for this module - in practice, import from glyph_parser)
-// ============================================================================
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Expression {
-    Literal(i64),
-    Var(String),
-    Lambda { param: String, body: Box<Expression> },
-    Apply { func: Box<Expression>, arg: Box<Expression> },
-    Let { name: String, value: Box<Expression>, body: Box<Expression> },
-}
-
 // ============================================================================
 // Core Data Structures
 // ============================================================================
@@ -31,10 +37,30 @@ pub enum Expression {
 pub struct GraphNode {
     pub id: NodeId,
     pub root_ref: Hash,
+    /// Also reads nodes written with the legacy graph AST
+    #[serde(deserialize_with = "deserialize_data")]
     pub data: Expression,
     pub metadata: NodeMetadata,
 }
 
+/// A node's expression as either AST encodes it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyExpression {
+    Shared(Expression),
+    Legacy(legacy::graph::Expression),
+}
+
+fn deserialize_data<'de, D>(deserializer: D) -> Result<Expression, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match AnyExpression::deserialize(deserializer)? {
+        AnyExpression::Shared(expr) => expr,
+        AnyExpression::Legacy(expr) => expr.into(),
+    })
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NodeMetadata {
     pub timestamp: u64,
@@ -49,7 +75,7 @@ pub struct GraphEdge {
     pub edge_type: EdgeType,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
 pub enum EdgeType {
     Dependency,
     Derivation,
@@ -57,10 +83,64 @@ pub enum EdgeType {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "GraphParts")]
 pub struct GenesisGraph {
     nodes: HashMap<Hash, GraphNode>,
     edges: Vec<GraphEdge>,
     root_hash: Hash,
+    /// Extra garbage collection roots
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pinned: BTreeSet<Hash>,
+    /// Nodes removed by garbage collection
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tombstones: BTreeMap<Hash, Tombstone>,
+    /// Derived from `edges`, so rebuilt on load rather than serialized
+    #[serde(skip_serializing)]
+    adjacency: Adjacency,
+}
+
+/// The serialized fields of a `GenesisGraph`.
+#[derive(Deserialize)]
+struct GraphParts {
+    nodes: HashMap<Hash, GraphNode>,
+    edges: Vec<GraphEdge>,
+    root_hash: Hash,
+    #[serde(default)]
+    pinned: BTreeSet<Hash>,
+    #[serde(default)]
+    tombstones: BTreeMap<Hash, Tombstone>,
+}
+
+/// Loading holds edges to the same rules as `link_nodes`: both ends exist,
+/// no self-loops and no cycles.
+impl TryFrom<GraphParts> for GenesisGraph {
+    type Error = GraphError;
+
+    fn try_from(parts: GraphParts) -> Result<Self, GraphError> {
+        for edge in &parts.edges {
+            for end in [&edge.from, &edge.to] {
+                if !parts.nodes.contains_key(end) {
+                    return Err(GraphError::NodeNotFound(end.clone()));
+                }
+            }
+            if edge.from == edge.to {
+                return Err(GraphError::SelfLoopForbidden(edge.from.clone()));
+            }
+        }
+
+        let mut graph = GenesisGraph::from_parts(parts.nodes, parts.edges, parts.root_hash);
+        if graph.has_cycle() {
+            let cycle = graph
+                .edges
+                .iter()
+                .find_map(|edge| graph.adjacency.cycle_through(&edge.from, &edge.to))
+                .expect("a cycle runs through some edge");
+            return Err(GraphError::CycleDetected { cycle });
+        }
+        graph.pinned = parts.pinned;
+        graph.tombstones = parts.tombstones;
+        Ok(graph)
+    }
 }
 
 // ============================================================================
@@ -75,8 +155,9 @@ pub enum GraphError {
     #[error("Node already exists: {0}")]
     NodeAlreadyExists(Hash),
     
-    #[error("Cycle detected: adding edge would create a cycle")]
-    CycleDetected,
+    /// `cycle` starts and ends at the source of the rejected edge.
+    #[error("Cycle detected: adding edge would create the cycle {}", .cycle.join(" -> "))]
+    CycleDetected { cycle: Vec<Hash> },
     
     #[error("Self-loop forbidden: {0} -> {0}")]
     SelfLoopForbidden(Hash),
@@ -89,6 +170,31 @@ pub enum GraphError {
     
     #[error("Serialization error: {0}")]
     SerializationError(String),
+
+    #[error("I/O error on {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Corrupt graph store: {0}")]
+    CorruptStore(String),
+
+    #[error("Invalid query at offset {position}: {message}")]
+    InvalidQuery { position: usize, message: String },
+
+    #[error("Invalid bundle: {0}")]
+    InvalidBundle(String),
+
+    #[error("Dangling reference: {0} is not in the bundle")]
+    DanglingReference(Hash),
+
+    #[error("Bundle signer {0} is not trusted")]
+    UntrustedSigner(String),
+
+    #[error(transparent)]
+    Capsule(#[from] capsule_core::CapsuleError),
 }
 
 // ============================================================================
@@ -116,9 +222,38 @@ pub fn compute_root_hash(root_node: &GraphNode) -> Hash {
     hex::encode(hasher.finalize())
 }
 
+/// The bytes a node's hash covers. An expression the legacy graph AST can
+/// hold is encoded with that AST, so nodes written before the shared AST
+/// keep their hashes.
 fn canonical_serialize_node(node: &GraphNode) -> Vec<u8> {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Data {
+        Legacy(legacy::graph::Expression),
+        Shared(Expression),
+    }
+    
+    #[derive(Serialize)]
+    struct NodeFields<'a> {
+        id: &'a NodeId,
+        root_ref: &'a Hash,
+        data: Data,
+        metadata: &'a NodeMetadata,
+    }
+    
+    let data = node.data.canonicalize();
+    let data = match legacy::graph::Expression::try_from(data.clone()) {
+        Ok(legacy) => Data::Legacy(legacy),
+        Err(_) => Data::Shared(data),
+    };
+    let fields = NodeFields {
+        id: &node.id,
+        root_ref: &node.root_ref,
+        data,
+        metadata: &node.metadata,
+    };
     let mut buffer = Vec::new();
-    ciborium::into_writer(node, &mut buffer).expect("Node serialization failed");
+    ciborium::into_writer(&fields, &mut buffer).expect("Node serialization failed");
     buffer
 }
 
@@ -151,11 +286,23 @@ impl GenesisGraph {
         let mut nodes = HashMap::new();
         nodes.insert(root_hash.clone(), normalized_node);
         
-        Ok(Self {
+        Ok(Self::from_parts(nodes, Vec::new(), root_hash))
+    }
+
+    pub(crate) fn from_parts(
+        nodes: HashMap<Hash, GraphNode>,
+        edges: Vec<GraphEdge>,
+        root_hash: Hash,
+    ) -> Self {
+        let adjacency = Adjacency::from_edges(&edges);
+        Self {
             nodes,
-            edges: Vec::new(),
+            edges,
             root_hash,
-        })
+            pinned: BTreeSet::new(),
+            tombstones: BTreeMap::new(),
+            adjacency,
+        }
     }
     
     /// Get the root hash
@@ -177,6 +324,27 @@ impl GenesisGraph {
     pub fn edges(&self) -> &[GraphEdge] {
         &self.edges
     }
+
+    /// Targets of edges out of `hash`, restricted to `edge_type` if given.
+    /// A target linked by several edges appears once per edge.
+    pub fn successors(&self, hash: &Hash, edge_type: Option<EdgeType>) -> Vec<&Hash> {
+        self.adjacency.successors(hash, edge_type).collect()
+    }
+
+    /// Sources of edges into `hash`, restricted to `edge_type` if given.
+    pub fn predecessors(&self, hash: &Hash, edge_type: Option<EdgeType>) -> Vec<&Hash> {
+        self.adjacency.predecessors(hash, edge_type).collect()
+    }
+
+    /// Number of edges into `hash`, restricted to `edge_type` if given.
+    pub fn in_degree(&self, hash: &Hash, edge_type: Option<EdgeType>) -> usize {
+        self.adjacency.in_degree(hash, edge_type)
+    }
+
+    /// Number of edges out of `hash`, restricted to `edge_type` if given.
+    pub fn out_degree(&self, hash: &Hash, edge_type: Option<EdgeType>) -> usize {
+        self.adjacency.out_degree(hash, edge_type)
+    }
     
     /// Insert a new node into the graph
     pub fn insert_node(&mut self, node: GraphNode) -> Result<Hash, GraphError> {
@@ -219,23 +387,18 @@ impl GenesisGraph {
             return Err(GraphError::SelfLoopForbidden(from));
         }
         
-        // Check for cycles before adding edge
+        // The graph is acyclic, so the new edge closes a cycle exactly when
+        // `from` is already reachable from `to`
+        if let Some(cycle) = self.adjacency.cycle_through(&from, &to) {
+            return Err(GraphError::CycleDetected { cycle });
+        }
+        
         let new_edge = GraphEdge {
-            from: from.clone(),
-            to: to.clone(),
+            from,
+            to,
             edge_type,
         };
-        
-        // Temporarily add edge to check for cycles
-        self.edges.push(new_edge.clone());
-        
-        if self.has_cycle() {
-            // Remove the edge we just added
-            self.edges.pop();
-            return Err(GraphError::CycleDetected);
-        }
-        
-        // Edge is valid and already added
+        self.adjacency.link(&mut self.edges, new_edge);
         Ok(())
     }
     
@@ -251,9 +414,10 @@ impl GenesisGraph {
             .nodes
             .remove(hash)
             .ok_or_else(|| GraphError::NodeNotFound(hash.clone()))?;
+        self.pinned.remove(hash);
         
-        // Remove all edges connected to this node
-        self.edges.retain(|edge| edge.from != *hash && edge.to != *hash);
+        // Remove all edges connected to this node, found via the index
+        self.adjacency.unlink_node(&mut self.edges, hash);
         
         Ok(node)
     }
@@ -263,18 +427,12 @@ impl GenesisGraph {
         let mut visited = HashSet::new();
         let mut rec_stack = HashSet::new();
         
-        // Build adjacency list
-        let mut adj_list: HashMap<&Hash, Vec<&Hash>> = HashMap::new();
-        for edge in &self.edges {
-            adj_list.entry(&edge.from).or_insert_with(Vec::new).push(&edge.to);
-        }
-        
         // Check all nodes
         for node_hash in self.nodes.keys() {
-            if !visited.contains(node_hash) {
-                if self.has_cycle_util(node_hash, &adj_list, &mut visited, &mut rec_stack) {
-                    return true;
-                }
+            if !visited.contains(node_hash)
+                && self.has_cycle_util(node_hash, &mut visited, &mut rec_stack)
+            {
+                return true;
             }
         }
         
@@ -283,25 +441,22 @@ impl GenesisGraph {
     
     /// DFS helper for cycle detection
     fn has_cycle_util<'a>(
-        &self,
+        &'a self,
         node: &'a Hash,
-        adj_list: &HashMap<&'a Hash, Vec<&'a Hash>>,
         visited: &mut HashSet<&'a Hash>,
         rec_stack: &mut HashSet<&'a Hash>,
     ) -> bool {
         visited.insert(node);
         rec_stack.insert(node);
         
-        if let Some(neighbors) = adj_list.get(node) {
-            for neighbor in neighbors {
-                if !visited.contains(neighbor) {
-                    if self.has_cycle_util(neighbor, adj_list, visited, rec_stack) {
-                        return true;
-                    }
-                } else if rec_stack.contains(neighbor) {
-                    // Back edge found - cycle detected
+        for neighbor in self.adjacency.successors(node, None) {
+            if !visited.contains(neighbor) {
+                if self.has_cycle_util(neighbor, visited, rec_stack) {
                     return true;
                 }
+            } else if rec_stack.contains(neighbor) {
+                // Back edge found - cycle detected
+                return true;
             }
         }
         
@@ -315,26 +470,17 @@ impl GenesisGraph {
             return None;
         }
         
-        let mut in_degree: HashMap<Hash, usize> = HashMap::new();
-        let mut adj_list: HashMap<Hash, Vec<Hash>> = HashMap::new();
-        
-        // Initialize in-degree and adjacency list
-        for node_hash in self.nodes.keys() {
-            in_degree.insert(node_hash.clone(), 0);
-            adj_list.insert(node_hash.clone(), Vec::new());
-        }
-        
-        // Build graph
-        for edge in &self.edges {
-            adj_list.get_mut(&edge.from).unwrap().push(edge.to.clone());
-            *in_degree.get_mut(&edge.to).unwrap() += 1;
-        }
+        let mut in_degree: HashMap<&Hash, usize> = self
+            .nodes
+            .keys()
+            .map(|hash| (hash, self.adjacency.in_degree(hash, None)))
+            .collect();
         
         // Find all nodes with in-degree 0
-        let mut queue: Vec<Hash> = in_degree
+        let mut queue: Vec<&Hash> = in_degree
             .iter()
             .filter(|(_, &degree)| degree == 0)
-            .map(|(hash, _)| hash.clone())
+            .map(|(hash, _)| *hash)
             .collect();
         
         // Sort queue for deterministic ordering
@@ -346,15 +492,13 @@ impl GenesisGraph {
             result.push(node.clone());
             
             // Reduce in-degree for neighbors
-            if let Some(neighbors) = adj_list.get(&node) {
-                for neighbor in neighbors {
-                    let degree = in_degree.get_mut(neighbor).unwrap();
-                    *degree -= 1;
-                    
-                    if *degree == 0 {
-                        queue.push(neighbor.clone());
-                        queue.sort();
-                    }
+            for neighbor in self.adjacency.successors(node, None) {
+                let degree = in_degree.get_mut(neighbor).unwrap();
+                *degree -= 1;
+                
+                if *degree == 0 {
+                    queue.push(neighbor);
+                    queue.sort();
                 }
             }
         }
@@ -422,15 +566,6 @@ impl GenesisGraph {
         let mut parent: HashMap<Hash, Hash> = HashMap::new();
         let mut visited = HashSet::new();
         
-        // Build adjacency list
-        let mut adj_list: HashMap<Hash, Vec<Hash>> = HashMap::new();
-        for edge in &self.edges {
-            adj_list
-                .entry(edge.from.clone())
-                .or_insert_with(Vec::new)
-                .push(edge.to.clone());
-        }
-        
         visited.insert(self.root_hash.clone());
         
         while let Some(current) = queue.pop() {
@@ -448,13 +583,11 @@ impl GenesisGraph {
                 return Some(path);
             }
             
-            if let Some(neighbors) = adj_list.get(&current) {
-                for neighbor in neighbors {
-                    if !visited.contains(neighbor) {
-                        visited.insert(neighbor.clone());
-                        parent.insert(neighbor.clone(), current.clone());
-                        queue.insert(0, neighbor.clone()); // BFS
-                    }
+            for neighbor in self.adjacency.successors(&current, None) {
+                if !visited.contains(neighbor) {
+                    visited.insert(neighbor.clone());
+                    parent.insert(neighbor.clone(), current.clone());
+                    queue.insert(0, neighbor.clone()); // BFS
                 }
             }
         }
@@ -474,7 +607,7 @@ pub fn create_root_node() -> GraphNode {
         tags: vec!["genesis".to_string(), "root".to_string()],
     };
     
-    let data = Expression::Literal(0); // Genesis value
+    let data = Expression::Literal(Literal::Int(0)); // Genesis value
     
     // Root node has empty root_ref (it's the genesis, no parent)
     GraphNode {
@@ -533,7 +666,7 @@ mod tests {
         let mut bad_node = GraphNode {
             id: "node1".to_string(),
             root_ref: "wrong_hash".to_string(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -580,7 +713,7 @@ mod tests {
         let node = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(42),
+            data: Expression::Literal(Literal::Int(42)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -601,7 +734,7 @@ mod tests {
         let node1 = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -612,7 +745,7 @@ mod tests {
         let node2 = GraphNode {
             id: "node2".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(2),
+            data: Expression::Literal(Literal::Int(2)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -640,7 +773,7 @@ mod tests {
         let node = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -662,7 +795,7 @@ mod tests {
         let node1 = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -673,7 +806,7 @@ mod tests {
         let node2 = GraphNode {
             id: "node2".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(2),
+            data: Expression::Literal(Literal::Int(2)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -684,7 +817,7 @@ mod tests {
         let node3 = GraphNode {
             id: "node3".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(3),
+            data: Expression::Literal(Literal::Int(3)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -701,8 +834,15 @@ mod tests {
         graph.link_nodes(hash2.clone(), hash3.clone(), EdgeType::Dependency).unwrap();
         
         // Try to create cycle: 3 -> 1
-        let result = graph.link_nodes(hash3, hash1, EdgeType::Dependency);
-        assert!(matches!(result, Err(GraphError::CycleDetected)));
+        let result = graph.link_nodes(hash3.clone(), hash1.clone(), EdgeType::Dependency);
+        match result {
+            Err(GraphError::CycleDetected { cycle }) => {
+                assert_eq!(cycle, vec![hash3.clone(), hash1.clone(), hash2, hash3]);
+            }
+            other => panic!("expected CycleDetected, got {:?}", other),
+        }
+        assert_eq!(graph.edges().len(), 2);
+        assert_eq!(graph.in_degree(&hash1, None), 0);
     }
 
     #[test]
@@ -713,7 +853,7 @@ mod tests {
         let node = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -747,7 +887,7 @@ mod tests {
         let node1 = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -758,7 +898,7 @@ mod tests {
         let node2 = GraphNode {
             id: "node2".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(2),
+            data: Expression::Literal(Literal::Int(2)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -776,6 +916,126 @@ mod tests {
         assert_eq!(graph.edges().len(), 0);
     }
 
+    #[test]
+    fn test_delete_node_keeps_index_in_step_with_edges() {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root_hash = graph.root_hash().clone();
+        let hashes: Vec<Hash> = (0..5)
+            .map(|i| {
+                graph
+                    .insert_node(GraphNode {
+                        id: format!("node{}", i),
+                        root_ref: root_hash.clone(),
+                        data: Expression::Literal(Literal::Int(i)),
+                        metadata: NodeMetadata {
+                            timestamp: 1,
+                            lineage_depth: 1,
+                            tags: vec![],
+                        },
+                    })
+                    .unwrap()
+            })
+            .collect();
+        for (i, from) in hashes.iter().enumerate() {
+            for to in &hashes[i + 1..] {
+                graph.link_nodes(from.clone(), to.clone(), EdgeType::Dependency).unwrap();
+            }
+        }
+        graph.link_nodes(hashes[0].clone(), hashes[4].clone(), EdgeType::Reference).unwrap();
+
+        // Each delete moves surviving edges into the holes it leaves
+        graph.delete_node(&hashes[1]).unwrap();
+        graph.delete_node(&hashes[3]).unwrap();
+
+        let mut edges: Vec<(Hash, Hash, EdgeType)> = graph
+            .edges()
+            .iter()
+            .map(|e| (e.from.clone(), e.to.clone(), e.edge_type))
+            .collect();
+        edges.sort();
+        let mut expected = vec![
+            (hashes[0].clone(), hashes[2].clone(), EdgeType::Dependency),
+            (hashes[0].clone(), hashes[4].clone(), EdgeType::Dependency),
+            (hashes[0].clone(), hashes[4].clone(), EdgeType::Reference),
+            (hashes[2].clone(), hashes[4].clone(), EdgeType::Dependency),
+        ];
+        expected.sort();
+        assert_eq!(edges, expected);
+        assert_eq!(graph.out_degree(&hashes[0], None), 3);
+        assert_eq!(graph.predecessors(&hashes[4], Some(EdgeType::Dependency)).len(), 2);
+
+        graph.delete_node(&hashes[4]).unwrap();
+        assert_eq!(graph.edges().len(), 1);
+        assert_eq!(graph.successors(&hashes[0], None), vec![&hashes[2]]);
+    }
+
+    /// A graph with two linked nodes, serialized before the shared AST
+    const LEGACY_GRAPH: &[u8] = include_bytes!("../tests/fixtures/legacy_graph.cbor");
+    
+    #[test]
+    fn test_loads_graph_encoded_with_legacy_ast() {
+        let root = "45e3f27670e8e8d984d97aa5c975e47d69db3dab5c58cc3ba04468b77fac4081".to_string();
+        let a = "dd678d661e4bede735020ea6f1c4894ccbdfb1fe9a36f9a036d4684e2b73108f".to_string();
+        let b = "1f92ffe4d3b39b965f711257e5c55fe1e08bbe94bb39ede20615d40839b8911b".to_string();
+        
+        let graph: GenesisGraph = ciborium::from_reader(LEGACY_GRAPH).unwrap();
+        assert_eq!(graph.root_hash(), &root);
+        assert_eq!(graph.get_node(&a).unwrap().data, Expression::Literal(Literal::Int(1)));
+        assert_eq!(
+            graph.get_node(&b).unwrap().data,
+            Expression::Lambda {
+                param: "x".to_string(),
+                body: Box::new(Expression::Apply {
+                    func: Box::new(Expression::Var("x".to_string())),
+                    arg: Box::new(Expression::Literal(Literal::Int(2))),
+                }),
+            }
+        );
+        assert_eq!(graph.successors(&a, None), vec![&b]);
+        
+        // Hashes are unchanged for expressions the legacy AST could hold
+        assert_eq!(compute_root_hash(&create_root_node()), root);
+        for hash in [&a, &b] {
+            assert_eq!(&compute_node_hash(graph.get_node(hash).unwrap()), hash);
+        }
+        
+        // Re-encoded with the shared AST, it still loads the same
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&graph, &mut bytes).unwrap();
+        let reloaded: GenesisGraph = ciborium::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(
+            reloaded.canonical_serialize().unwrap(),
+            graph.canonical_serialize().unwrap()
+        );
+    }
+    
+    #[test]
+    fn test_node_hash_ignores_record_field_order() {
+        let record = |fields: [(&str, i64); 2]| GraphNode {
+            id: "r".to_string(),
+            root_ref: String::new(),
+            data: Expression::Record(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), Expression::Literal(Literal::Int(*v))))
+                    .collect(),
+            ),
+            metadata: NodeMetadata {
+                timestamp: 1,
+                lineage_depth: 1,
+                tags: vec![],
+            },
+        };
+        assert_eq!(
+            compute_node_hash(&record([("a", 1), ("b", 2)])),
+            compute_node_hash(&record([("b", 2), ("a", 1)]))
+        );
+        assert_ne!(
+            compute_node_hash(&record([("a", 1), ("b", 2)])),
+            compute_node_hash(&record([("a", 2), ("b", 1)]))
+        );
+    }
+    
     #[test]
     fn test_canonical_serialization() {
         let root = create_root_node();
@@ -784,7 +1044,7 @@ mod tests {
         let node = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(42),
+            data: Expression::Literal(Literal::Int(42)),
             metadata: NodeMetadata {
                 timestamp: 1234567890,
                 lineage_depth: 1,
@@ -811,7 +1071,7 @@ mod tests {
         let node_a = GraphNode {
             id: "node_a".to_string(),
             root_ref: graph1.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: 1000,
                 lineage_depth: 1,
@@ -822,7 +1082,7 @@ mod tests {
         let node_b = GraphNode {
             id: "node_b".to_string(),
             root_ref: graph1.root_hash().clone(),
-            data: Expression::Literal(2),
+            data: Expression::Literal(Literal::Int(2)),
             metadata: NodeMetadata {
                 timestamp: 2000,
                 lineage_depth: 1,
@@ -852,7 +1112,7 @@ mod tests {
         let node1 = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -863,7 +1123,7 @@ mod tests {
         let node2 = GraphNode {
             id: "node2".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(2),
+            data: Expression::Literal(Literal::Int(2)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -892,7 +1152,7 @@ mod tests {
         let node1 = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -903,7 +1163,7 @@ mod tests {
         let node2 = GraphNode {
             id: "node2".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(2),
+            data: Expression::Literal(Literal::Int(2)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -931,7 +1191,7 @@ mod tests {
         let node1 = GraphNode {
             id: "node1".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -942,7 +1202,7 @@ mod tests {
         let node2 = GraphNode {
             id: "node2".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(2),
+            data: Expression::Literal(Literal::Int(2)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 2,
@@ -971,7 +1231,7 @@ mod tests {
         let node_c = GraphNode {
             id: "c_node".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(3),
+            data: Expression::Literal(Literal::Int(3)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -982,7 +1242,7 @@ mod tests {
         let node_a = GraphNode {
             id: "a_node".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(1),
+            data: Expression::Literal(Literal::Int(1)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -993,7 +1253,7 @@ mod tests {
         let node_b = GraphNode {
             id: "b_node".to_string(),
             root_ref: graph.root_hash().clone(),
-            data: Expression::Literal(2),
+            data: Expression::Literal(Literal::Int(2)),
             metadata: NodeMetadata {
                 timestamp: current_timestamp(),
                 lineage_depth: 1,
@@ -1031,7 +1291,7 @@ mod tests {
                 root_ref: graph.root_hash().clone(),
                 data: Expression::Let {
                     name: format!("x{}", i),
-                    value: Box::new(Expression::Literal(i)),
+                    value: Box::new(Expression::Literal(Literal::Int(i))),
                     body: Box::new(Expression::Var(format!("x{}", i))),
                 },
                 metadata: NodeMetadata {
@@ -1094,4 +1354,113 @@ mod tests {
         
         println!("\n=== All comprehensive tests passed ===");
     }
+
+    fn leaf(graph: &GenesisGraph, id: &str) -> GraphNode {
+        GraphNode {
+            id: id.to_string(),
+            root_ref: graph.root_hash().clone(),
+            data: Expression::Var(id.to_string()),
+            metadata: NodeMetadata {
+                timestamp: 1,
+                lineage_depth: 1,
+                tags: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn test_adjacency_queries() {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root = graph.root_hash().clone();
+        let a = graph.insert_node(leaf(&graph, "a")).unwrap();
+        let b = graph.insert_node(leaf(&graph, "b")).unwrap();
+
+        graph.link_nodes(root.clone(), a.clone(), EdgeType::Derivation).unwrap();
+        graph.link_nodes(root.clone(), b.clone(), EdgeType::Reference).unwrap();
+        graph.link_nodes(a.clone(), b.clone(), EdgeType::Derivation).unwrap();
+        graph.link_nodes(a.clone(), b.clone(), EdgeType::Dependency).unwrap();
+
+        assert_eq!(graph.successors(&root, Some(EdgeType::Derivation)), vec![&a]);
+        assert_eq!(graph.successors(&root, None).len(), 2);
+        assert_eq!(graph.predecessors(&b, Some(EdgeType::Reference)), vec![&root]);
+        assert!(graph.predecessors(&root, None).is_empty());
+        assert_eq!(graph.out_degree(&a, None), 2);
+        assert_eq!(graph.out_degree(&a, Some(EdgeType::Reference)), 0);
+        assert_eq!(graph.in_degree(&b, None), 3);
+        assert_eq!(graph.in_degree(&b, Some(EdgeType::Derivation)), 1);
+
+        // A rejected link leaves the indexes untouched
+        assert!(graph.link_nodes(b.clone(), root.clone(), EdgeType::Reference).is_err());
+        assert_eq!(graph.out_degree(&b, None), 0);
+        assert_eq!(graph.in_degree(&root, None), 0);
+
+        graph.delete_node(&a).unwrap();
+        assert_eq!(graph.successors(&root, None), vec![&b]);
+        assert_eq!(graph.predecessors(&b, None), vec![&root]);
+        assert_eq!(graph.edges().len(), 1);
+    }
+
+    #[test]
+    fn test_adjacency_rebuilt_on_deserialize() {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root = graph.root_hash().clone();
+        let a = graph.insert_node(leaf(&graph, "a")).unwrap();
+        graph.link_nodes(root.clone(), a.clone(), EdgeType::Derivation).unwrap();
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&graph, &mut bytes).unwrap();
+        let restored: GenesisGraph = ciborium::from_reader(bytes.as_slice()).unwrap();
+
+        assert_eq!(restored.predecessors(&a, Some(EdgeType::Derivation)), vec![&root]);
+        assert_eq!(restored.get_lineage(&a).unwrap(), vec![root, a]);
+        assert_eq!(
+            restored.canonical_serialize().unwrap(),
+            graph.canonical_serialize().unwrap()
+        );
+    }
+
+    /// Encode `graph` with its edges replaced by `edges` and load it back.
+    fn load_with_edges(
+        graph: &GenesisGraph,
+        edges: &[(&Hash, &Hash)],
+    ) -> Result<GenesisGraph, String> {
+        let mut value = ciborium::Value::serialized(graph).unwrap();
+        let edges = edges
+            .iter()
+            .map(|(from, to)| GraphEdge {
+                from: (*from).clone(),
+                to: (*to).clone(),
+                edge_type: EdgeType::Dependency,
+            })
+            .collect::<Vec<_>>();
+        let map = value.as_map_mut().unwrap();
+        for (key, field) in map.iter_mut() {
+            if key.as_text() == Some("edges") {
+                *field = ciborium::Value::serialized(&edges).unwrap();
+            }
+        }
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&value, &mut bytes).unwrap();
+        ciborium::from_reader::<GenesisGraph, _>(bytes.as_slice()).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_edges() {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root = graph.root_hash().clone();
+        let a = graph.insert_node(leaf(&graph, "a")).unwrap();
+        let b = graph.insert_node(leaf(&graph, "b")).unwrap();
+        let missing = "0".repeat(64);
+
+        assert!(load_with_edges(&graph, &[(&root, &a), (&a, &b)]).is_ok());
+
+        let err = load_with_edges(&graph, &[(&a, &a)]).unwrap_err();
+        assert!(err.contains("Self-loop forbidden"), "{}", err);
+
+        let err = load_with_edges(&graph, &[(&root, &missing)]).unwrap_err();
+        assert!(err.contains(&format!("Node not found: {}", missing)), "{}", err);
+
+        let err = load_with_edges(&graph, &[(&root, &a), (&a, &b), (&b, &a)]).unwrap_err();
+        assert!(err.contains("Cycle detected"), "{}", err);
+    }
 }
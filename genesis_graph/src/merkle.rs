@@ -0,0 +1,357 @@
+// ============================================================================
+// Merkle Commitment and Inclusion Proofs
+// ============================================================================
+//
+// A graph commits to its state with one Merkle root over a leaf per node
+// and a leaf per edge:
+//
+//   node leaf = SHA-256(0x00 | "GlyphV1:MerkleNode:" | node hash)
+//   edge leaf = SHA-256(0x00 | "GlyphV1:MerkleEdge:" | canonical CBOR(edge))
+//   interior  = SHA-256(0x01 | left | right)
+//
+// Leaves are sorted by value, so the root does not depend on insertion
+// order. A level with an odd number of entries promotes its last entry
+// unchanged rather than pairing it with itself. The 0x00/0x01 prefixes keep
+// a leaf from ever being passed off as an interior node.
+//
+// Node leaves commit to node hashes, which already cover the node's whole
+// content, so a node plus the sibling hashes on its path is enough for a
+// third party to check membership against a (signed) root.
+
+use crate::{
+    compute_node_hash, compute_root_hash, GenesisGraph, GraphEdge, GraphError, GraphNode, Hash,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const LEAF_PREFIX: u8 = 0x00;
+const INTERIOR_PREFIX: u8 = 0x01;
+const NODE_TAG: &[u8] = b"GlyphV1:MerkleNode:";
+const EDGE_TAG: &[u8] = b"GlyphV1:MerkleEdge:";
+
+pub type Digest32 = [u8; 32];
+
+/// Which side of the running hash a proof sibling sits on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    pub side: Side,
+    pub hash: Hash,
+}
+
+/// The sibling hashes from a node's leaf up to the Merkle root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeProof {
+    pub path: Vec<ProofStep>,
+}
+
+// ============================================================================
+// Tree
+// ============================================================================
+
+/// A Merkle tree over sorted leaves.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// `levels[0]` holds the leaves; the last level holds the root.
+    levels: Vec<Vec<Digest32>>,
+}
+
+impl MerkleTree {
+    pub fn from_leaves(mut leaves: Vec<Digest32>) -> Self {
+        leaves.sort_unstable();
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => interior_hash(left, right),
+                    [lone] => *lone,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree { levels }
+    }
+
+    /// The root, or the hash of the empty string for a tree with no leaves.
+    pub fn root(&self) -> Digest32 {
+        match self.levels.last().and_then(|level| level.first()) {
+            Some(root) => *root,
+            None => Sha256::digest([]).into(),
+        }
+    }
+
+    pub fn root_hex(&self) -> Hash {
+        hex::encode(self.root())
+    }
+
+    pub fn contains(&self, leaf: &Digest32) -> bool {
+        self.levels[0].binary_search(leaf).is_ok()
+    }
+
+    /// The path from `leaf` to the root, if `leaf` is in the tree.
+    pub fn proof(&self, leaf: &Digest32) -> Option<NodeProof> {
+        let mut index = self.levels[0].binary_search(leaf).ok()?;
+        let mut path = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            let step = if index % 2 == 1 {
+                Some((Side::Left, level[index - 1]))
+            } else {
+                level.get(index + 1).map(|right| (Side::Right, *right))
+            };
+            if let Some((side, hash)) = step {
+                path.push(ProofStep {
+                    side,
+                    hash: hex::encode(hash),
+                });
+            }
+            index /= 2;
+        }
+        Some(NodeProof { path })
+    }
+}
+
+pub fn node_leaf(node_hash: &Hash) -> Digest32 {
+    leaf_hash(NODE_TAG, node_hash.as_bytes())
+}
+
+pub fn edge_leaf(edge: &GraphEdge) -> Digest32 {
+    let buffer = capsule_core::canonical_cbor(edge).expect("Edge serialization failed");
+    leaf_hash(EDGE_TAG, &buffer)
+}
+
+/// A domain-separated leaf hash; `tag` names what the leaf commits to.
+pub fn leaf_hash(tag: &[u8], data: &[u8]) -> Digest32 {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(tag);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+fn interior_hash(left: &Digest32, right: &Digest32) -> Digest32 {
+    let mut hasher = Sha256::new();
+    hasher.update([INTERIOR_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// The tree behind [`GenesisGraph::merkle_root`], for graphs held some
+/// other way: `node_hashes` are content hashes, as `compute_node_hash` and
+/// `compute_root_hash` give them, and edges refer to the same hashes.
+pub fn graph_tree<'a>(
+    node_hashes: impl Iterator<Item = &'a Hash>,
+    edges: &[GraphEdge],
+) -> MerkleTree {
+    let leaves = node_hashes
+        .map(node_leaf)
+        .chain(edges.iter().map(edge_leaf))
+        .collect();
+    MerkleTree::from_leaves(leaves)
+}
+
+// ============================================================================
+// Graph Commitments
+// ============================================================================
+
+impl GenesisGraph {
+    /// The Merkle root committing to every node and edge.
+    pub fn merkle_root(&self) -> Hash {
+        graph_tree(self.nodes.keys(), &self.edges).root_hex()
+    }
+
+    /// Prove that the node `hash` is part of the graph at [`Self::merkle_root`].
+    pub fn prove_node(&self, hash: &Hash) -> Result<NodeProof, GraphError> {
+        if !self.nodes.contains_key(hash) {
+            return Err(GraphError::NodeNotFound(hash.clone()));
+        }
+        Ok(graph_tree(self.nodes.keys(), &self.edges)
+            .proof(&node_leaf(hash))
+            .expect("every node has a leaf"))
+    }
+}
+
+/// Check that `node` belongs to the graph committed to by `root`. A node
+/// with an empty `root_ref` is checked as the graph's root node.
+pub fn verify_node_proof(root: &Hash, node: &GraphNode, proof: &NodeProof) -> bool {
+    let node_hash = if node.root_ref.is_empty() {
+        compute_root_hash(node)
+    } else {
+        compute_node_hash(node)
+    };
+
+    let mut current = node_leaf(&node_hash);
+    for step in &proof.path {
+        let Some(sibling) = decode_digest(&step.hash) else {
+            return false;
+        };
+        current = match step.side {
+            Side::Left => interior_hash(&sibling, &current),
+            Side::Right => interior_hash(&current, &sibling),
+        };
+    }
+    hex::encode(current) == *root
+}
+
+fn decode_digest(hex_hash: &str) -> Option<Digest32> {
+    hex::decode(hex_hash).ok()?.try_into().ok()
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_root_node, EdgeType, Expression, GraphStore, Literal, NodeMetadata};
+
+    fn node(graph: &GenesisGraph, i: i64) -> GraphNode {
+        GraphNode {
+            id: format!("n{}", i),
+            root_ref: graph.root_hash().clone(),
+            data: Expression::Literal(Literal::Int(i)),
+            metadata: NodeMetadata {
+                timestamp: 1,
+                lineage_depth: 1,
+                tags: vec![],
+            },
+        }
+    }
+
+    fn chain(len: i64) -> (GenesisGraph, Vec<Hash>) {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let mut hashes = vec![graph.root_hash().clone()];
+        for i in 1..=len {
+            let hash = graph.insert_node(node(&graph, i)).unwrap();
+            graph
+                .link_nodes(
+                    hashes.last().unwrap().clone(),
+                    hash.clone(),
+                    EdgeType::Derivation,
+                )
+                .unwrap();
+            hashes.push(hash);
+        }
+        (graph, hashes)
+    }
+
+    #[test]
+    fn test_edge_leaf_hashes_deterministic_cbor() {
+        let edge = GraphEdge {
+            from: "a".to_string(),
+            to: "b".to_string(),
+            edge_type: EdgeType::Reference,
+        };
+        // Keys sorted by encoded length, then bytes: "to", "from", "edge_type"
+        let mut cbor = vec![0xa3, 0x62];
+        cbor.extend_from_slice(b"to");
+        cbor.push(0x61);
+        cbor.push(b'b');
+        cbor.push(0x64);
+        cbor.extend_from_slice(b"from");
+        cbor.push(0x61);
+        cbor.push(b'a');
+        cbor.push(0x69);
+        cbor.extend_from_slice(b"edge_type");
+        cbor.push(0x69);
+        cbor.extend_from_slice(b"Reference");
+        assert_eq!(edge_leaf(&edge), leaf_hash(EDGE_TAG, &cbor));
+    }
+
+    #[test]
+    fn test_every_node_proves_membership() {
+        // Sizes around powers of two exercise promoted odd entries
+        for len in [0, 1, 2, 3, 6, 7] {
+            let (graph, hashes) = chain(len);
+            let root = graph.merkle_root();
+            for hash in &hashes {
+                let proof = graph.prove_node(hash).unwrap();
+                let node = graph.get_node(hash).unwrap();
+                assert!(verify_node_proof(&root, node, &proof), "len {}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_root_commits_to_nodes_and_edges() {
+        let (graph, hashes) = chain(3);
+        let root = graph.merkle_root();
+
+        // Independent of insertion order
+        let mut reordered = GenesisGraph::new(create_root_node()).unwrap();
+        for hash in hashes.iter().skip(1).rev() {
+            reordered
+                .insert_node(graph.get_node(hash).unwrap().clone())
+                .unwrap();
+        }
+        for edge in graph.edges().iter().rev() {
+            reordered
+                .link_nodes(edge.from.clone(), edge.to.clone(), edge.edge_type)
+                .unwrap();
+        }
+        assert_eq!(reordered.merkle_root(), root);
+
+        // An extra edge changes the root
+        let mut linked = graph.clone();
+        linked
+            .link_nodes(hashes[0].clone(), hashes[3].clone(), EdgeType::Reference)
+            .unwrap();
+        assert_ne!(linked.merkle_root(), root);
+    }
+
+    #[test]
+    fn test_tampered_proofs_fail() {
+        let (graph, hashes) = chain(5);
+        let root = graph.merkle_root();
+        let proof = graph.prove_node(&hashes[2]).unwrap();
+        let node = graph.get_node(&hashes[2]).unwrap();
+
+        let mut changed = node.clone();
+        changed.metadata.tags.push("forged".to_string());
+        assert!(!verify_node_proof(&root, &changed, &proof));
+
+        let mut flipped = proof.clone();
+        flipped.path[0].side = match flipped.path[0].side {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        };
+        assert!(!verify_node_proof(&root, node, &flipped));
+
+        let mut truncated = proof.clone();
+        truncated.path.pop();
+        assert!(!verify_node_proof(&root, node, &truncated));
+
+        let other = graph.get_node(&hashes[3]).unwrap();
+        assert!(!verify_node_proof(&root, other, &proof));
+
+        assert!(matches!(
+            graph.prove_node(&"00".repeat(32)),
+            Err(GraphError::NodeNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_store_matches_in_memory_graph() {
+        let (graph, hashes) = chain(4);
+        let dir = std::env::temp_dir().join(format!("genesis_merkle_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = GraphStore::create_from_graph(&dir, &graph).unwrap();
+
+        assert_eq!(store.merkle_root(), graph.merkle_root());
+        assert_eq!(
+            store.prove_node(&hashes[1]).unwrap(),
+            graph.prove_node(&hashes[1]).unwrap()
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
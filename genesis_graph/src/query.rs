@@ -0,0 +1,1134 @@
+// ============================================================================
+// Graph Queries
+// ============================================================================
+//
+// A query is a pipeline of steps over a set of nodes. It starts from every
+// node in the graph; `where` steps filter the set, `out`/`in` steps replace
+// it with the nodes reached along edges, and an optional `return` picks the
+// fields reported for each node. The text form mirrors the builder:
+//
+//   nodes where id = "X"
+//   | out Derivation 1..
+//   | where tag = "audio"
+//   | return id, hash, depth
+//
+// Predicates:
+//
+//   tag = "t"            node carries tag `t`
+//   id = "x" | id ^= "x" id equals / starts with `x`
+//   hash ^= "ab12"       node hash starts with the hex prefix
+//   depth <op> n         lineage_depth compared with =, !=, <, <=, >, >=
+//   shape = <pattern>    expression shape, e.g. `apply(lambda, _)`
+//   not p | p and q | p or q | (p)
+//
+// Shape patterns are `_`, `literal`, `literal(n)` (an integer), `var`,
+// `var("x")`, `lambda`, `lambda(body)`, `apply`, `apply(func, arg)`,
+// `linear_apply`, `linear_apply(func, arg)`, `let`, `let(value, body)`,
+// `match`, `match(scrutinee)`, `tuple`, `tuple(item, ...)`, `list`,
+// `list(item, ...)` and `record`; a bare kind matches any children. These
+// are the names `return shape` reports.
+//
+// Traversals take an optional edge type and a depth bound `n`, `n..m` or
+// `n..` (default 1). Depth is the shortest distance from the current set,
+// so `out 0..` keeps the starting nodes as well as everything below them.
+
+use crate::{EdgeType, Expression, GenesisGraph, GraphError, GraphNode, Hash, Literal};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::str::FromStr;
+
+// ============================================================================
+// Query Model
+// ============================================================================
+
+/// A filter on individual nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    Tag(String),
+    Id(String),
+    IdPrefix(String),
+    HashPrefix(String),
+    LineageDepth(Comparison, u32),
+    Shape(ShapePattern),
+    Not(Box<Predicate>),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A pattern over the outer structure of a node's expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShapePattern {
+    Any,
+    Literal(Option<i64>),
+    Var(Option<String>),
+    Lambda {
+        body: Box<ShapePattern>,
+    },
+    Apply {
+        func: Box<ShapePattern>,
+        arg: Box<ShapePattern>,
+    },
+    LinearApply {
+        func: Box<ShapePattern>,
+        arg: Box<ShapePattern>,
+    },
+    Let {
+        value: Box<ShapePattern>,
+        body: Box<ShapePattern>,
+    },
+    /// Matches on the scrutinee only, whatever the arms.
+    Match {
+        expr: Box<ShapePattern>,
+    },
+    /// `None` matches any items; `Some` matches exactly that many.
+    Tuple(Option<Vec<ShapePattern>>),
+    List(Option<Vec<ShapePattern>>),
+    Record,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Follow edges from source to target.
+    Out,
+    /// Follow edges from target back to source.
+    In,
+}
+
+/// Move along edges, keeping the nodes whose distance from the current
+/// set lies in `min_depth..=max_depth` (`None` is unbounded).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Traversal {
+    pub direction: Direction,
+    pub edge_type: Option<EdgeType>,
+    pub min_depth: u32,
+    pub max_depth: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Filter(Predicate),
+    Traverse(Traversal),
+}
+
+/// A node field reported by a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Hash,
+    Id,
+    Tags,
+    LineageDepth,
+    Timestamp,
+    Shape,
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub steps: Vec<Step>,
+    pub fields: Vec<Field>,
+}
+
+// ============================================================================
+// Query Results
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Text(String),
+    Number(u64),
+    List(Vec<String>),
+    Expression(Expression),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub hash: Hash,
+    /// One value per field of the query, in order.
+    pub values: Vec<FieldValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryResult {
+    pub fields: Vec<Field>,
+    /// Matching nodes, ordered by node id and then hash.
+    pub rows: Vec<Row>,
+}
+
+impl QueryResult {
+    pub fn hashes(&self) -> Vec<&Hash> {
+        self.rows.iter().map(|row| &row.hash).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+// ============================================================================
+// Builder
+// ============================================================================
+
+impl Predicate {
+    pub fn tag(tag: &str) -> Self {
+        Predicate::Tag(tag.to_string())
+    }
+
+    pub fn id(id: &str) -> Self {
+        Predicate::Id(id.to_string())
+    }
+
+    pub fn id_prefix(prefix: &str) -> Self {
+        Predicate::IdPrefix(prefix.to_string())
+    }
+
+    pub fn and(self, other: Predicate) -> Self {
+        Predicate::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Predicate) -> Self {
+        Predicate::Or(Box::new(self), Box::new(other))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        Predicate::Not(Box::new(self))
+    }
+}
+
+impl Traversal {
+    /// Direct successors, along edges of `edge_type` if given.
+    pub fn out(edge_type: Option<EdgeType>) -> Self {
+        Traversal {
+            direction: Direction::Out,
+            edge_type,
+            min_depth: 1,
+            max_depth: Some(1),
+        }
+    }
+
+    /// Direct predecessors, along edges of `edge_type` if given.
+    pub fn incoming(edge_type: Option<EdgeType>) -> Self {
+        Traversal {
+            direction: Direction::In,
+            ..Traversal::out(edge_type)
+        }
+    }
+
+    pub fn depth(mut self, min_depth: u32, max_depth: Option<u32>) -> Self {
+        self.min_depth = min_depth;
+        self.max_depth = max_depth;
+        self
+    }
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query::new()
+    }
+}
+
+impl Query {
+    /// A query over every node, reporting hash and id.
+    pub fn new() -> Self {
+        Query {
+            steps: Vec::new(),
+            fields: vec![Field::Hash, Field::Id],
+        }
+    }
+
+    pub fn filter(mut self, predicate: Predicate) -> Self {
+        self.steps.push(Step::Filter(predicate));
+        self
+    }
+
+    pub fn traverse(mut self, traversal: Traversal) -> Self {
+        self.steps.push(Step::Traverse(traversal));
+        self
+    }
+
+    pub fn project(mut self, fields: Vec<Field>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Parse the text form described at the top of this module.
+    pub fn parse(text: &str) -> Result<Self, GraphError> {
+        Parser::new(text)?.query()
+    }
+
+    pub fn run(&self, graph: &GenesisGraph) -> QueryResult {
+        let mut current: BTreeSet<&Hash> = graph.nodes.keys().collect();
+        for step in &self.steps {
+            current = match step {
+                Step::Filter(predicate) => current
+                    .into_iter()
+                    .filter(|hash| predicate.matches(hash, &graph.nodes[*hash]))
+                    .collect(),
+                Step::Traverse(traversal) => traversal.apply(graph, &current),
+            };
+        }
+
+        let mut matched: Vec<(&Hash, &GraphNode)> = current
+            .into_iter()
+            .map(|hash| (hash, &graph.nodes[hash]))
+            .collect();
+        matched.sort_by(|a, b| a.1.id.cmp(&b.1.id).then_with(|| a.0.cmp(b.0)));
+
+        QueryResult {
+            fields: self.fields.clone(),
+            rows: matched
+                .into_iter()
+                .map(|(hash, node)| Row {
+                    hash: hash.clone(),
+                    values: self.fields.iter().map(|f| f.value(hash, node)).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = GraphError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Query::parse(text)
+    }
+}
+
+impl GenesisGraph {
+    /// Parse and run a query in the text syntax of [`Query::parse`].
+    pub fn query(&self, text: &str) -> Result<QueryResult, GraphError> {
+        Ok(Query::parse(text)?.run(self))
+    }
+}
+
+// ============================================================================
+// Evaluation
+// ============================================================================
+
+impl Predicate {
+    pub fn matches(&self, hash: &Hash, node: &GraphNode) -> bool {
+        match self {
+            Predicate::Tag(tag) => node.metadata.tags.contains(tag),
+            Predicate::Id(id) => node.id == *id,
+            Predicate::IdPrefix(prefix) => node.id.starts_with(prefix.as_str()),
+            Predicate::HashPrefix(prefix) => hash.starts_with(prefix.as_str()),
+            Predicate::LineageDepth(op, depth) => op.holds(node.metadata.lineage_depth, *depth),
+            Predicate::Shape(pattern) => pattern.matches(&node.data),
+            Predicate::Not(inner) => !inner.matches(hash, node),
+            Predicate::And(a, b) => a.matches(hash, node) && b.matches(hash, node),
+            Predicate::Or(a, b) => a.matches(hash, node) || b.matches(hash, node),
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(self, left: u32, right: u32) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+impl ShapePattern {
+    pub fn matches(&self, expr: &Expression) -> bool {
+        match (self, expr) {
+            (ShapePattern::Any, _) => true,
+            (ShapePattern::Literal(want), Expression::Literal(literal)) => match want {
+                None => true,
+                Some(w) => *literal == Literal::Int(*w),
+            },
+            (ShapePattern::Var(want), Expression::Var(name)) => {
+                want.as_ref().is_none_or(|w| w == name)
+            }
+            (ShapePattern::Lambda { body }, Expression::Lambda { body: b, .. }) => body.matches(b),
+            (ShapePattern::Apply { func, arg }, Expression::Apply { func: f, arg: a }) => {
+                func.matches(f) && arg.matches(a)
+            }
+            (
+                ShapePattern::Let { value, body },
+                Expression::Let {
+                    value: v, body: b, ..
+                },
+            ) => value.matches(v) && body.matches(b),
+            (
+                ShapePattern::LinearApply { func, arg },
+                Expression::LinearApply { func: f, arg: a },
+            ) => func.matches(f) && arg.matches(a),
+            (ShapePattern::Match { expr }, Expression::Match { expr: e, .. }) => expr.matches(e),
+            (ShapePattern::Tuple(items), Expression::Tuple(exprs))
+            | (ShapePattern::List(items), Expression::List(exprs)) => {
+                items.as_ref().is_none_or(|items| {
+                    items.len() == exprs.len()
+                        && items.iter().zip(exprs).all(|(item, e)| item.matches(e))
+                })
+            }
+            (ShapePattern::Record, Expression::Record(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Traversal {
+    fn apply<'a>(&self, graph: &'a GenesisGraph, start: &BTreeSet<&'a Hash>) -> BTreeSet<&'a Hash> {
+        let mut depth: HashMap<&Hash, u32> = start.iter().map(|h| (*h, 0)).collect();
+        let mut queue: VecDeque<&Hash> = start.iter().copied().collect();
+        let mut reached = BTreeSet::new();
+
+        while let Some(current) = queue.pop_front() {
+            let d = depth[current];
+            if d >= self.min_depth {
+                reached.insert(current);
+            }
+            if self.max_depth.is_some_and(|max| d >= max) {
+                continue;
+            }
+            let next = match self.direction {
+                Direction::Out => graph.successors(current, self.edge_type),
+                Direction::In => graph.predecessors(current, self.edge_type),
+            };
+            for hash in next {
+                if !depth.contains_key(hash) {
+                    depth.insert(hash, d + 1);
+                    queue.push_back(hash);
+                }
+            }
+        }
+        reached
+    }
+}
+
+impl Field {
+    fn value(self, hash: &Hash, node: &GraphNode) -> FieldValue {
+        match self {
+            Field::Hash => FieldValue::Text(hash.clone()),
+            Field::Id => FieldValue::Text(node.id.clone()),
+            Field::Tags => FieldValue::List(node.metadata.tags.clone()),
+            Field::LineageDepth => FieldValue::Number(node.metadata.lineage_depth as u64),
+            Field::Timestamp => FieldValue::Number(node.metadata.timestamp),
+            Field::Shape => FieldValue::Text(shape_name(&node.data).to_string()),
+            Field::Data => FieldValue::Expression(node.data.clone()),
+        }
+    }
+}
+
+fn shape_name(expr: &Expression) -> &'static str {
+    match expr {
+        Expression::Literal(_) => "literal",
+        Expression::Var(_) => "var",
+        Expression::Lambda { .. } => "lambda",
+        Expression::Apply { .. } => "apply",
+        Expression::Let { .. } => "let",
+        Expression::LinearApply { .. } => "linear_apply",
+        Expression::Match { .. } => "match",
+        Expression::Tuple(_) => "tuple",
+        Expression::List(_) => "list",
+        Expression::Record(_) => "record",
+    }
+}
+
+// ============================================================================
+// Text Syntax
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Str(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 12] = [
+    "..", "^=", "!=", "<=", ">=", "=", "<", ">", "|", ",", "(", ")",
+];
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, GraphError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(pos, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&(_, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push((pos, Token::Ident(ident)));
+        } else if c.is_ascii_digit()
+            || (c == '-' && text[pos + 1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            chars.next();
+            let mut digits = c.to_string();
+            while let Some(&(_, c)) = chars.peek().filter(|(_, c)| c.is_ascii_digit()) {
+                digits.push(c);
+                chars.next();
+            }
+            let value = digits
+                .parse()
+                .map_err(|_| query_error(pos, format!("integer {} out of range", digits)))?;
+            tokens.push((pos, Token::Int(value)));
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c @ ('"' | '\\'))) => value.push(c),
+                        _ => return Err(query_error(pos, "bad escape in string".to_string())),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err(query_error(pos, "unterminated string".to_string())),
+                }
+            }
+            tokens.push((pos, Token::Str(value)));
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| text[pos..].starts_with(**s))
+                .ok_or_else(|| query_error(pos, format!("unexpected character '{}'", c)))?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push((pos, Token::Symbol(symbol)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn query_error(position: usize, message: String) -> GraphError {
+    GraphError::InvalidQuery { position, message }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self, GraphError> {
+        Ok(Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            end: text.len(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(p, _)| *p)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, GraphError> {
+        Err(query_error(self.offset(), message.into()))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(s)) if s == keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), GraphError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            self.error(format!("expected '{}'", symbol))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), GraphError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            self.error(format!("expected '{}'", keyword))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, GraphError> {
+        match self.peek() {
+            Some(Token::Str(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => self.error("expected a string"),
+        }
+    }
+
+    fn int(&mut self) -> Result<i64, GraphError> {
+        match self.peek() {
+            Some(Token::Int(n)) => {
+                let n = *n;
+                self.pos += 1;
+                Ok(n)
+            }
+            _ => self.error("expected an integer"),
+        }
+    }
+
+    fn count(&mut self) -> Result<u32, GraphError> {
+        let offset = self.offset();
+        u32::try_from(self.int()?)
+            .map_err(|_| query_error(offset, "expected a non-negative count".to_string()))
+    }
+
+    // query := "nodes" ("where" pred)? ("|" stage)*
+    fn query(mut self) -> Result<Query, GraphError> {
+        self.expect_keyword("nodes")?;
+        let mut query = Query::new();
+        if self.eat_keyword("where") {
+            query = query.filter(self.predicate()?);
+        }
+
+        while self.eat_symbol("|") {
+            if self.eat_keyword("where") {
+                query = query.filter(self.predicate()?);
+            } else if self.eat_keyword("out") {
+                query = query.traverse(self.traversal(Direction::Out)?);
+            } else if self.eat_keyword("in") {
+                query = query.traverse(self.traversal(Direction::In)?);
+            } else if self.eat_keyword("return") {
+                query = query.project(self.fields()?);
+                break;
+            } else {
+                return self.error("expected 'where', 'out', 'in' or 'return'");
+            }
+        }
+
+        if self.peek().is_some() {
+            return self.error("unexpected input after query");
+        }
+        Ok(query)
+    }
+
+    fn traversal(&mut self, direction: Direction) -> Result<Traversal, GraphError> {
+        let edge_type = match self.peek() {
+            Some(Token::Ident(name)) => {
+                let edge_type = match name.as_str() {
+                    "Dependency" => EdgeType::Dependency,
+                    "Derivation" => EdgeType::Derivation,
+                    "Reference" => EdgeType::Reference,
+                    _ => return self.error(format!("unknown edge type '{}'", name)),
+                };
+                self.pos += 1;
+                Some(edge_type)
+            }
+            _ => None,
+        };
+
+        let mut traversal = Traversal {
+            direction,
+            ..Traversal::out(edge_type)
+        };
+        if matches!(self.peek(), Some(Token::Int(_))) {
+            let min = self.count()?;
+            let max = if self.eat_symbol("..") {
+                match self.peek() {
+                    Some(Token::Int(_)) => Some(self.count()?),
+                    _ => None,
+                }
+            } else {
+                Some(min)
+            };
+            if max.is_some_and(|max| max < min) {
+                return self.error("depth range is empty");
+            }
+            traversal = traversal.depth(min, max);
+        }
+        Ok(traversal)
+    }
+
+    fn fields(&mut self) -> Result<Vec<Field>, GraphError> {
+        let mut fields = Vec::new();
+        loop {
+            let field = match self.peek() {
+                Some(Token::Ident(name)) => match name.as_str() {
+                    "hash" => Field::Hash,
+                    "id" => Field::Id,
+                    "tags" => Field::Tags,
+                    "depth" => Field::LineageDepth,
+                    "timestamp" => Field::Timestamp,
+                    "shape" => Field::Shape,
+                    "data" => Field::Data,
+                    _ => return self.error(format!("unknown field '{}'", name)),
+                },
+                _ => return self.error("expected a field name"),
+            };
+            self.pos += 1;
+            fields.push(field);
+            if !self.eat_symbol(",") {
+                return Ok(fields);
+            }
+        }
+    }
+
+    // pred := conj ("or" conj)*
+    fn predicate(&mut self) -> Result<Predicate, GraphError> {
+        let mut predicate = self.conjunction()?;
+        while self.eat_keyword("or") {
+            predicate = predicate.or(self.conjunction()?);
+        }
+        Ok(predicate)
+    }
+
+    // conj := unary ("and" unary)*
+    fn conjunction(&mut self) -> Result<Predicate, GraphError> {
+        let mut predicate = self.unary()?;
+        while self.eat_keyword("and") {
+            predicate = predicate.and(self.unary()?);
+        }
+        Ok(predicate)
+    }
+
+    fn unary(&mut self) -> Result<Predicate, GraphError> {
+        if self.eat_keyword("not") {
+            return Ok(self.unary()?.not());
+        }
+        if self.eat_symbol("(") {
+            let predicate = self.predicate()?;
+            self.expect_symbol(")")?;
+            return Ok(predicate);
+        }
+
+        let Some(Token::Ident(subject)) = self.peek().cloned() else {
+            return self.error("expected a predicate");
+        };
+        self.pos += 1;
+        match subject.as_str() {
+            "tag" => {
+                self.expect_symbol("=")?;
+                Ok(Predicate::Tag(self.string()?))
+            }
+            "id" => {
+                if self.eat_symbol("^=") {
+                    Ok(Predicate::IdPrefix(self.string()?))
+                } else {
+                    self.expect_symbol("=")?;
+                    Ok(Predicate::Id(self.string()?))
+                }
+            }
+            "hash" => {
+                self.expect_symbol("^=")?;
+                Ok(Predicate::HashPrefix(self.string()?))
+            }
+            "depth" => {
+                let op = match self.next() {
+                    Some(Token::Symbol("=")) => Comparison::Eq,
+                    Some(Token::Symbol("!=")) => Comparison::Ne,
+                    Some(Token::Symbol("<")) => Comparison::Lt,
+                    Some(Token::Symbol("<=")) => Comparison::Le,
+                    Some(Token::Symbol(">")) => Comparison::Gt,
+                    Some(Token::Symbol(">=")) => Comparison::Ge,
+                    _ => {
+                        self.pos -= 1;
+                        return self.error("expected a comparison");
+                    }
+                };
+                Ok(Predicate::LineageDepth(op, self.count()?))
+            }
+            "shape" => {
+                self.expect_symbol("=")?;
+                Ok(Predicate::Shape(self.shape()?))
+            }
+            _ => {
+                self.pos -= 1;
+                self.error(format!("unknown predicate '{}'", subject))
+            }
+        }
+    }
+
+    fn shape(&mut self) -> Result<ShapePattern, GraphError> {
+        let Some(Token::Ident(kind)) = self.peek().cloned() else {
+            return self.error("expected a shape");
+        };
+        self.pos += 1;
+        let open = self.eat_symbol("(");
+
+        let pattern = match kind.as_str() {
+            "_" if !open => return Ok(ShapePattern::Any),
+            "literal" => ShapePattern::Literal(if open { Some(self.int()?) } else { None }),
+            "var" => ShapePattern::Var(if open { Some(self.string()?) } else { None }),
+            "lambda" => ShapePattern::Lambda {
+                body: Box::new(self.child_shape(open)?),
+            },
+            "apply" => {
+                let func = self.child_shape(open)?;
+                let arg = self.second_child_shape(open)?;
+                ShapePattern::Apply {
+                    func: Box::new(func),
+                    arg: Box::new(arg),
+                }
+            }
+            "linear_apply" => {
+                let func = self.child_shape(open)?;
+                let arg = self.second_child_shape(open)?;
+                ShapePattern::LinearApply {
+                    func: Box::new(func),
+                    arg: Box::new(arg),
+                }
+            }
+            "let" => {
+                let value = self.child_shape(open)?;
+                let body = self.second_child_shape(open)?;
+                ShapePattern::Let {
+                    value: Box::new(value),
+                    body: Box::new(body),
+                }
+            }
+            "match" => ShapePattern::Match {
+                expr: Box::new(self.child_shape(open)?),
+            },
+            "tuple" => ShapePattern::Tuple(self.item_shapes(open)?),
+            "list" => ShapePattern::List(self.item_shapes(open)?),
+            "record" if !open => return Ok(ShapePattern::Record),
+            _ => {
+                self.pos -= if open { 2 } else { 1 };
+                return self.error(format!("unknown shape '{}'", kind));
+            }
+        };
+        if open {
+            self.expect_symbol(")")?;
+        }
+        Ok(pattern)
+    }
+
+    fn child_shape(&mut self, open: bool) -> Result<ShapePattern, GraphError> {
+        if open {
+            self.shape()
+        } else {
+            Ok(ShapePattern::Any)
+        }
+    }
+
+    fn second_child_shape(&mut self, open: bool) -> Result<ShapePattern, GraphError> {
+        if open {
+            self.expect_symbol(",")?;
+        }
+        self.child_shape(open)
+    }
+
+    /// Comma-separated shapes up to, not including, the closing `)`.
+    fn item_shapes(&mut self, open: bool) -> Result<Option<Vec<ShapePattern>>, GraphError> {
+        if !open {
+            return Ok(None);
+        }
+        let mut items = Vec::new();
+        if self.peek() == Some(&Token::Symbol(")")) {
+            return Ok(Some(items));
+        }
+        loop {
+            items.push(self.shape()?);
+            if !self.eat_symbol(",") {
+                return Ok(Some(items));
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_root_node, NodeMetadata};
+
+    struct Fixture {
+        graph: GenesisGraph,
+        hashes: HashMap<&'static str, Hash>,
+    }
+
+    impl Fixture {
+        fn hash(&self, id: &str) -> &Hash {
+            &self.hashes[id]
+        }
+
+        fn ids(&self, result: &QueryResult) -> Vec<String> {
+            result
+                .rows
+                .iter()
+                .map(|row| self.graph.get_node(&row.hash).unwrap().id.clone())
+                .collect()
+        }
+    }
+
+    //   root -D-> x -D-> a1 -D-> a2
+    //             x -D-> v1
+    //             x -R-> r
+    //   a1 -Dep-> lib
+    fn fixture() -> Fixture {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let mut hashes = HashMap::new();
+        hashes.insert("⊙₀", graph.root_hash().clone());
+
+        let nodes: [(&str, u32, &[&str], Expression); 6] = [
+            ("x", 1, &["seed"], Expression::Var("x".to_string())),
+            (
+                "a1",
+                2,
+                &["audio"],
+                Expression::Apply {
+                    func: Box::new(Expression::Lambda {
+                        param: "y".to_string(),
+                        body: Box::new(Expression::Var("y".to_string())),
+                    }),
+                    arg: Box::new(Expression::Literal(Literal::Int(7))),
+                },
+            ),
+            (
+                "a2",
+                3,
+                &["audio", "final"],
+                Expression::Literal(Literal::Int(2)),
+            ),
+            ("v1", 2, &["video"], Expression::Literal(Literal::Int(3))),
+            ("r", 2, &["audio"], Expression::Literal(Literal::Int(4))),
+            ("lib", 1, &["audio"], Expression::Literal(Literal::Int(5))),
+        ];
+        for (id, depth, tags, data) in nodes {
+            let node = GraphNode {
+                id: id.to_string(),
+                root_ref: graph.root_hash().clone(),
+                data,
+                metadata: NodeMetadata {
+                    timestamp: 10,
+                    lineage_depth: depth,
+                    tags: tags.iter().map(|t| t.to_string()).collect(),
+                },
+            };
+            hashes.insert(id, graph.insert_node(node).unwrap());
+        }
+
+        let edges = [
+            ("⊙₀", "x", EdgeType::Derivation),
+            ("x", "a1", EdgeType::Derivation),
+            ("a1", "a2", EdgeType::Derivation),
+            ("x", "v1", EdgeType::Derivation),
+            ("x", "r", EdgeType::Reference),
+            ("a1", "lib", EdgeType::Dependency),
+        ];
+        for (from, to, edge_type) in edges {
+            graph
+                .link_nodes(hashes[from].clone(), hashes[to].clone(), edge_type)
+                .unwrap();
+        }
+        Fixture { graph, hashes }
+    }
+
+    #[test]
+    fn test_derivation_descendants_with_tag() {
+        let f = fixture();
+        let result = f
+            .graph
+            .query(r#"nodes where id = "x" | out Derivation 1.. | where tag = "audio""#)
+            .unwrap();
+        assert_eq!(f.ids(&result), vec!["a1", "a2"]);
+
+        let built = Query::new()
+            .filter(Predicate::id("x"))
+            .traverse(Traversal::out(Some(EdgeType::Derivation)).depth(1, None))
+            .filter(Predicate::tag("audio"))
+            .run(&f.graph);
+        assert_eq!(built, result);
+    }
+
+    #[test]
+    fn test_traversal_depth_bounds_and_direction() {
+        let f = fixture();
+        let ids = |text: &str| f.ids(&f.graph.query(text).unwrap());
+
+        assert_eq!(ids(r#"nodes where id = "x" | out"#), vec!["a1", "r", "v1"]);
+        assert_eq!(ids(r#"nodes where id = "x" | out 2"#), vec!["a2", "lib"]);
+        assert_eq!(
+            ids(r#"nodes where id = "x" | out Derivation 0..1"#),
+            vec!["a1", "v1", "x"]
+        );
+        assert_eq!(
+            ids(r#"nodes where id = "a2" | in 1.."#),
+            vec!["a1", "x", "⊙₀"]
+        );
+        assert_eq!(
+            ids(r#"nodes where id = "lib" | in Derivation"#),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_predicates() {
+        let f = fixture();
+        let ids = |text: &str| f.ids(&f.graph.query(text).unwrap());
+
+        assert_eq!(
+            ids(r#"nodes where tag = "audio" and depth >= 2 and not id ^= "a""#),
+            vec!["r"]
+        );
+        assert_eq!(
+            ids(r#"nodes where (id = "v1" or id = "lib") and depth < 2"#),
+            vec!["lib"]
+        );
+        assert_eq!(
+            ids("nodes where shape = apply(lambda(var), literal(7))"),
+            vec!["a1"]
+        );
+        assert_eq!(
+            ids("nodes where shape = apply(_, literal(8))"),
+            Vec::<String>::new()
+        );
+        assert_eq!(ids(r#"nodes where shape = var("x")"#), vec!["x"]);
+        assert_eq!(
+            ids("nodes where shape = literal and depth = 2"),
+            vec!["r", "v1"]
+        );
+
+        let prefix = &f.hash("lib")[..12];
+        assert_eq!(
+            ids(&format!(r#"nodes where hash ^= "{}""#, prefix)),
+            vec!["lib"]
+        );
+    }
+
+    #[test]
+    fn test_projection() {
+        let f = fixture();
+        let result = f
+            .graph
+            .query(r#"nodes where tag = "final" | return id, tags, depth, shape"#)
+            .unwrap();
+        assert_eq!(
+            result.fields,
+            vec![Field::Id, Field::Tags, Field::LineageDepth, Field::Shape]
+        );
+        assert_eq!(result.hashes(), vec![f.hash("a2")]);
+        assert_eq!(
+            result.rows[0].values,
+            vec![
+                FieldValue::Text("a2".to_string()),
+                FieldValue::List(vec!["audio".to_string(), "final".to_string()]),
+                FieldValue::Number(3),
+                FieldValue::Text("literal".to_string()),
+            ]
+        );
+
+        // Without a projection, rows carry hash and id
+        let result = f.graph.query(r#"nodes where id = "r""#).unwrap();
+        assert_eq!(
+            result.rows[0].values,
+            vec![
+                FieldValue::Text(f.hash("r").clone()),
+                FieldValue::Text("r".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_every_reported_shape_parses() {
+        let var = |name: &str| Box::new(Expression::Var(name.to_string()));
+        let int = |n: i64| Expression::Literal(Literal::Int(n));
+        let exprs = [
+            Expression::LinearApply {
+                func: var("f"),
+                arg: Box::new(int(1)),
+            },
+            Expression::Match {
+                expr: var("x"),
+                arms: vec![],
+            },
+            Expression::Tuple(vec![int(1), *var("y")]),
+            Expression::List(vec![]),
+            Expression::Record(vec![("a".to_string(), int(2))]),
+        ];
+
+        let shape = |text: &str| {
+            let query = Query::parse(&format!("nodes where shape = {}", text)).unwrap();
+            match &query.steps[..] {
+                [Step::Filter(Predicate::Shape(pattern))] => pattern.clone(),
+                other => panic!("expected a shape filter, got {:?}", other),
+            }
+        };
+        let matches = |text: &str, expr: &Expression| shape(text).matches(expr);
+
+        // Each name `return shape` reports selects exactly its own kind
+        for expr in &exprs {
+            let name = shape_name(expr);
+            let matched: Vec<&str> = exprs
+                .iter()
+                .filter(|e| matches(name, e))
+                .map(shape_name)
+                .collect();
+            assert_eq!(matched, vec![name]);
+        }
+
+        assert!(matches(r#"linear_apply(var("f"), literal(1))"#, &exprs[0]));
+        assert!(!matches("linear_apply(_, literal(2))", &exprs[0]));
+        assert!(matches(r#"match(var("x"))"#, &exprs[1]));
+        assert!(matches("tuple(literal(1), var)", &exprs[2]));
+        assert!(!matches("tuple(literal(1))", &exprs[2]));
+        assert!(matches("list()", &exprs[3]));
+        assert!(Query::parse("nodes where shape = record(_)").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors_report_position() {
+        let position = |text: &str| match Query::parse(text) {
+            Err(GraphError::InvalidQuery { position, .. }) => position,
+            other => panic!("expected InvalidQuery, got {:?}", other),
+        };
+
+        assert_eq!(position("select"), 0);
+        assert_eq!(position(r#"nodes where colour = "red""#), 12);
+        assert_eq!(position("nodes | out Sideways"), 12);
+        assert_eq!(position("nodes | out 3..1"), 16);
+        assert_eq!(position(r#"nodes where tag = "open"#), 18);
+        assert_eq!(position("nodes | return id, size"), 19);
+        assert_eq!(position("nodes | return id | out"), 18);
+        assert!("nodes | in Reference 2..".parse::<Query>().is_ok());
+    }
+}
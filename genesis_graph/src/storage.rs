@@ -0,0 +1,972 @@
+// ============================================================================
+// Append-Only On-Disk Graph Store
+// ============================================================================
+//
+// A store directory holds two files:
+//
+//   wal.log        the write-ahead log: a header, then one record per
+//                  operation. Node records carry the node's CBOR, so the log
+//                  is also where node bodies live.
+//   snapshot.cbor  the node index (hash -> log offset), edge list, pins and
+//                  tombstones as of some log length, written atomically.
+//
+// Opening a store reads the snapshot and replays only the log written after
+// it. Node bodies are read from the log on demand, so reopening a large
+// graph never decodes nodes it does not touch.
+//
+// Each record is `len: u32 LE | checksum: [u8; 4] | payload`, where the
+// checksum is the first 4 bytes of SHA-256(payload). A torn or corrupt
+// record ends the log: recovery truncates the file back to the last good
+// record, which is exactly the state the last acknowledged write left. A
+// record that is intact but cannot be applied was written by something other
+// than this store, so opening fails instead of discarding it.
+
+use crate::adjacency::Adjacency;
+use crate::gc::Tombstone;
+use crate::merkle::{graph_tree, node_leaf, NodeProof};
+use crate::{
+    compute_node_hash, compute_root_hash, EdgeType, GenesisGraph, GraphEdge, GraphError, GraphNode,
+    Hash,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const WAL_FILE: &str = "wal.log";
+const SNAPSHOT_FILE: &str = "snapshot.cbor";
+const WAL_MAGIC: &[u8; 8] = b"GGWAL\x00\x00\x01";
+const SNAPSHOT_MAGIC: &[u8; 8] = b"GGSNP\x00\x00\x01";
+const RECORD_HEADER_LEN: u64 = 8;
+const HASH_LEN: usize = 64;
+
+const OP_ROOT: u8 = 0;
+const OP_INSERT: u8 = 1;
+const OP_LINK: u8 = 2;
+const OP_DELETE: u8 = 3;
+const OP_PIN: u8 = 4;
+const OP_UNPIN: u8 = 5;
+const OP_TOMBSTONE: u8 = 6;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    wal_len: u64,
+    root_hash: Hash,
+    index: BTreeMap<Hash, u64>,
+    edges: Vec<GraphEdge>,
+    #[serde(default)]
+    pinned: BTreeSet<Hash>,
+    #[serde(default)]
+    tombstones: BTreeMap<Hash, Tombstone>,
+}
+
+/// A `GenesisGraph` kept on disk as a write-ahead log plus snapshots.
+pub struct GraphStore {
+    dir: PathBuf,
+    wal: File,
+    wal_len: u64,
+    root_hash: Hash,
+    /// Offset in the log of each live node's record.
+    index: HashMap<Hash, u64>,
+    edges: Vec<GraphEdge>,
+    adjacency: Adjacency,
+    pinned: BTreeSet<Hash>,
+    tombstones: BTreeMap<Hash, Tombstone>,
+    discarded_bytes: u64,
+    /// Set when a failed append could not be cut back off the log.
+    poisoned: bool,
+    /// Make the next append write only this many bytes, then fail.
+    #[cfg(test)]
+    short_write: Option<usize>,
+}
+
+impl GraphStore {
+    /// Create a store in `dir` holding a graph rooted at `root_node`.
+    pub fn create<P: AsRef<Path>>(dir: P, root_node: GraphNode) -> Result<Self, GraphError> {
+        Self::create_from_graph(dir, &GenesisGraph::new(root_node)?)
+    }
+
+    /// Create a store in `dir` holding a copy of `graph`.
+    pub fn create_from_graph<P: AsRef<Path>>(
+        dir: P,
+        graph: &GenesisGraph,
+    ) -> Result<Self, GraphError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+        let wal_path = dir.join(WAL_FILE);
+        if wal_path.exists() {
+            return Err(GraphError::CorruptStore(format!(
+                "{} already holds a graph store",
+                dir.display()
+            )));
+        }
+
+        let mut wal = OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .append(true)
+            .open(&wal_path)
+            .map_err(|e| io_error(&wal_path, e))?;
+        wal.write_all(WAL_MAGIC)
+            .map_err(|e| io_error(&wal_path, e))?;
+
+        let mut store = Self {
+            dir,
+            wal,
+            wal_len: WAL_MAGIC.len() as u64,
+            root_hash: graph.root_hash.clone(),
+            index: HashMap::new(),
+            edges: Vec::new(),
+            adjacency: Adjacency::default(),
+            pinned: BTreeSet::new(),
+            tombstones: BTreeMap::new(),
+            discarded_bytes: 0,
+            poisoned: false,
+            #[cfg(test)]
+            short_write: None,
+        };
+
+        let root = &graph.nodes[&graph.root_hash];
+        let offset = store.append(&node_payload(OP_ROOT, &graph.root_hash, root)?)?;
+        store.index.insert(graph.root_hash.clone(), offset);
+
+        let mut hashes: Vec<&Hash> = graph.nodes.keys().collect();
+        hashes.sort();
+        for hash in hashes.into_iter().filter(|h| **h != graph.root_hash) {
+            let offset = store.append(&node_payload(OP_INSERT, hash, &graph.nodes[hash])?)?;
+            store.index.insert(hash.clone(), offset);
+        }
+        for edge in &graph.edges {
+            store.append(&edge_payload(edge)?)?;
+        }
+        store.edges = graph.edges.clone();
+        store.adjacency = graph.adjacency.clone();
+        for hash in &graph.pinned {
+            store.append(&hash_payload(OP_PIN, hash))?;
+        }
+        for (hash, tombstone) in &graph.tombstones {
+            store.append(&tombstone_payload(hash, tombstone)?)?;
+        }
+        store.pinned = graph.pinned.clone();
+        store.tombstones = graph.tombstones.clone();
+
+        store.sync()?;
+        store.snapshot()?;
+        Ok(store)
+    }
+
+    /// Open the store in `dir`, recovering from any interrupted write.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, GraphError> {
+        let dir = dir.as_ref().to_path_buf();
+        let wal_path = dir.join(WAL_FILE);
+        let mut wal = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&wal_path)
+            .map_err(|e| io_error(&wal_path, e))?;
+        let file_len = wal.metadata().map_err(|e| io_error(&wal_path, e))?.len();
+
+        let mut magic = [0u8; 8];
+        wal.read_exact(&mut magic)
+            .map_err(|e| io_error(&wal_path, e))?;
+        if &magic != WAL_MAGIC {
+            return Err(GraphError::CorruptStore(format!(
+                "{} is not a graph log",
+                wal_path.display()
+            )));
+        }
+
+        let mut store = Self {
+            dir,
+            wal,
+            wal_len: WAL_MAGIC.len() as u64,
+            root_hash: Hash::new(),
+            index: HashMap::new(),
+            edges: Vec::new(),
+            adjacency: Adjacency::default(),
+            pinned: BTreeSet::new(),
+            tombstones: BTreeMap::new(),
+            discarded_bytes: 0,
+            poisoned: false,
+            #[cfg(test)]
+            short_write: None,
+        };
+
+        // A snapshot describing more log than survived cannot be trusted
+        if let Some(snapshot) = store.read_snapshot().filter(|s| s.wal_len <= file_len) {
+            store.wal_len = snapshot.wal_len;
+            store.root_hash = snapshot.root_hash;
+            store.index = snapshot.index.into_iter().collect();
+            store.edges = snapshot.edges;
+            store.adjacency = Adjacency::from_edges(&store.edges);
+            store.pinned = snapshot.pinned;
+            store.tombstones = snapshot.tombstones;
+        }
+
+        store.replay(file_len)?;
+        if store.root_hash.is_empty() {
+            return Err(GraphError::CorruptStore(
+                "graph log has no root record".to_string(),
+            ));
+        }
+        Ok(store)
+    }
+
+    // ========================================================================
+    // Accessors
+    // ========================================================================
+
+    pub fn root_hash(&self) -> &Hash {
+        &self.root_hash
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.index.contains_key(hash)
+    }
+
+    /// Number of live nodes, including the root.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Hashes of all live nodes, sorted.
+    pub fn node_hashes(&self) -> Vec<Hash> {
+        let mut hashes: Vec<Hash> = self.index.keys().cloned().collect();
+        hashes.sort();
+        hashes
+    }
+
+    pub fn edges(&self) -> &[GraphEdge] {
+        &self.edges
+    }
+
+    /// [`GenesisGraph::successors`], answered without reading the log.
+    pub fn successors(&self, hash: &Hash, edge_type: Option<EdgeType>) -> Vec<&Hash> {
+        self.adjacency.successors(hash, edge_type).collect()
+    }
+
+    /// [`GenesisGraph::predecessors`], answered without reading the log.
+    pub fn predecessors(&self, hash: &Hash, edge_type: Option<EdgeType>) -> Vec<&Hash> {
+        self.adjacency.predecessors(hash, edge_type).collect()
+    }
+
+    pub fn in_degree(&self, hash: &Hash, edge_type: Option<EdgeType>) -> usize {
+        self.adjacency.in_degree(hash, edge_type)
+    }
+
+    pub fn out_degree(&self, hash: &Hash, edge_type: Option<EdgeType>) -> usize {
+        self.adjacency.out_degree(hash, edge_type)
+    }
+
+    /// [`GenesisGraph::pinned`].
+    pub fn pinned(&self) -> impl Iterator<Item = &Hash> {
+        self.pinned.iter()
+    }
+
+    /// [`GenesisGraph::tombstone`].
+    pub fn tombstone(&self, hash: &Hash) -> Option<&Tombstone> {
+        self.tombstones.get(hash)
+    }
+
+    /// Bytes of torn or corrupt log dropped when the store was opened.
+    pub fn discarded_bytes(&self) -> u64 {
+        self.discarded_bytes
+    }
+
+    /// Read a node from disk, checking it against its hash.
+    pub fn get_node(&self, hash: &Hash) -> Result<Option<GraphNode>, GraphError> {
+        let Some(&offset) = self.index.get(hash) else {
+            return Ok(None);
+        };
+        let payload = self.read_record_at(offset)?;
+        let (op, stored_hash, node) = decode_node_payload(&payload)?;
+        let actual = if op == OP_ROOT {
+            compute_root_hash(&node)
+        } else {
+            compute_node_hash(&node)
+        };
+        if stored_hash != *hash || actual != *hash {
+            return Err(GraphError::CorruptStore(format!(
+                "record at offset {} does not hold node {}",
+                offset, hash
+            )));
+        }
+        Ok(Some(node))
+    }
+
+    /// Load the whole graph into memory.
+    pub fn to_graph(&self) -> Result<GenesisGraph, GraphError> {
+        let mut nodes = HashMap::with_capacity(self.index.len());
+        for hash in self.index.keys() {
+            let node = self
+                .get_node(hash)?
+                .ok_or_else(|| GraphError::NodeNotFound(hash.clone()))?;
+            nodes.insert(hash.clone(), node);
+        }
+        Ok(GenesisGraph {
+            nodes,
+            edges: self.edges.clone(),
+            root_hash: self.root_hash.clone(),
+            pinned: self.pinned.clone(),
+            tombstones: self.tombstones.clone(),
+            adjacency: self.adjacency.clone(),
+        })
+    }
+
+    /// [`GenesisGraph::merkle_root`], computed without reading node bodies.
+    pub fn merkle_root(&self) -> Hash {
+        graph_tree(self.index.keys(), &self.edges).root_hex()
+    }
+
+    /// [`GenesisGraph::prove_node`], computed without reading node bodies.
+    pub fn prove_node(&self, hash: &Hash) -> Result<NodeProof, GraphError> {
+        if !self.index.contains_key(hash) {
+            return Err(GraphError::NodeNotFound(hash.clone()));
+        }
+        Ok(graph_tree(self.index.keys(), &self.edges)
+            .proof(&node_leaf(hash))
+            .expect("every node has a leaf"))
+    }
+
+    // ========================================================================
+    // Mutations
+    // ========================================================================
+
+    /// [`GenesisGraph::insert_node`], durably logged before it returns.
+    pub fn insert_node(&mut self, node: GraphNode) -> Result<Hash, GraphError> {
+        if node.root_ref != self.root_hash {
+            return Err(GraphError::RootRefMismatch {
+                expected: self.root_hash.clone(),
+                actual: node.root_ref.clone(),
+            });
+        }
+        let hash = compute_node_hash(&node);
+        if self.index.contains_key(&hash) {
+            return Err(GraphError::NodeAlreadyExists(hash));
+        }
+
+        let offset = self.log(&node_payload(OP_INSERT, &hash, &node)?)?;
+        self.index.insert(hash.clone(), offset);
+        Ok(hash)
+    }
+
+    /// [`GenesisGraph::link_nodes`], durably logged before it returns.
+    pub fn link_nodes(
+        &mut self,
+        from: Hash,
+        to: Hash,
+        edge_type: EdgeType,
+    ) -> Result<(), GraphError> {
+        if !self.index.contains_key(&from) {
+            return Err(GraphError::NodeNotFound(from));
+        }
+        if !self.index.contains_key(&to) {
+            return Err(GraphError::NodeNotFound(to));
+        }
+        if from == to {
+            return Err(GraphError::SelfLoopForbidden(from));
+        }
+        if let Some(cycle) = self.adjacency.cycle_through(&from, &to) {
+            return Err(GraphError::CycleDetected { cycle });
+        }
+
+        let edge = GraphEdge {
+            from,
+            to,
+            edge_type,
+        };
+        self.log(&edge_payload(&edge)?)?;
+        self.adjacency.link(&mut self.edges, edge);
+        Ok(())
+    }
+
+    /// [`GenesisGraph::delete_node`], durably logged before it returns.
+    /// The log is append-only, so the node's record stays on disk.
+    pub fn delete_node(&mut self, hash: &Hash) -> Result<GraphNode, GraphError> {
+        if *hash == self.root_hash {
+            return Err(GraphError::InvalidRootHash);
+        }
+        let node = self
+            .get_node(hash)?
+            .ok_or_else(|| GraphError::NodeNotFound(hash.clone()))?;
+
+        self.log(&hash_payload(OP_DELETE, hash))?;
+        self.apply_delete(hash);
+        Ok(node)
+    }
+
+    /// [`GenesisGraph::pin`], durably logged before it returns.
+    pub fn pin(&mut self, hash: &Hash) -> Result<(), GraphError> {
+        if !self.index.contains_key(hash) {
+            return Err(GraphError::NodeNotFound(hash.clone()));
+        }
+        if !self.pinned.contains(hash) {
+            self.log(&hash_payload(OP_PIN, hash))?;
+            self.pinned.insert(hash.clone());
+        }
+        Ok(())
+    }
+
+    /// [`GenesisGraph::unpin`], durably logged before it returns.
+    pub fn unpin(&mut self, hash: &Hash) -> Result<bool, GraphError> {
+        if !self.pinned.contains(hash) {
+            return Ok(false);
+        }
+        self.log(&hash_payload(OP_UNPIN, hash))?;
+        Ok(self.pinned.remove(hash))
+    }
+
+    /// Write a snapshot of the index and edges, so the next open only
+    /// replays log records appended after this point.
+    pub fn snapshot(&self) -> Result<(), GraphError> {
+        let snapshot = Snapshot {
+            wal_len: self.wal_len,
+            root_hash: self.root_hash.clone(),
+            index: self.index.iter().map(|(h, o)| (h.clone(), *o)).collect(),
+            edges: self.edges.clone(),
+            pinned: self.pinned.clone(),
+            tombstones: self.tombstones.clone(),
+        };
+        let mut body = Vec::new();
+        ciborium::into_writer(&snapshot, &mut body)
+            .map_err(|e| GraphError::SerializationError(e.to_string()))?;
+
+        let mut data = SNAPSHOT_MAGIC.to_vec();
+        data.extend_from_slice(&Sha256::digest(&body));
+        data.extend_from_slice(&body);
+
+        let path = self.dir.join(SNAPSHOT_FILE);
+        let tmp = self.dir.join(format!("{}.tmp", SNAPSHOT_FILE));
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+            fs::rename(&tmp, &path)
+        };
+        write().map_err(|e| io_error(&path, e))
+    }
+
+    // ========================================================================
+    // Log I/O
+    // ========================================================================
+
+    fn wal_path(&self) -> PathBuf {
+        self.dir.join(WAL_FILE)
+    }
+
+    /// Append one record and sync it, returning its offset. A record that
+    /// fails to sync is cut back off, so it cannot reappear on replay.
+    fn log(&mut self, payload: &[u8]) -> Result<u64, GraphError> {
+        let offset = self.append(payload)?;
+        if let Err(e) = self.sync() {
+            self.truncate_to(offset);
+            return Err(e);
+        }
+        Ok(offset)
+    }
+
+    /// Append one record, returning its offset. On failure the log is cut
+    /// back to where it was, since the file is in append mode and torn
+    /// bytes left behind would sit in front of every later record.
+    fn append(&mut self, payload: &[u8]) -> Result<u64, GraphError> {
+        if self.poisoned {
+            return Err(GraphError::CorruptStore(
+                "an earlier failed write could not be undone".to_string(),
+            ));
+        }
+        let mut record = Vec::with_capacity(payload.len() + RECORD_HEADER_LEN as usize);
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&checksum(payload));
+        record.extend_from_slice(payload);
+
+        let offset = self.wal_len;
+        if let Err(e) = self.write_record(&record) {
+            self.truncate_to(offset);
+            return Err(io_error(self.wal_path(), e));
+        }
+        self.wal_len += record.len() as u64;
+        Ok(offset)
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> std::io::Result<()> {
+        #[cfg(test)]
+        if let Some(limit) = self.short_write.take() {
+            self.wal.write_all(&record[..limit.min(record.len())])?;
+            return Err(std::io::Error::other("simulated short write"));
+        }
+        self.wal.write_all(record)
+    }
+
+    /// Drop everything after `len`, poisoning the store if that fails.
+    fn truncate_to(&mut self, len: u64) {
+        match self.wal.set_len(len) {
+            Ok(()) => self.wal_len = len,
+            Err(_) => self.poisoned = true,
+        }
+    }
+
+    fn sync(&self) -> Result<(), GraphError> {
+        self.wal
+            .sync_data()
+            .map_err(|e| io_error(self.wal_path(), e))
+    }
+
+    /// Read the record at `offset`. `None` means the record runs past
+    /// `limit` or fails its checksum.
+    fn read_record(&self, offset: u64, limit: u64) -> Result<Option<Vec<u8>>, GraphError> {
+        let mut file = &self.wal;
+        let mut read = || -> std::io::Result<Option<Vec<u8>>> {
+            if offset + RECORD_HEADER_LEN > limit {
+                return Ok(None);
+            }
+            file.seek(SeekFrom::Start(offset))?;
+            let mut header = [0u8; RECORD_HEADER_LEN as usize];
+            file.read_exact(&mut header)?;
+            let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as u64;
+            if offset + RECORD_HEADER_LEN + len > limit {
+                return Ok(None);
+            }
+            let mut payload = vec![0u8; len as usize];
+            file.read_exact(&mut payload)?;
+            Ok((checksum(&payload) == header[4..]).then_some(payload))
+        };
+        read().map_err(|e| io_error(self.wal_path(), e))
+    }
+
+    fn read_record_at(&self, offset: u64) -> Result<Vec<u8>, GraphError> {
+        self.read_record(offset, self.wal_len)?
+            .ok_or_else(|| GraphError::CorruptStore(format!("bad record at offset {}", offset)))
+    }
+
+    fn read_snapshot(&self) -> Option<Snapshot> {
+        let data = fs::read(self.dir.join(SNAPSHOT_FILE)).ok()?;
+        let rest = data.strip_prefix(SNAPSHOT_MAGIC.as_slice())?;
+        if rest.len() < 32 || Sha256::digest(&rest[32..]).as_slice() != &rest[..32] {
+            return None;
+        }
+        ciborium::from_reader(&rest[32..]).ok()
+    }
+
+    /// Apply every intact record from `self.wal_len` to `file_len`, then
+    /// truncate anything after the last intact record. Only torn or
+    /// checksum-failing records are dropped; an intact record that does not
+    /// apply is reported as corruption and the log is left untouched.
+    fn replay(&mut self, file_len: u64) -> Result<(), GraphError> {
+        while self.wal_len < file_len {
+            let offset = self.wal_len;
+            let Some(payload) = self.read_record(offset, file_len)? else {
+                break;
+            };
+            self.apply(offset, &payload).map_err(|e| match e {
+                GraphError::CorruptStore(_) => e,
+                e => GraphError::CorruptStore(format!("record at offset {}: {}", offset, e)),
+            })?;
+            self.wal_len = offset + RECORD_HEADER_LEN + payload.len() as u64;
+        }
+
+        if self.wal_len < file_len {
+            self.discarded_bytes = file_len - self.wal_len;
+            self.wal
+                .set_len(self.wal_len)
+                .map_err(|e| io_error(self.wal_path(), e))?;
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, offset: u64, payload: &[u8]) -> Result<(), GraphError> {
+        match payload.first() {
+            Some(&OP_ROOT) | Some(&OP_INSERT) => {
+                let hash = payload_hash(payload)?;
+                if payload[0] == OP_ROOT {
+                    self.root_hash = hash.clone();
+                }
+                self.index.insert(hash, offset);
+            }
+            Some(&OP_LINK) => {
+                let edge: GraphEdge = ciborium::from_reader(&payload[1..])
+                    .map_err(|e| GraphError::SerializationError(e.to_string()))?;
+                self.adjacency.link(&mut self.edges, edge);
+            }
+            Some(&OP_DELETE) => {
+                let hash = payload_hash(payload)?;
+                self.apply_delete(&hash);
+            }
+            Some(&OP_PIN) => {
+                self.pinned.insert(payload_hash(payload)?);
+            }
+            Some(&OP_UNPIN) => {
+                self.pinned.remove(&payload_hash(payload)?);
+            }
+            Some(&OP_TOMBSTONE) => {
+                let hash = payload_hash(payload)?;
+                let tombstone = ciborium::from_reader(&payload[1 + HASH_LEN..])
+                    .map_err(|e| GraphError::SerializationError(e.to_string()))?;
+                self.tombstones.insert(hash, tombstone);
+            }
+            _ => {
+                return Err(GraphError::CorruptStore(format!(
+                    "unknown record at offset {}",
+                    offset
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_delete(&mut self, hash: &Hash) {
+        self.index.remove(hash);
+        self.pinned.remove(hash);
+        self.adjacency.unlink_node(&mut self.edges, hash);
+    }
+}
+
+// ============================================================================
+// Record Encoding
+// ============================================================================
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let digest = Sha256::digest(payload);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+fn node_payload(op: u8, hash: &Hash, node: &GraphNode) -> Result<Vec<u8>, GraphError> {
+    if hash.len() != HASH_LEN {
+        return Err(GraphError::SerializationError(format!(
+            "node hash {} is not {} hex digits",
+            hash, HASH_LEN
+        )));
+    }
+    let mut payload = vec![op];
+    payload.extend_from_slice(hash.as_bytes());
+    ciborium::into_writer(node, &mut payload)
+        .map_err(|e| GraphError::SerializationError(e.to_string()))?;
+    Ok(payload)
+}
+
+fn hash_payload(op: u8, hash: &Hash) -> Vec<u8> {
+    let mut payload = vec![op];
+    payload.extend_from_slice(hash.as_bytes());
+    payload
+}
+
+fn tombstone_payload(hash: &Hash, tombstone: &Tombstone) -> Result<Vec<u8>, GraphError> {
+    let mut payload = hash_payload(OP_TOMBSTONE, hash);
+    ciborium::into_writer(tombstone, &mut payload)
+        .map_err(|e| GraphError::SerializationError(e.to_string()))?;
+    Ok(payload)
+}
+
+fn edge_payload(edge: &GraphEdge) -> Result<Vec<u8>, GraphError> {
+    let mut payload = vec![OP_LINK];
+    ciborium::into_writer(edge, &mut payload)
+        .map_err(|e| GraphError::SerializationError(e.to_string()))?;
+    Ok(payload)
+}
+
+fn payload_hash(payload: &[u8]) -> Result<Hash, GraphError> {
+    payload
+        .get(1..1 + HASH_LEN)
+        .and_then(|bytes| std::str::from_utf8(bytes).ok())
+        .map(str::to_string)
+        .ok_or_else(|| GraphError::CorruptStore("truncated node hash".to_string()))
+}
+
+fn decode_node_payload(payload: &[u8]) -> Result<(u8, Hash, GraphNode), GraphError> {
+    let hash = payload_hash(payload)?;
+    let node = ciborium::from_reader(&payload[1 + HASH_LEN..])
+        .map_err(|e| GraphError::SerializationError(e.to_string()))?;
+    Ok((payload[0], hash, node))
+}
+
+fn io_error(path: impl AsRef<Path>, source: std::io::Error) -> GraphError {
+    GraphError::Io {
+        path: path.as_ref().display().to_string(),
+        source,
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_root_node, Expression, Literal, NodeMetadata};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("genesis_store_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn node(store: &GraphStore, id: &str, value: i64) -> GraphNode {
+        GraphNode {
+            id: id.to_string(),
+            root_ref: store.root_hash().clone(),
+            data: Expression::Literal(Literal::Int(value)),
+            metadata: NodeMetadata {
+                timestamp: 1,
+                lineage_depth: 1,
+                tags: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn test_reopen_preserves_graph() {
+        let dir = temp_dir("reopen");
+        let mut store = GraphStore::create(&dir, create_root_node()).unwrap();
+        let root = store.root_hash().clone();
+        let a = store.insert_node(node(&store, "a", 1)).unwrap();
+        let b = store.insert_node(node(&store, "b", 2)).unwrap();
+        store
+            .link_nodes(root.clone(), a.clone(), EdgeType::Derivation)
+            .unwrap();
+        store
+            .link_nodes(a.clone(), b.clone(), EdgeType::Dependency)
+            .unwrap();
+        let expected = store.to_graph().unwrap().canonical_serialize().unwrap();
+        drop(store);
+
+        let store = GraphStore::open(&dir).unwrap();
+        assert_eq!(store.root_hash(), &root);
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.successors(&a, Some(EdgeType::Dependency)), vec![&b]);
+        assert_eq!(store.in_degree(&a, None), 1);
+        assert_eq!(store.discarded_bytes(), 0);
+        assert_eq!(store.get_node(&b).unwrap().unwrap().id, "b");
+        assert_eq!(
+            store.to_graph().unwrap().canonical_serialize().unwrap(),
+            expected
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_graph_rules_enforced() {
+        let dir = temp_dir("rules");
+        let mut store = GraphStore::create(&dir, create_root_node()).unwrap();
+        let root = store.root_hash().clone();
+        let a = store.insert_node(node(&store, "a", 1)).unwrap();
+
+        assert!(matches!(
+            store.insert_node(node(&store, "a", 1)),
+            Err(GraphError::NodeAlreadyExists(_))
+        ));
+        store
+            .link_nodes(root.clone(), a.clone(), EdgeType::Reference)
+            .unwrap();
+        assert!(matches!(
+            store.link_nodes(a.clone(), root.clone(), EdgeType::Reference),
+            Err(GraphError::CycleDetected { cycle }) if cycle == vec![a.clone(), root.clone(), a.clone()]
+        ));
+        assert!(matches!(
+            store.link_nodes(a.clone(), a.clone(), EdgeType::Reference),
+            Err(GraphError::SelfLoopForbidden(_))
+        ));
+        assert!(matches!(
+            store.delete_node(&root),
+            Err(GraphError::InvalidRootHash)
+        ));
+
+        store.delete_node(&a).unwrap();
+        assert!(store.edges().is_empty());
+        drop(store);
+
+        let store = GraphStore::open(&dir).unwrap();
+        assert!(!store.contains(&a));
+        assert!(store.edges().is_empty());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_snapshot_limits_replay() {
+        let dir = temp_dir("snapshot");
+        let mut store = GraphStore::create(&dir, create_root_node()).unwrap();
+        let a = store.insert_node(node(&store, "a", 1)).unwrap();
+        store.snapshot().unwrap();
+        let b = store.insert_node(node(&store, "b", 2)).unwrap();
+        drop(store);
+
+        let store = GraphStore::open(&dir).unwrap();
+        assert!(store.contains(&a));
+        assert!(store.contains(&b));
+
+        // A corrupt snapshot is ignored and the whole log replayed
+        let path = dir.join(SNAPSHOT_FILE);
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        fs::write(&path, data).unwrap();
+        let store = GraphStore::open(&dir).unwrap();
+        assert_eq!(store.node_hashes().len(), 3);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_torn_write_is_truncated() {
+        let dir = temp_dir("torn");
+        let mut store = GraphStore::create(&dir, create_root_node()).unwrap();
+        let a = store.insert_node(node(&store, "a", 1)).unwrap();
+        let b = store.insert_node(node(&store, "b", 2)).unwrap();
+        drop(store);
+
+        // Cut the last record in half, as a crash mid-append would
+        let wal = dir.join(WAL_FILE);
+        let len = fs::metadata(&wal).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&wal)
+            .unwrap()
+            .set_len(len - 10)
+            .unwrap();
+
+        let mut store = GraphStore::open(&dir).unwrap();
+        assert!(store.contains(&a));
+        assert!(!store.contains(&b));
+        assert!(store.discarded_bytes() > 0);
+
+        // The store keeps working after recovery
+        let b = store.insert_node(node(&store, "b", 2)).unwrap();
+        drop(store);
+        let store = GraphStore::open(&dir).unwrap();
+        assert!(store.contains(&b));
+        assert_eq!(store.discarded_bytes(), 0);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_unappliable_record_is_not_truncated() {
+        let dir = temp_dir("unappliable");
+        let mut store = GraphStore::create(&dir, create_root_node()).unwrap();
+        store.insert_node(node(&store, "a", 1)).unwrap();
+        // Well framed, but no such operation
+        store.append(&[0x7f]).unwrap();
+        store.sync().unwrap();
+        drop(store);
+
+        let wal = dir.join(WAL_FILE);
+        let len = fs::metadata(&wal).unwrap().len();
+        assert!(matches!(
+            GraphStore::open(&dir),
+            Err(GraphError::CorruptStore(_))
+        ));
+        assert_eq!(fs::metadata(&wal).unwrap().len(), len);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_pins_and_tombstones_persist() {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root = graph.root_hash().clone();
+        let mut add = |id: &str| {
+            graph
+                .insert_node(GraphNode {
+                    id: id.to_string(),
+                    root_ref: root.clone(),
+                    data: Expression::Var(id.to_string()),
+                    metadata: NodeMetadata {
+                        timestamp: 1,
+                        lineage_depth: 1,
+                        tags: vec![],
+                    },
+                })
+                .unwrap()
+        };
+        let kept = add("kept");
+        let orphan = add("orphan");
+        let later = add("later");
+        graph.pin(&kept).unwrap();
+        graph.collect_garbage_with_clock(&capsule_core::FixedClock(9));
+        assert!(graph.tombstone(&orphan).is_some());
+        assert!(graph.tombstone(&later).is_some());
+
+        let dir = temp_dir("pins");
+        let store = GraphStore::create_from_graph(&dir, &graph).unwrap();
+        assert_eq!(store.pinned().collect::<Vec<_>>(), vec![&kept]);
+        drop(store);
+
+        // Pins logged after the snapshot are replayed from the log
+        let mut store = GraphStore::open(&dir).unwrap();
+        assert_eq!(store.tombstone(&orphan), graph.tombstone(&orphan));
+        let extra = store.insert_node(node(&store, "extra", 3)).unwrap();
+        store.pin(&extra).unwrap();
+        assert!(store.unpin(&kept).unwrap());
+        assert!(!store.unpin(&kept).unwrap());
+        drop(store);
+
+        let store = GraphStore::open(&dir).unwrap();
+        assert_eq!(store.pinned().collect::<Vec<_>>(), vec![&extra]);
+        let loaded = store.to_graph().unwrap();
+        assert_eq!(loaded.pinned().collect::<Vec<_>>(), vec![&extra]);
+        assert_eq!(loaded.tombstone(&later), graph.tombstone(&later));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_failed_append_is_cut_back() {
+        let dir = temp_dir("short_write");
+        let mut store = GraphStore::create(&dir, create_root_node()).unwrap();
+        let a = store.insert_node(node(&store, "a", 1)).unwrap();
+
+        store.short_write = Some(5);
+        assert!(matches!(
+            store.insert_node(node(&store, "b", 2)),
+            Err(GraphError::Io { .. })
+        ));
+        let wal = dir.join(WAL_FILE);
+        assert_eq!(fs::metadata(&wal).unwrap().len(), store.wal_len);
+
+        // Later writes still land where the index says they are
+        let c = store.insert_node(node(&store, "c", 3)).unwrap();
+        assert_eq!(store.get_node(&c).unwrap().unwrap().id, "c");
+        drop(store);
+
+        let store = GraphStore::open(&dir).unwrap();
+        assert_eq!(store.discarded_bytes(), 0);
+        assert!(store.contains(&a));
+        assert!(store.contains(&c));
+        assert_eq!(store.len(), 3);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_create_from_existing_graph() {
+        let mut graph = GenesisGraph::new(create_root_node()).unwrap();
+        let root = graph.root_hash().clone();
+        let child = GraphNode {
+            id: "child".to_string(),
+            root_ref: root.clone(),
+            data: Expression::Var("x".to_string()),
+            metadata: NodeMetadata {
+                timestamp: 5,
+                lineage_depth: 1,
+                tags: vec!["t".to_string()],
+            },
+        };
+        let child_hash = graph.insert_node(child).unwrap();
+        graph
+            .link_nodes(root, child_hash, EdgeType::Derivation)
+            .unwrap();
+
+        let dir = temp_dir("import");
+        GraphStore::create_from_graph(&dir, &graph).unwrap();
+        assert!(GraphStore::create_from_graph(&dir, &graph).is_err());
+
+        let store = GraphStore::open(&dir).unwrap();
+        assert_eq!(
+            store.to_graph().unwrap().canonical_serialize().unwrap(),
+            graph.canonical_serialize().unwrap()
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+}
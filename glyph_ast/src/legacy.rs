@@ -0,0 +1,473 @@
+// ============================================================================
+// Legacy ASTs
+// ============================================================================
+//
+// Frozen copies of the ASTs that predate this crate, kept so data written
+// with them can still be read and converted:
+//
+//   graph   genesis_graph's five-variant AST with bare `Literal(i64)`
+//   parser  glyph_parser's AST with `Record(HashMap)` and a five-variant
+//           `Pattern`
+//
+// glyph_engine's and genesis_engine's ASTs had the same shape and encoding
+// as the shared one, so they need no legacy type.
+//
+// Conversions into the shared AST are lossless. Conversions back fail with
+// `AstError::Unrepresentable` on anything the legacy type cannot hold.
+
+use crate::{AstError, Expression, Literal, MatchArm, Pattern};
+
+pub mod graph {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Expression {
+        Literal(i64),
+        Var(String),
+        Lambda {
+            param: String,
+            body: Box<Expression>,
+        },
+        Apply {
+            func: Box<Expression>,
+            arg: Box<Expression>,
+        },
+        Let {
+            name: String,
+            value: Box<Expression>,
+            body: Box<Expression>,
+        },
+    }
+}
+
+pub mod parser {
+    use crate::Literal;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Expression {
+        Literal(Literal),
+        Var(String),
+        Lambda {
+            param: String,
+            body: Box<Expression>,
+        },
+        Apply {
+            func: Box<Expression>,
+            arg: Box<Expression>,
+        },
+        LinearApply {
+            func: Box<Expression>,
+            arg: Box<Expression>,
+        },
+        Let {
+            name: String,
+            value: Box<Expression>,
+            body: Box<Expression>,
+        },
+        Match {
+            expr: Box<Expression>,
+            arms: Vec<MatchArm>,
+        },
+        Tuple(Vec<Expression>),
+        List(Vec<Expression>),
+        Record(HashMap<String, Expression>),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct MatchArm {
+        pub pattern: Pattern,
+        pub guard: Option<Box<Expression>>,
+        pub body: Box<Expression>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Pattern {
+        Wildcard,
+        Var(String),
+        Literal(Literal),
+        Tuple(Vec<Pattern>),
+        Constructor { name: String, args: Vec<Pattern> },
+    }
+}
+
+const GRAPH_AST: &str = "the legacy graph AST";
+const PARSER_AST: &str = "the legacy parser AST";
+
+fn unrepresentable(target: &'static str, construct: impl Into<String>) -> AstError {
+    AstError::Unrepresentable {
+        target,
+        construct: construct.into(),
+    }
+}
+
+fn expression_kind(expr: &Expression) -> &'static str {
+    match expr {
+        Expression::Literal(_) => "a non-integer literal",
+        Expression::Var(_) => "a variable",
+        Expression::Lambda { .. } => "a lambda",
+        Expression::Apply { .. } => "an application",
+        Expression::LinearApply { .. } => "a linear application",
+        Expression::Let { .. } => "a let binding",
+        Expression::Match { .. } => "a match",
+        Expression::Tuple(_) => "a tuple",
+        Expression::List(_) => "a list",
+        Expression::Record(_) => "a record",
+    }
+}
+
+fn pattern_kind(pattern: &Pattern) -> &'static str {
+    match pattern {
+        Pattern::Wildcard => "a wildcard pattern",
+        Pattern::Var(_) => "a variable pattern",
+        Pattern::Literal(_) => "a literal pattern",
+        Pattern::Bind { .. } => "a binding pattern",
+        Pattern::Tuple(_) => "a tuple pattern",
+        Pattern::List(_) => "a list pattern",
+        Pattern::Constructor { .. } => "a constructor pattern",
+        Pattern::Record(_) => "a record pattern",
+        Pattern::Lambda { .. } => "a lambda pattern",
+        Pattern::Apply { .. } => "an application pattern",
+    }
+}
+
+// ============================================================================
+// genesis_graph
+// ============================================================================
+
+impl From<graph::Expression> for Expression {
+    fn from(expr: graph::Expression) -> Self {
+        let boxed = |e: Box<graph::Expression>| Box::new(Expression::from(*e));
+        match expr {
+            graph::Expression::Literal(n) => Expression::Literal(Literal::Int(n)),
+            graph::Expression::Var(name) => Expression::Var(name),
+            graph::Expression::Lambda { param, body } => Expression::Lambda {
+                param,
+                body: boxed(body),
+            },
+            graph::Expression::Apply { func, arg } => Expression::Apply {
+                func: boxed(func),
+                arg: boxed(arg),
+            },
+            graph::Expression::Let { name, value, body } => Expression::Let {
+                name,
+                value: boxed(value),
+                body: boxed(body),
+            },
+        }
+    }
+}
+
+impl TryFrom<Expression> for graph::Expression {
+    type Error = AstError;
+
+    fn try_from(expr: Expression) -> Result<Self, AstError> {
+        let boxed = |e: Box<Expression>| graph::Expression::try_from(*e).map(Box::new);
+        Ok(match expr {
+            Expression::Literal(Literal::Int(n)) => graph::Expression::Literal(n),
+            Expression::Var(name) => graph::Expression::Var(name),
+            Expression::Lambda { param, body } => graph::Expression::Lambda {
+                param,
+                body: boxed(body)?,
+            },
+            Expression::Apply { func, arg } => graph::Expression::Apply {
+                func: boxed(func)?,
+                arg: boxed(arg)?,
+            },
+            Expression::Let { name, value, body } => graph::Expression::Let {
+                name,
+                value: boxed(value)?,
+                body: boxed(body)?,
+            },
+            other => return Err(unrepresentable(GRAPH_AST, expression_kind(&other))),
+        })
+    }
+}
+
+// ============================================================================
+// glyph_parser
+// ============================================================================
+
+impl From<parser::Expression> for Expression {
+    /// Record fields come out sorted by key, since the map had no order.
+    fn from(expr: parser::Expression) -> Self {
+        let boxed = |e: Box<parser::Expression>| Box::new(Expression::from(*e));
+        let all = |es: Vec<parser::Expression>| es.into_iter().map(Expression::from).collect();
+        match expr {
+            parser::Expression::Literal(literal) => Expression::Literal(literal),
+            parser::Expression::Var(name) => Expression::Var(name),
+            parser::Expression::Lambda { param, body } => Expression::Lambda {
+                param,
+                body: boxed(body),
+            },
+            parser::Expression::Apply { func, arg } => Expression::Apply {
+                func: boxed(func),
+                arg: boxed(arg),
+            },
+            parser::Expression::LinearApply { func, arg } => Expression::LinearApply {
+                func: boxed(func),
+                arg: boxed(arg),
+            },
+            parser::Expression::Let { name, value, body } => Expression::Let {
+                name,
+                value: boxed(value),
+                body: boxed(body),
+            },
+            parser::Expression::Match { expr, arms } => Expression::Match {
+                expr: boxed(expr),
+                arms: arms.into_iter().map(MatchArm::from).collect(),
+            },
+            parser::Expression::Tuple(items) => Expression::Tuple(all(items)),
+            parser::Expression::List(items) => Expression::List(all(items)),
+            parser::Expression::Record(fields) => {
+                let mut fields: Vec<(String, Expression)> = fields
+                    .into_iter()
+                    .map(|(k, v)| (k, Expression::from(v)))
+                    .collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                Expression::Record(fields)
+            }
+        }
+    }
+}
+
+impl From<parser::MatchArm> for MatchArm {
+    fn from(arm: parser::MatchArm) -> Self {
+        MatchArm {
+            pattern: arm.pattern.into(),
+            guard: arm.guard.map(|g| Box::new(Expression::from(*g))),
+            body: Box::new(Expression::from(*arm.body)),
+        }
+    }
+}
+
+impl From<parser::Pattern> for Pattern {
+    fn from(pattern: parser::Pattern) -> Self {
+        match pattern {
+            parser::Pattern::Wildcard => Pattern::Wildcard,
+            parser::Pattern::Var(name) => Pattern::Var(name),
+            parser::Pattern::Literal(literal) => Pattern::Literal(literal),
+            parser::Pattern::Tuple(items) => {
+                Pattern::Tuple(items.into_iter().map(Pattern::from).collect())
+            }
+            parser::Pattern::Constructor { name, args } => Pattern::Constructor {
+                name,
+                args: args.into_iter().map(Pattern::from).collect(),
+            },
+        }
+    }
+}
+
+impl TryFrom<Expression> for parser::Expression {
+    type Error = AstError;
+
+    fn try_from(expr: Expression) -> Result<Self, AstError> {
+        let boxed = |e: Box<Expression>| parser::Expression::try_from(*e).map(Box::new);
+        let all = |es: Vec<Expression>| {
+            es.into_iter()
+                .map(parser::Expression::try_from)
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(match expr {
+            Expression::Literal(literal) => parser::Expression::Literal(literal),
+            Expression::Var(name) => parser::Expression::Var(name),
+            Expression::Lambda { param, body } => parser::Expression::Lambda {
+                param,
+                body: boxed(body)?,
+            },
+            Expression::Apply { func, arg } => parser::Expression::Apply {
+                func: boxed(func)?,
+                arg: boxed(arg)?,
+            },
+            Expression::LinearApply { func, arg } => parser::Expression::LinearApply {
+                func: boxed(func)?,
+                arg: boxed(arg)?,
+            },
+            Expression::Let { name, value, body } => parser::Expression::Let {
+                name,
+                value: boxed(value)?,
+                body: boxed(body)?,
+            },
+            Expression::Match { expr, arms } => parser::Expression::Match {
+                expr: boxed(expr)?,
+                arms: arms
+                    .into_iter()
+                    .map(parser::MatchArm::try_from)
+                    .collect::<Result<_, _>>()?,
+            },
+            Expression::Tuple(items) => parser::Expression::Tuple(all(items)?),
+            Expression::List(items) => parser::Expression::List(all(items)?),
+            Expression::Record(fields) => {
+                let mut map = std::collections::HashMap::with_capacity(fields.len());
+                for (key, value) in fields {
+                    if map.contains_key(&key) {
+                        return Err(unrepresentable(
+                            PARSER_AST,
+                            format!("a record with two `{}` fields", key),
+                        ));
+                    }
+                    map.insert(key, parser::Expression::try_from(value)?);
+                }
+                parser::Expression::Record(map)
+            }
+        })
+    }
+}
+
+impl TryFrom<MatchArm> for parser::MatchArm {
+    type Error = AstError;
+
+    fn try_from(arm: MatchArm) -> Result<Self, AstError> {
+        Ok(parser::MatchArm {
+            pattern: arm.pattern.try_into()?,
+            guard: match arm.guard {
+                Some(guard) => Some(Box::new(parser::Expression::try_from(*guard)?)),
+                None => None,
+            },
+            body: Box::new(parser::Expression::try_from(*arm.body)?),
+        })
+    }
+}
+
+impl TryFrom<Pattern> for parser::Pattern {
+    type Error = AstError;
+
+    fn try_from(pattern: Pattern) -> Result<Self, AstError> {
+        let all = |ps: Vec<Pattern>| {
+            ps.into_iter()
+                .map(parser::Pattern::try_from)
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(match pattern {
+            Pattern::Wildcard => parser::Pattern::Wildcard,
+            Pattern::Var(name) => parser::Pattern::Var(name),
+            Pattern::Literal(literal) => parser::Pattern::Literal(literal),
+            Pattern::Tuple(items) => parser::Pattern::Tuple(all(items)?),
+            Pattern::Constructor { name, args } => parser::Pattern::Constructor {
+                name,
+                args: all(args)?,
+            },
+            other => return Err(unrepresentable(PARSER_AST, pattern_kind(&other))),
+        })
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn graph_sample() -> graph::Expression {
+        graph::Expression::Let {
+            name: "f".to_string(),
+            value: Box::new(graph::Expression::Lambda {
+                param: "x".to_string(),
+                body: Box::new(graph::Expression::Var("x".to_string())),
+            }),
+            body: Box::new(graph::Expression::Apply {
+                func: Box::new(graph::Expression::Var("f".to_string())),
+                arg: Box::new(graph::Expression::Literal(7)),
+            }),
+        }
+    }
+
+    fn parser_sample() -> parser::Expression {
+        let mut fields = HashMap::new();
+        fields.insert("z".to_string(), parser::Expression::Literal(Literal::Unit));
+        fields.insert(
+            "a".to_string(),
+            parser::Expression::List(vec![parser::Expression::Literal(Literal::Float(
+                "1.5".to_string(),
+            ))]),
+        );
+        parser::Expression::Match {
+            expr: Box::new(parser::Expression::Record(fields)),
+            arms: vec![parser::MatchArm {
+                pattern: parser::Pattern::Constructor {
+                    name: "Some".to_string(),
+                    args: vec![parser::Pattern::Tuple(vec![
+                        parser::Pattern::Var("y".to_string()),
+                        parser::Pattern::Wildcard,
+                    ])],
+                },
+                guard: Some(Box::new(parser::Expression::Var("y".to_string()))),
+                body: Box::new(parser::Expression::LinearApply {
+                    func: Box::new(parser::Expression::Var("g".to_string())),
+                    arg: Box::new(parser::Expression::Tuple(vec![])),
+                }),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_graph_ast_round_trips() {
+        let shared = Expression::from(graph_sample());
+        match &shared {
+            Expression::Let { body, .. } => assert!(matches!(
+                body.as_ref(),
+                Expression::Apply { arg, .. } if **arg == Expression::Literal(Literal::Int(7))
+            )),
+            other => panic!("expected a let, got {:?}", other),
+        }
+        assert_eq!(graph::Expression::try_from(shared).unwrap(), graph_sample());
+
+        let err = graph::Expression::try_from(Expression::Literal(Literal::Bool(true)));
+        assert!(matches!(err, Err(AstError::Unrepresentable { .. })));
+        let err = graph::Expression::try_from(Expression::Lambda {
+            param: "x".to_string(),
+            body: Box::new(Expression::Tuple(vec![])),
+        });
+        assert!(matches!(err, Err(AstError::Unrepresentable { .. })));
+    }
+
+    #[test]
+    fn test_parser_ast_round_trips() {
+        let shared = Expression::from(parser_sample());
+        match &shared {
+            Expression::Match { expr, .. } => match expr.as_ref() {
+                Expression::Record(fields) => {
+                    let keys: Vec<&str> = fields.iter().map(|(k, _)| k.as_str()).collect();
+                    assert_eq!(keys, ["a", "z"]);
+                }
+                other => panic!("expected a record, got {:?}", other),
+            },
+            other => panic!("expected a match, got {:?}", other),
+        }
+        assert_eq!(shared.canonicalize(), shared);
+        assert_eq!(
+            parser::Expression::try_from(shared).unwrap(),
+            parser_sample()
+        );
+    }
+
+    #[test]
+    fn test_parser_ast_rejects_what_it_cannot_hold() {
+        let duplicate = Expression::Record(vec![
+            ("a".to_string(), Expression::Var("x".to_string())),
+            ("a".to_string(), Expression::Var("y".to_string())),
+        ]);
+        let err = parser::Expression::try_from(duplicate).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the legacy parser AST cannot represent a record with two `a` fields"
+        );
+
+        let list_pattern = Expression::Match {
+            expr: Box::new(Expression::Var("x".to_string())),
+            arms: vec![MatchArm {
+                pattern: Pattern::List(vec![]),
+                guard: None,
+                body: Box::new(Expression::Var("x".to_string())),
+            }],
+        };
+        assert!(matches!(
+            parser::Expression::try_from(list_pattern),
+            Err(AstError::Unrepresentable { construct, .. }) if construct == "a list pattern"
+        ));
+    }
+}
@@ -0,0 +1,302 @@
+// ============================================================================
+// G-LYPH Abstract Syntax
+// ============================================================================
+//
+// The one `Expression` type shared by the parser, the pattern and
+// substitution engine, the rewrite runtimes and the graph store. It is the
+// AST glyph_engine already used: records keep their fields in a `Vec`, and
+// patterns cover binders, lists, records and lambda/application shapes as
+// well as what the parser can produce.
+//
+// Records are ordered for equality, but not for hashing: the canonical form
+// sorts record fields by key, and `content_hash` is taken over the
+// deterministic CBOR (capsule_core's `canonical_cbor`) of that form. The older, narrower ASTs live on in `legacy` so existing data
+// can be converted.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub mod legacy;
+
+pub type Hash = String;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub enum Expression {
+    Literal(Literal),
+    Var(String),
+    Lambda {
+        param: String,
+        body: Box<Expression>,
+    },
+    Apply {
+        func: Box<Expression>,
+        arg: Box<Expression>,
+    },
+    LinearApply {
+        func: Box<Expression>,
+        arg: Box<Expression>,
+    },
+    Let {
+        name: String,
+        value: Box<Expression>,
+        body: Box<Expression>,
+    },
+    Match {
+        expr: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
+    Tuple(Vec<Expression>),
+    List(Vec<Expression>),
+    Record(Vec<(String, Expression)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub enum Literal {
+    Int(i64),
+    Float(String),
+    String(String),
+    Bool(bool),
+    Unit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Box<Expression>>,
+    pub body: Box<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub enum Pattern {
+    Wildcard,
+    Var(String),
+    Literal(Literal),
+    Bind {
+        name: String,
+        pattern: Box<Pattern>,
+    },
+    Tuple(Vec<Pattern>),
+    List(Vec<Pattern>),
+    Constructor {
+        name: String,
+        args: Vec<Pattern>,
+    },
+    Record(Vec<(String, Pattern)>),
+    Lambda {
+        param_pattern: Box<Pattern>,
+        body_pattern: Box<Pattern>,
+    },
+    Apply {
+        func_pattern: Box<Pattern>,
+        arg_pattern: Box<Pattern>,
+    },
+}
+
+// ============================================================================
+// Error Types
+// ============================================================================
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AstError {
+    #[error("Deserialization failed: {0}")]
+    Deserialization(String),
+
+    /// A conversion into a legacy AST met a construct it cannot hold.
+    #[error("{target} cannot represent {construct}")]
+    Unrepresentable {
+        target: &'static str,
+        construct: String,
+    },
+}
+
+// ============================================================================
+// Canonical Form and Hashing
+// ============================================================================
+
+impl Expression {
+    /// This expression with every record's fields sorted by key. Fields
+    /// sharing a key keep their relative order.
+    pub fn canonicalize(&self) -> Expression {
+        let boxed = |e: &Expression| Box::new(e.canonicalize());
+        match self {
+            Expression::Literal(_) | Expression::Var(_) => self.clone(),
+            Expression::Lambda { param, body } => Expression::Lambda {
+                param: param.clone(),
+                body: boxed(body),
+            },
+            Expression::Apply { func, arg } => Expression::Apply {
+                func: boxed(func),
+                arg: boxed(arg),
+            },
+            Expression::LinearApply { func, arg } => Expression::LinearApply {
+                func: boxed(func),
+                arg: boxed(arg),
+            },
+            Expression::Let { name, value, body } => Expression::Let {
+                name: name.clone(),
+                value: boxed(value),
+                body: boxed(body),
+            },
+            Expression::Match { expr, arms } => Expression::Match {
+                expr: boxed(expr),
+                arms: arms
+                    .iter()
+                    .map(|arm| MatchArm {
+                        pattern: arm.pattern.canonicalize(),
+                        guard: arm.guard.as_deref().map(boxed),
+                        body: boxed(&arm.body),
+                    })
+                    .collect(),
+            },
+            Expression::Tuple(items) => {
+                Expression::Tuple(items.iter().map(Expression::canonicalize).collect())
+            }
+            Expression::List(items) => {
+                Expression::List(items.iter().map(Expression::canonicalize).collect())
+            }
+            Expression::Record(fields) => {
+                let mut fields: Vec<(String, Expression)> = fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.canonicalize()))
+                    .collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                Expression::Record(fields)
+            }
+        }
+    }
+
+    /// SHA-256 over the domain tag and the canonical CBOR encoding, so
+    /// expressions differing only in record field order hash alike.
+    pub fn content_hash(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update(b"GlyphV1:Expr:");
+        hasher.update(canonical_serialize(self));
+        hex::encode(hasher.finalize())
+    }
+}
+
+impl Pattern {
+    /// This pattern with every record pattern's fields sorted by key.
+    pub fn canonicalize(&self) -> Pattern {
+        let boxed = |p: &Pattern| Box::new(p.canonicalize());
+        let all = |ps: &[Pattern]| ps.iter().map(Pattern::canonicalize).collect();
+        match self {
+            Pattern::Wildcard | Pattern::Var(_) | Pattern::Literal(_) => self.clone(),
+            Pattern::Bind { name, pattern } => Pattern::Bind {
+                name: name.clone(),
+                pattern: boxed(pattern),
+            },
+            Pattern::Tuple(items) => Pattern::Tuple(all(items)),
+            Pattern::List(items) => Pattern::List(all(items)),
+            Pattern::Constructor { name, args } => Pattern::Constructor {
+                name: name.clone(),
+                args: all(args),
+            },
+            Pattern::Record(fields) => {
+                let mut fields: Vec<(String, Pattern)> = fields
+                    .iter()
+                    .map(|(k, p)| (k.clone(), p.canonicalize()))
+                    .collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                Pattern::Record(fields)
+            }
+            Pattern::Lambda {
+                param_pattern,
+                body_pattern,
+            } => Pattern::Lambda {
+                param_pattern: boxed(param_pattern),
+                body_pattern: boxed(body_pattern),
+            },
+            Pattern::Apply {
+                func_pattern,
+                arg_pattern,
+            } => Pattern::Apply {
+                func_pattern: boxed(func_pattern),
+                arg_pattern: boxed(arg_pattern),
+            },
+        }
+    }
+}
+
+/// Deterministic CBOR encoding of the canonical form of `expr`.
+pub fn canonical_serialize(expr: &Expression) -> Vec<u8> {
+    capsule_core::canonical_cbor(&expr.canonicalize()).expect("Expression serialization failed")
+}
+
+pub fn deserialize(data: &[u8]) -> Result<Expression, AstError> {
+    ciborium::from_reader(data).map_err(|e| AstError::Deserialization(e.to_string()))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(n: i64) -> Expression {
+        Expression::Literal(Literal::Int(n))
+    }
+
+    fn record(fields: &[(&str, Expression)]) -> Expression {
+        Expression::Record(
+            fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_canonical_round_trip() {
+        let expr = Expression::Match {
+            expr: Box::new(record(&[("b", int(2)), ("a", int(1))])),
+            arms: vec![MatchArm {
+                pattern: Pattern::Record(vec![
+                    ("b".to_string(), Pattern::Var("y".to_string())),
+                    ("a".to_string(), Pattern::Wildcard),
+                ]),
+                guard: Some(Box::new(Expression::Literal(Literal::Bool(true)))),
+                body: Box::new(Expression::Var("y".to_string())),
+            }],
+        };
+
+        let decoded = deserialize(&canonical_serialize(&expr)).unwrap();
+        assert_eq!(decoded, expr.canonicalize());
+        assert_eq!(decoded.canonicalize(), decoded);
+        assert!(matches!(
+            deserialize(&[0xff]),
+            Err(AstError::Deserialization(_))
+        ));
+    }
+
+    #[test]
+    fn test_hash_ignores_record_order_only() {
+        let ab = record(&[("a", int(1)), ("b", int(2))]);
+        let ba = record(&[("b", int(2)), ("a", int(1))]);
+        assert_ne!(ab, ba);
+        assert_eq!(ab.content_hash(), ba.content_hash());
+
+        let nested = |e: Expression| Expression::List(vec![e]);
+        assert_eq!(nested(ab.clone()).content_hash(), nested(ba).content_hash());
+
+        assert_ne!(ab.content_hash(), record(&[("a", int(1))]).content_hash());
+        assert_ne!(int(1).content_hash(), int(2).content_hash());
+        assert_eq!(int(1).content_hash().len(), 64);
+    }
+
+    #[test]
+    fn test_canonical_serialize_is_deterministic_cbor() {
+        // Encoded in field order, `Let`'s keys would be out of order
+        let expr = Expression::Let {
+            name: "x".to_string(),
+            value: Box::new(Expression::Literal(Literal::Float("0.5".to_string()))),
+            body: Box::new(record(&[("b", int(-1)), ("a", int(1 << 40))])),
+        };
+        let bytes = canonical_serialize(&expr);
+        assert!(capsule_core::cbor::is_canonical(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), expr.canonicalize());
+    }
+}
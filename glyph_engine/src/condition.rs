@@ -0,0 +1,396 @@
+// Evaluation of rule conditions and match guards over pattern bindings.
+//
+// A condition is an ordinary expression. Variables bound by the match stand
+// for the subterms they matched and are not evaluated further; literals,
+// tuples, lists and records evaluate to themselves; `let` binds a value.
+// Operators are builtins applied in curried form, e.g. `and (lt n 10) (eq x y)`:
+//
+//   eq ne                  structural equality of any two terms
+//   lt le gt ge            ordering of two integers or two strings
+//   and or not             booleans; `and` and `or` short-circuit
+//   add (+) sub mul        checked integer arithmetic
+//   length                 elements of a tuple or list, chars of a string
+//   free_in                whether variable `v` occurs free in a term
+//   is_int is_bool is_string is_literal is_var is_lambda is_apply
+//
+// Bindings shadow builtins of the same name.
+
+use crate::pattern::{Bindings, Expression, Literal};
+use crate::substitute::free_vars;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConditionError {
+    #[error("Unbound variable in condition: {0}")]
+    UnboundVariable(String),
+
+    #[error("Not a builtin function: {0:?}")]
+    NotAFunction(Expression),
+
+    #[error("Builtin `{builtin}` takes {expected} argument(s), got {found}")]
+    Arity {
+        builtin: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("Builtin `{builtin}` cannot be applied to {found:?}")]
+    TypeMismatch { builtin: String, found: Expression },
+
+    #[error("Integer overflow in `{0}`")]
+    Overflow(String),
+
+    #[error("Conditions cannot contain {0}")]
+    Unsupported(&'static str),
+
+    #[error("Condition evaluated to a non-boolean: {0:?}")]
+    NotBoolean(Expression),
+}
+
+/// Evaluate `condition` under `bindings`; it must produce a boolean.
+pub fn evaluate_condition(
+    condition: &Expression,
+    bindings: &Bindings,
+) -> Result<bool, ConditionError> {
+    match evaluate(condition, bindings)? {
+        Expression::Literal(Literal::Bool(b)) => Ok(b),
+        other => Err(ConditionError::NotBoolean(other)),
+    }
+}
+
+/// Evaluate a condition expression to a term.
+pub fn evaluate(expr: &Expression, bindings: &Bindings) -> Result<Expression, ConditionError> {
+    match expr {
+        Expression::Literal(_) => Ok(expr.clone()),
+        Expression::Var(name) => match bindings.get(name) {
+            Some(term) => Ok(term.clone()),
+            None if arity(name).is_some() => Err(ConditionError::Arity {
+                builtin: name.clone(),
+                expected: arity(name).unwrap(),
+                found: 0,
+            }),
+            None => Err(ConditionError::UnboundVariable(name.clone())),
+        },
+        Expression::Apply { .. } => {
+            let mut args = Vec::new();
+            let mut head = expr;
+            while let Expression::Apply { func, arg } = head {
+                args.push(arg.as_ref());
+                head = func;
+            }
+            args.reverse();
+            apply_builtin(head, &args, bindings)
+        }
+        Expression::Let { name, value, body } => {
+            let value = evaluate(value, bindings)?;
+            let mut scope = bindings.clone();
+            scope.insert(name.clone(), value);
+            evaluate(body, &scope)
+        }
+        Expression::Tuple(items) => Ok(Expression::Tuple(evaluate_all(items, bindings)?)),
+        Expression::List(items) => Ok(Expression::List(evaluate_all(items, bindings)?)),
+        Expression::Record(fields) => fields
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), evaluate(v, bindings)?)))
+            .collect::<Result<_, _>>()
+            .map(Expression::Record),
+        Expression::Lambda { .. } => Err(ConditionError::Unsupported("lambdas")),
+        Expression::LinearApply { .. } => Err(ConditionError::Unsupported("linear application")),
+        Expression::Match { .. } => Err(ConditionError::Unsupported("match expressions")),
+    }
+}
+
+fn evaluate_all(
+    items: &[Expression],
+    bindings: &Bindings,
+) -> Result<Vec<Expression>, ConditionError> {
+    items.iter().map(|item| evaluate(item, bindings)).collect()
+}
+
+pub(crate) fn arity(builtin: &str) -> Option<usize> {
+    match builtin {
+        "not" | "length" | "is_int" | "is_bool" | "is_string" | "is_literal" | "is_var"
+        | "is_lambda" | "is_apply" => Some(1),
+        "eq" | "ne" | "lt" | "le" | "gt" | "ge" | "and" | "or" | "add" | "+" | "sub" | "mul"
+        | "free_in" => Some(2),
+        _ => None,
+    }
+}
+
+fn boolean(b: bool) -> Expression {
+    Expression::Literal(Literal::Bool(b))
+}
+
+fn int(n: i64) -> Expression {
+    Expression::Literal(Literal::Int(n))
+}
+
+fn apply_builtin(
+    head: &Expression,
+    args: &[&Expression],
+    bindings: &Bindings,
+) -> Result<Expression, ConditionError> {
+    let name = match head {
+        Expression::Var(name) if !bindings.contains_key(name) => name.as_str(),
+        other => return Err(ConditionError::NotAFunction(evaluate(other, bindings)?)),
+    };
+    let expected = arity(name).ok_or_else(|| ConditionError::UnboundVariable(name.to_string()))?;
+    if args.len() != expected {
+        return Err(ConditionError::Arity {
+            builtin: name.to_string(),
+            expected,
+            found: args.len(),
+        });
+    }
+    let mismatch = |found: Expression| ConditionError::TypeMismatch {
+        builtin: name.to_string(),
+        found,
+    };
+    let as_bool = |term: Expression| match term {
+        Expression::Literal(Literal::Bool(b)) => Ok(b),
+        other => Err(mismatch(other)),
+    };
+
+    // The boolean connectives evaluate their second argument lazily
+    match name {
+        "and" => {
+            return Ok(boolean(
+                as_bool(evaluate(args[0], bindings)?)? && as_bool(evaluate(args[1], bindings)?)?,
+            ))
+        }
+        "or" => {
+            return Ok(boolean(
+                as_bool(evaluate(args[0], bindings)?)? || as_bool(evaluate(args[1], bindings)?)?,
+            ))
+        }
+        _ => {}
+    }
+
+    let values = args
+        .iter()
+        .map(|arg| evaluate(arg, bindings))
+        .collect::<Result<Vec<_>, _>>()?;
+    apply_primitive(name, values)
+}
+
+/// Apply a strict builtin (anything but `and` and `or`) to evaluated
+/// arguments, as many as `arity` asks for.
+pub(crate) fn apply_primitive(
+    name: &str,
+    values: Vec<Expression>,
+) -> Result<Expression, ConditionError> {
+    let mismatch = |found: Expression| ConditionError::TypeMismatch {
+        builtin: name.to_string(),
+        found,
+    };
+    let as_bool = |term: Expression| match term {
+        Expression::Literal(Literal::Bool(b)) => Ok(b),
+        other => Err(mismatch(other)),
+    };
+
+    let mut values = values.into_iter();
+    let a = values.next().expect("every builtin takes an argument");
+    let b = values.next();
+
+    Ok(match (name, b) {
+        ("not", None) => boolean(!as_bool(a)?),
+        ("eq", Some(b)) => boolean(a == b),
+        ("ne", Some(b)) => boolean(a != b),
+        ("lt" | "le" | "gt" | "ge", Some(b)) => {
+            let ordering = match (&a, &b) {
+                (Expression::Literal(Literal::Int(x)), Expression::Literal(Literal::Int(y))) => {
+                    x.cmp(y)
+                }
+                (
+                    Expression::Literal(Literal::String(x)),
+                    Expression::Literal(Literal::String(y)),
+                ) => x.cmp(y),
+                (Expression::Literal(Literal::Int(_) | Literal::String(_)), _) => {
+                    return Err(mismatch(b))
+                }
+                _ => return Err(mismatch(a)),
+            };
+            boolean(match name {
+                "lt" => ordering.is_lt(),
+                "le" => ordering.is_le(),
+                "gt" => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            })
+        }
+        ("add" | "+" | "sub" | "mul", Some(b)) => {
+            let (x, y) = match (&a, &b) {
+                (Expression::Literal(Literal::Int(x)), Expression::Literal(Literal::Int(y))) => {
+                    (*x, *y)
+                }
+                (Expression::Literal(Literal::Int(_)), _) => return Err(mismatch(b)),
+                _ => return Err(mismatch(a)),
+            };
+            let result = match name {
+                "sub" => x.checked_sub(y),
+                "mul" => x.checked_mul(y),
+                _ => x.checked_add(y),
+            };
+            int(result.ok_or_else(|| ConditionError::Overflow(name.to_string()))?)
+        }
+        ("free_in", Some(term)) => match a {
+            Expression::Var(var) => boolean(free_vars(&term).contains(&var)),
+            other => return Err(mismatch(other)),
+        },
+        ("length", None) => match &a {
+            Expression::Tuple(items) | Expression::List(items) => int(items.len() as i64),
+            Expression::Literal(Literal::String(s)) => int(s.chars().count() as i64),
+            _ => return Err(mismatch(a)),
+        },
+        ("is_int", None) => boolean(matches!(a, Expression::Literal(Literal::Int(_)))),
+        ("is_bool", None) => boolean(matches!(a, Expression::Literal(Literal::Bool(_)))),
+        ("is_string", None) => boolean(matches!(a, Expression::Literal(Literal::String(_)))),
+        ("is_literal", None) => boolean(matches!(a, Expression::Literal(_))),
+        ("is_var", None) => boolean(matches!(a, Expression::Var(_))),
+        ("is_lambda", None) => boolean(matches!(a, Expression::Lambda { .. })),
+        ("is_apply", None) => boolean(matches!(
+            a,
+            Expression::Apply { .. } | Expression::LinearApply { .. }
+        )),
+        _ => unreachable!("arity table and builtins disagree on `{}`", name),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(name.to_string())
+    }
+
+    fn call(name: &str, args: Vec<Expression>) -> Expression {
+        args.into_iter()
+            .fold(var(name), |func, arg| Expression::Apply {
+                func: Box::new(func),
+                arg: Box::new(arg),
+            })
+    }
+
+    fn bindings(pairs: &[(&str, Expression)]) -> Bindings {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_comparisons_and_connectives() {
+        let env = bindings(&[
+            ("n", int(7)),
+            ("s", Expression::Literal(Literal::String("b".into()))),
+        ]);
+        let check = |cond: Expression| evaluate_condition(&cond, &env);
+
+        assert_eq!(check(call("lt", vec![var("n"), int(10)])), Ok(true));
+        assert_eq!(check(call("ge", vec![var("n"), int(10)])), Ok(false));
+        assert_eq!(
+            check(call(
+                "gt",
+                vec![var("s"), Expression::Literal(Literal::String("a".into()))]
+            )),
+            Ok(true)
+        );
+        assert_eq!(
+            check(call(
+                "and",
+                vec![
+                    call("eq", vec![call("add", vec![var("n"), int(1)]), int(8)]),
+                    call("not", vec![call("is_var", vec![var("n")])]),
+                ],
+            )),
+            Ok(true)
+        );
+        // `or` does not evaluate its second argument once the first holds
+        assert_eq!(
+            check(call("or", vec![boolean(true), var("unbound")])),
+            Ok(true)
+        );
+        assert_eq!(
+            check(call("le", vec![call("length", vec![var("s")]), int(1)])),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn test_structural_equality_of_bound_subterms() {
+        let term = Expression::Apply {
+            func: Box::new(var("f")),
+            arg: Box::new(var("y")),
+        };
+        let env = bindings(&[("x", term.clone()), ("z", term), ("w", var("y"))]);
+
+        assert_eq!(
+            evaluate_condition(&call("eq", vec![var("x"), var("z")]), &env),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate_condition(&call("eq", vec![var("x"), var("w")]), &env),
+            Ok(false)
+        );
+        assert_eq!(
+            evaluate_condition(&call("free_in", vec![var("w"), var("x")]), &env),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate_condition(&call("is_apply", vec![var("x")]), &env),
+            Ok(true)
+        );
+
+        let let_bound = Expression::Let {
+            name: "k".to_string(),
+            value: Box::new(int(3)),
+            body: Box::new(call("eq", vec![var("k"), int(3)])),
+        };
+        assert_eq!(evaluate_condition(&let_bound, &env), Ok(true));
+    }
+
+    #[test]
+    fn test_errors() {
+        let env = bindings(&[("n", int(1)), ("b", boolean(true))]);
+        let check = |cond: Expression| evaluate_condition(&cond, &env);
+
+        assert_eq!(check(var("n")), Err(ConditionError::NotBoolean(int(1))));
+        assert_eq!(
+            check(var("missing")),
+            Err(ConditionError::UnboundVariable("missing".to_string()))
+        );
+        assert_eq!(
+            check(call("lt", vec![var("n"), var("b")])),
+            Err(ConditionError::TypeMismatch {
+                builtin: "lt".to_string(),
+                found: boolean(true),
+            })
+        );
+        assert_eq!(
+            check(call("not", vec![var("b"), var("b")])),
+            Err(ConditionError::Arity {
+                builtin: "not".to_string(),
+                expected: 1,
+                found: 2,
+            })
+        );
+        assert_eq!(
+            check(call(
+                "eq",
+                vec![call("mul", vec![int(i64::MAX), int(2)]), int(0)]
+            )),
+            Err(ConditionError::Overflow("mul".to_string()))
+        );
+        assert_eq!(
+            check(call("n", vec![int(1)])),
+            Err(ConditionError::NotAFunction(int(1)))
+        );
+        assert!(matches!(
+            check(Expression::Lambda {
+                param: "x".to_string(),
+                body: Box::new(boolean(true)),
+            }),
+            Err(ConditionError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,884 @@
+// Evaluation of expressions as programs.
+//
+// An environment machine: lambdas close over the environment they were
+// built in, and variables are looked up rather than substituted. The
+// strategy decides when an argument (or a `let` value) is evaluated:
+//
+//   call-by-value   before the function body runs
+//   call-by-need    on first use, and at most once
+//   normal order    on every use; the result is then normalized under
+//                   binders as well, so it is the term's normal form
+//
+// `Match` tries its arms in order, binding pattern variables and checking
+// guards. Data built from unbound capitalized names, such as `Some x`, is
+// matched by constructor patterns. The builtins of `condition` are the
+// primitive operations, applied in curried form. Every beta reduction,
+// `let`, primitive application and arm selection costs a step of fuel,
+// and running out is an error, as is nesting past the depth limit.
+//
+// Under normal order, variables bound by a lambda being normalized are
+// free while its body is evaluated; applications, primitives and matches
+// that need their value stay in the result as they are.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::condition::{self, ConditionError};
+use crate::pattern::{Bindings, Expression, Literal, MatchArm, Pattern};
+use crate::substitute::{free_vars, substitute_many};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvalStrategy {
+    #[default]
+    CallByValue,
+    CallByNeed,
+    NormalOrder,
+}
+
+#[derive(Debug, Clone)]
+pub struct EvalConfig {
+    pub strategy: EvalStrategy,
+    /// Fuel: reductions allowed per evaluation
+    pub max_steps: usize,
+    /// Nesting allowed before evaluation gives up rather than overflow
+    /// the stack
+    pub max_depth: usize,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        Self {
+            strategy: EvalStrategy::CallByValue,
+            max_steps: 10_000,
+            max_depth: 256,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    #[error("Unbound variable: {0}")]
+    UnboundVariable(String),
+
+    #[error("Not a function: {0:?}")]
+    NotAFunction(Expression),
+
+    #[error("No match arm applies to {0:?}")]
+    NoMatchingArm(Expression),
+
+    #[error("Guard evaluated to a non-boolean: {0:?}")]
+    NotBoolean(Expression),
+
+    #[error("Evaluation does not support {0}")]
+    Unsupported(&'static str),
+
+    #[error(transparent)]
+    Primitive(#[from] ConditionError),
+
+    #[error("Maximum steps reached: {0}")]
+    MaxStepsReached(usize),
+
+    #[error("Maximum depth reached: {0}")]
+    MaxDepthReached(usize),
+}
+
+pub struct Evaluator {
+    config: EvalConfig,
+    steps: usize,
+    depth: usize,
+    /// Variables of the lambdas being normalized, innermost last
+    bound: Vec<String>,
+}
+
+impl Evaluator {
+    pub fn new(config: EvalConfig) -> Self {
+        Self {
+            config,
+            steps: 0,
+            depth: 0,
+            bound: Vec::new(),
+        }
+    }
+
+    /// Steps taken by the last evaluation.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Evaluate a closed expression.
+    pub fn evaluate(&mut self, expr: &Expression) -> Result<Expression, EvalError> {
+        self.evaluate_in(expr, &Bindings::new())
+    }
+
+    /// Evaluate `expr` with the given names in scope. Each bound expression
+    /// is itself evaluated, in an empty environment, as the strategy
+    /// dictates.
+    pub fn evaluate_in(
+        &mut self,
+        expr: &Expression,
+        bindings: &Bindings,
+    ) -> Result<Expression, EvalError> {
+        self.steps = 0;
+        self.depth = 0;
+        self.bound.clear();
+
+        let mut env = Env::default();
+        for (name, value) in bindings {
+            let thunk = self.delay(value, &Env::default())?;
+            env = env.bind(name, thunk);
+        }
+        let value = self.eval(expr, &env)?;
+        self.read_back(&value)
+    }
+
+    fn tick(&mut self) -> Result<(), EvalError> {
+        self.steps += 1;
+        if self.steps > self.config.max_steps {
+            return Err(EvalError::MaxStepsReached(self.config.max_steps));
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expression, env: &Env) -> Result<Value, EvalError> {
+        if self.depth >= self.config.max_depth {
+            return Err(EvalError::MaxDepthReached(self.config.max_depth));
+        }
+        self.depth += 1;
+        let result = self.eval_inner(expr, env);
+        self.depth -= 1;
+        result
+    }
+
+    fn eval_inner(&mut self, expr: &Expression, env: &Env) -> Result<Value, EvalError> {
+        match expr {
+            Expression::Literal(literal) => Ok(Value::Literal(literal.clone())),
+            Expression::Var(name) => match env.lookup(name) {
+                Some(thunk) => self.force(&thunk),
+                None if condition::arity(name).is_some() => Ok(Value::Primitive {
+                    name: name.clone(),
+                    args: Vec::new(),
+                }),
+                None if name.starts_with(char::is_uppercase) => Ok(Value::Data {
+                    name: name.clone(),
+                    fields: Vec::new(),
+                }),
+                None => Err(EvalError::UnboundVariable(name.clone())),
+            },
+            Expression::Lambda { param, body } => Ok(Value::Closure {
+                param: param.clone(),
+                body: Rc::new(body.as_ref().clone()),
+                env: env.clone(),
+            }),
+            Expression::Apply { func, arg } | Expression::LinearApply { func, arg } => {
+                let func = self.eval(func, env)?;
+                let arg = self.delay(arg, env)?;
+                self.apply(func, arg)
+            }
+            Expression::Let { name, value, body } => {
+                self.tick()?;
+                let value = self.delay(value, env)?;
+                self.eval(body, &env.bind(name, value))
+            }
+            Expression::Match { expr, arms } => {
+                let scrutinee = self.delay(expr, env)?;
+                self.select_arm(&scrutinee, arms, env)
+            }
+            Expression::Tuple(items) => Ok(Value::Tuple(self.delay_all(items, env)?)),
+            Expression::List(items) => Ok(Value::List(self.delay_all(items, env)?)),
+            Expression::Record(fields) => fields
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), self.delay(v, env)?)))
+                .collect::<Result<_, _>>()
+                .map(Value::Record),
+        }
+    }
+
+    /// Suspend `expr`, or under call-by-value evaluate it right away.
+    fn delay(&mut self, expr: &Expression, env: &Env) -> Result<Thunk, EvalError> {
+        Ok(match self.config.strategy {
+            EvalStrategy::CallByValue => Thunk::forced(self.eval(expr, env)?),
+            EvalStrategy::CallByNeed | EvalStrategy::NormalOrder => Thunk::delayed(expr, env),
+        })
+    }
+
+    fn delay_all(&mut self, items: &[Expression], env: &Env) -> Result<Vec<Thunk>, EvalError> {
+        items.iter().map(|item| self.delay(item, env)).collect()
+    }
+
+    fn force(&mut self, thunk: &Thunk) -> Result<Value, EvalError> {
+        let (expr, env) = match &*thunk.0.borrow() {
+            ThunkState::Forced(value) => return Ok(value.clone()),
+            ThunkState::Delayed(expr, env) => (expr.clone(), env.clone()),
+        };
+        let value = self.eval(&expr, &env)?;
+        if self.config.strategy == EvalStrategy::CallByNeed {
+            *thunk.0.borrow_mut() = ThunkState::Forced(value.clone());
+        }
+        Ok(value)
+    }
+
+    fn apply(&mut self, func: Value, arg: Thunk) -> Result<Value, EvalError> {
+        match func {
+            Value::Closure { param, body, env } => {
+                self.tick()?;
+                self.eval(&body, &env.bind(&param, arg))
+            }
+            Value::Primitive { name, mut args } => {
+                args.push(arg);
+                if Some(args.len()) == condition::arity(&name) {
+                    self.tick()?;
+                    self.apply_primitive(&name, &args)
+                } else {
+                    Ok(Value::Primitive { name, args })
+                }
+            }
+            Value::Data { name, mut fields } => {
+                fields.push(arg);
+                Ok(Value::Data { name, fields })
+            }
+            Value::Neutral(head) => {
+                let arg = self.force(&arg)?;
+                Ok(Value::Neutral(Expression::Apply {
+                    func: Box::new(head),
+                    arg: Box::new(self.read_back(&arg)?),
+                }))
+            }
+            other => Err(EvalError::NotAFunction(self.read_back(&other)?)),
+        }
+    }
+
+    fn apply_primitive(&mut self, name: &str, args: &[Thunk]) -> Result<Value, EvalError> {
+        // The boolean connectives evaluate their second argument lazily
+        if name == "and" || name == "or" {
+            return match self.force(&args[0])? {
+                Value::Literal(Literal::Bool(b)) if b == (name == "or") => {
+                    Ok(Value::Literal(Literal::Bool(b)))
+                }
+                Value::Literal(Literal::Bool(_)) => match self.force(&args[1])? {
+                    value @ (Value::Literal(Literal::Bool(_)) | Value::Neutral(_)) => Ok(value),
+                    other => Err(self.type_mismatch(name, &other)),
+                },
+                first @ Value::Neutral(_) => {
+                    let second = self.force(&args[1])?;
+                    self.stuck_primitive(name, &[first, second])
+                }
+                other => Err(self.type_mismatch(name, &other)),
+            };
+        }
+
+        let values = args
+            .iter()
+            .map(|arg| self.force(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.iter().any(|v| matches!(v, Value::Neutral(_))) {
+            return self.stuck_primitive(name, &values);
+        }
+        let values = values
+            .iter()
+            .map(|v| self.read_back(v))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = condition::apply_primitive(name, values)?;
+        self.eval(&result, &Env::default())
+    }
+
+    fn type_mismatch(&mut self, name: &str, found: &Value) -> EvalError {
+        match self.read_back(found) {
+            Ok(found) => ConditionError::TypeMismatch {
+                builtin: name.to_string(),
+                found,
+            }
+            .into(),
+            Err(e) => e,
+        }
+    }
+
+    fn stuck_primitive(&mut self, name: &str, values: &[Value]) -> Result<Value, EvalError> {
+        let mut term = Expression::Var(name.to_string());
+        for value in values {
+            term = Expression::Apply {
+                func: Box::new(term),
+                arg: Box::new(self.read_back(value)?),
+            };
+        }
+        Ok(Value::Neutral(term))
+    }
+
+    fn select_arm(
+        &mut self,
+        scrutinee: &Thunk,
+        arms: &[MatchArm],
+        env: &Env,
+    ) -> Result<Value, EvalError> {
+        for arm in arms {
+            let mut bindings = Vec::new();
+            match self.match_value(&arm.pattern, scrutinee, &mut bindings)? {
+                Outcome::Matched => {}
+                Outcome::Failed => continue,
+                Outcome::Stuck => return self.stuck_match(scrutinee, arms, env),
+            }
+            let arm_env = bindings
+                .into_iter()
+                .fold(env.clone(), |env, (name, thunk)| env.bind(&name, thunk));
+
+            if let Some(guard) = &arm.guard {
+                match self.eval(guard, &arm_env)? {
+                    Value::Literal(Literal::Bool(true)) => {}
+                    Value::Literal(Literal::Bool(false)) => continue,
+                    Value::Neutral(_) => return self.stuck_match(scrutinee, arms, env),
+                    other => return Err(EvalError::NotBoolean(self.read_back(&other)?)),
+                }
+            }
+            self.tick()?;
+            return self.eval(&arm.body, &arm_env);
+        }
+
+        let scrutinee = self.force(scrutinee)?;
+        Err(EvalError::NoMatchingArm(self.read_back(&scrutinee)?))
+    }
+
+    fn match_value(
+        &mut self,
+        pattern: &Pattern,
+        thunk: &Thunk,
+        bindings: &mut Vec<(String, Thunk)>,
+    ) -> Result<Outcome, EvalError> {
+        match pattern {
+            Pattern::Wildcard => return Ok(Outcome::Matched),
+            Pattern::Var(name) => {
+                bindings.push((name.clone(), thunk.clone()));
+                return Ok(Outcome::Matched);
+            }
+            Pattern::Bind { name, pattern } => {
+                bindings.push((name.clone(), thunk.clone()));
+                return self.match_value(pattern, thunk, bindings);
+            }
+            Pattern::Lambda { .. } | Pattern::Apply { .. } => {
+                return Err(EvalError::Unsupported("lambda and application patterns"))
+            }
+            _ => {}
+        }
+
+        let value = self.force(thunk)?;
+        let (patterns, thunks): (Vec<&Pattern>, Vec<Thunk>) = match (pattern, &value) {
+            (_, Value::Neutral(_)) => return Ok(Outcome::Stuck),
+            (Pattern::Literal(expected), Value::Literal(found)) => {
+                return Ok(if expected == found {
+                    Outcome::Matched
+                } else {
+                    Outcome::Failed
+                })
+            }
+            (Pattern::Tuple(patterns), Value::Tuple(items))
+            | (Pattern::List(patterns), Value::List(items))
+                if patterns.len() == items.len() =>
+            {
+                (patterns.iter().collect(), items.clone())
+            }
+            (
+                Pattern::Constructor { name, args },
+                Value::Data {
+                    name: found,
+                    fields,
+                },
+            ) if name == found && args.len() == fields.len() => {
+                (args.iter().collect(), fields.clone())
+            }
+            (Pattern::Record(patterns), Value::Record(fields)) => {
+                let mut thunks = Vec::new();
+                for (key, _) in patterns {
+                    match fields.iter().find(|(k, _)| k == key) {
+                        Some((_, thunk)) => thunks.push(thunk.clone()),
+                        None => return Ok(Outcome::Failed),
+                    }
+                }
+                (patterns.iter().map(|(_, p)| p).collect(), thunks)
+            }
+            _ => return Ok(Outcome::Failed),
+        };
+
+        for (pattern, thunk) in patterns.into_iter().zip(&thunks) {
+            match self.match_value(pattern, thunk, bindings)? {
+                Outcome::Matched => {}
+                other => return Ok(other),
+            }
+        }
+        Ok(Outcome::Matched)
+    }
+
+    /// A match whose arm cannot be chosen because the scrutinee or a guard
+    /// depends on a free variable. The arms keep their environment.
+    fn stuck_match(
+        &mut self,
+        scrutinee: &Thunk,
+        arms: &[MatchArm],
+        env: &Env,
+    ) -> Result<Value, EvalError> {
+        let scrutinee = self.force(scrutinee)?;
+        let scrutinee = self.read_back(&scrutinee)?;
+        let placeholder = Expression::Match {
+            expr: Box::new(Expression::Literal(Literal::Unit)),
+            arms: arms.to_vec(),
+        };
+        match self.close(&placeholder, env)? {
+            Expression::Match { arms, .. } => Ok(Value::Neutral(Expression::Match {
+                expr: Box::new(scrutinee),
+                arms,
+            })),
+            _ => unreachable!("substitution preserves the match"),
+        }
+    }
+
+    // ------------------------------------------------------------------------
+    // Read-back
+    // ------------------------------------------------------------------------
+
+    /// The expression a value denotes. Data is forced all the way down;
+    /// lambdas are normalized under normal order and otherwise have their
+    /// environment substituted in.
+    fn read_back(&mut self, value: &Value) -> Result<Expression, EvalError> {
+        let all = |this: &mut Self, thunks: &[Thunk]| -> Result<Vec<Expression>, EvalError> {
+            thunks
+                .iter()
+                .map(|t| {
+                    let value = this.force(t)?;
+                    this.read_back(&value)
+                })
+                .collect()
+        };
+
+        Ok(match value {
+            Value::Literal(literal) => Expression::Literal(literal.clone()),
+            Value::Neutral(term) => term.clone(),
+            Value::Primitive { name, args: fields } | Value::Data { name, fields } => {
+                all(self, fields)?
+                    .into_iter()
+                    .fold(Expression::Var(name.clone()), |func, arg| {
+                        Expression::Apply {
+                            func: Box::new(func),
+                            arg: Box::new(arg),
+                        }
+                    })
+            }
+            Value::Tuple(items) => Expression::Tuple(all(self, items)?),
+            Value::List(items) => Expression::List(all(self, items)?),
+            Value::Record(fields) => {
+                let (keys, thunks): (Vec<String>, Vec<Thunk>) = fields.iter().cloned().unzip();
+                Expression::Record(keys.into_iter().zip(all(self, &thunks)?).collect())
+            }
+            Value::Closure { param, body, env } => {
+                if self.config.strategy != EvalStrategy::NormalOrder {
+                    let lambda = Expression::Lambda {
+                        param: param.clone(),
+                        body: Box::new(body.as_ref().clone()),
+                    };
+                    return self.close(&lambda, env);
+                }
+
+                let mut name = param.clone();
+                while self.bound.contains(&name) {
+                    name.push('\'');
+                }
+                let variable = Thunk::forced(Value::Neutral(Expression::Var(name.clone())));
+                self.bound.push(name.clone());
+                let body = self
+                    .eval(body, &env.bind(param, variable))
+                    .and_then(|value| self.read_back(&value));
+                self.bound.pop();
+                Expression::Lambda {
+                    param: name,
+                    body: Box::new(body?),
+                }
+            }
+        })
+    }
+
+    /// Substitute the environment into the free variables of `expr`,
+    /// without forcing anything still suspended.
+    fn close(&mut self, expr: &Expression, env: &Env) -> Result<Expression, EvalError> {
+        let mut substitutions = HashMap::new();
+        let mut names: Vec<String> = free_vars(expr).into_iter().collect();
+        names.sort();
+        for name in names {
+            let Some(thunk) = env.lookup(&name) else {
+                continue;
+            };
+            let state = thunk.0.borrow().clone();
+            let term = match state {
+                ThunkState::Forced(value) => self.read_back(&value)?,
+                ThunkState::Delayed(expr, env) => self.close(&expr, &env)?,
+            };
+            substitutions.insert(name, term);
+        }
+        Ok(substitute_many(expr, &substitutions))
+    }
+}
+
+// ============================================================================
+// Values and Environments
+// ============================================================================
+
+#[derive(Debug, Clone)]
+enum Value {
+    Literal(Literal),
+    Closure {
+        param: String,
+        body: Rc<Expression>,
+        env: Env,
+    },
+    /// A builtin still waiting for arguments
+    Primitive {
+        name: String,
+        args: Vec<Thunk>,
+    },
+    /// A constructor applied to its fields, e.g. `Some x`
+    Data {
+        name: String,
+        fields: Vec<Thunk>,
+    },
+    Tuple(Vec<Thunk>),
+    List(Vec<Thunk>),
+    Record(Vec<(String, Thunk)>),
+    /// A term stuck on a free variable, under normal order only
+    Neutral(Expression),
+}
+
+#[derive(Debug, Clone)]
+struct Thunk(Rc<RefCell<ThunkState>>);
+
+#[derive(Debug, Clone)]
+enum ThunkState {
+    Delayed(Rc<Expression>, Env),
+    Forced(Value),
+}
+
+impl Thunk {
+    fn forced(value: Value) -> Self {
+        Thunk(Rc::new(RefCell::new(ThunkState::Forced(value))))
+    }
+
+    fn delayed(expr: &Expression, env: &Env) -> Self {
+        Thunk(Rc::new(RefCell::new(ThunkState::Delayed(
+            Rc::new(expr.clone()),
+            env.clone(),
+        ))))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Env(Option<Rc<Frame>>);
+
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    value: Thunk,
+    next: Env,
+}
+
+impl Env {
+    fn bind(&self, name: &str, value: Thunk) -> Env {
+        Env(Some(Rc::new(Frame {
+            name: name.to_string(),
+            value,
+            next: self.clone(),
+        })))
+    }
+
+    fn lookup(&self, name: &str) -> Option<Thunk> {
+        let mut env = self;
+        while let Some(frame) = &env.0 {
+            if frame.name == name {
+                return Some(frame.value.clone());
+            }
+            env = &frame.next;
+        }
+        None
+    }
+}
+
+enum Outcome {
+    Matched,
+    Failed,
+    Stuck,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(n: i64) -> Expression {
+        Expression::Literal(Literal::Int(n))
+    }
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(name.to_string())
+    }
+
+    fn lambda(param: &str, body: Expression) -> Expression {
+        Expression::Lambda {
+            param: param.to_string(),
+            body: Box::new(body),
+        }
+    }
+
+    fn call(func: Expression, args: Vec<Expression>) -> Expression {
+        args.into_iter().fold(func, |func, arg| Expression::Apply {
+            func: Box::new(func),
+            arg: Box::new(arg),
+        })
+    }
+
+    fn prim(name: &str, args: Vec<Expression>) -> Expression {
+        call(var(name), args)
+    }
+
+    fn arm(pattern: Pattern, guard: Option<Expression>, body: Expression) -> MatchArm {
+        MatchArm {
+            pattern,
+            guard: guard.map(Box::new),
+            body: Box::new(body),
+        }
+    }
+
+    fn omega() -> Expression {
+        let self_apply = lambda("x", call(var("x"), vec![var("x")]));
+        call(self_apply.clone(), vec![self_apply])
+    }
+
+    fn run(strategy: EvalStrategy, expr: &Expression) -> (Result<Expression, EvalError>, usize) {
+        let mut evaluator = Evaluator::new(EvalConfig {
+            strategy,
+            max_steps: 100,
+            ..EvalConfig::default()
+        });
+        let result = evaluator.evaluate(expr);
+        (result, evaluator.steps())
+    }
+
+    const STRATEGIES: [EvalStrategy; 3] = [
+        EvalStrategy::CallByValue,
+        EvalStrategy::CallByNeed,
+        EvalStrategy::NormalOrder,
+    ];
+
+    #[test]
+    fn test_strategies_share_results_but_not_work() {
+        // let double = λx. add x x in double (add 1 2)
+        let program = Expression::Let {
+            name: "double".to_string(),
+            value: Box::new(lambda("x", prim("add", vec![var("x"), var("x")]))),
+            body: Box::new(call(var("double"), vec![prim("add", vec![int(1), int(2)])])),
+        };
+
+        let steps: Vec<usize> = STRATEGIES
+            .iter()
+            .map(|&strategy| {
+                let (result, steps) = run(strategy, &program);
+                assert_eq!(result, Ok(int(6)));
+                steps
+            })
+            .collect();
+        // Normal order evaluates the argument once per use of `x`
+        assert_eq!(steps, vec![4, 4, 5]);
+    }
+
+    #[test]
+    fn test_unused_divergent_argument() {
+        let program = call(lambda("x", int(1)), vec![omega()]);
+
+        assert_eq!(
+            run(EvalStrategy::CallByValue, &program).0,
+            Err(EvalError::MaxStepsReached(100))
+        );
+        assert_eq!(run(EvalStrategy::CallByNeed, &program).0, Ok(int(1)));
+        assert_eq!(run(EvalStrategy::NormalOrder, &program).0, Ok(int(1)));
+
+        // Without fuel running out first, the depth limit stops the descent
+        let mut evaluator = Evaluator::new(EvalConfig::default());
+        assert_eq!(
+            evaluator.evaluate(&omega()),
+            Err(EvalError::MaxDepthReached(256))
+        );
+    }
+
+    #[test]
+    fn test_match_arms_guards_and_constructors() {
+        let classify = |scrutinee: Expression| Expression::Match {
+            expr: Box::new(scrutinee),
+            arms: vec![
+                arm(
+                    Pattern::Constructor {
+                        name: "Some".to_string(),
+                        args: vec![Pattern::Var("n".to_string())],
+                    },
+                    Some(prim("gt", vec![var("n"), int(10)])),
+                    int(10),
+                ),
+                arm(
+                    Pattern::Constructor {
+                        name: "Some".to_string(),
+                        args: vec![Pattern::Var("n".to_string())],
+                    },
+                    None,
+                    prim("add", vec![var("n"), int(1)]),
+                ),
+                arm(
+                    Pattern::Tuple(vec![
+                        Pattern::Literal(Literal::Bool(true)),
+                        Pattern::Wildcard,
+                    ]),
+                    None,
+                    int(0),
+                ),
+            ],
+        };
+        let some = |e: Expression| call(var("Some"), vec![e]);
+
+        for strategy in STRATEGIES {
+            let eval = |e: Expression| run(strategy, &e).0;
+            assert_eq!(eval(classify(some(int(50)))), Ok(int(10)));
+            assert_eq!(
+                eval(classify(some(prim("sub", vec![int(5), int(1)])))),
+                Ok(int(5))
+            );
+            assert_eq!(
+                eval(classify(Expression::Tuple(vec![
+                    Expression::Literal(Literal::Bool(true)),
+                    int(7),
+                ]))),
+                Ok(int(0))
+            );
+            assert_eq!(
+                eval(classify(var("None"))),
+                Err(EvalError::NoMatchingArm(var("None")))
+            );
+            assert_eq!(
+                eval(classify(some(Expression::Literal(Literal::Bool(true))))),
+                Err(EvalError::Primitive(ConditionError::TypeMismatch {
+                    builtin: "gt".to_string(),
+                    found: Expression::Literal(Literal::Bool(true)),
+                }))
+            );
+        }
+
+        // Lazy strategies never touch a field no pattern looks at
+        let lazy = classify(Expression::Tuple(vec![
+            Expression::Literal(Literal::Bool(true)),
+            omega(),
+        ]));
+        assert_eq!(run(EvalStrategy::CallByNeed, &lazy).0, Ok(int(0)));
+        assert_eq!(
+            run(EvalStrategy::CallByValue, &lazy).0,
+            Err(EvalError::MaxStepsReached(100))
+        );
+    }
+
+    #[test]
+    fn test_normal_order_reduces_under_binders() {
+        // λx. (λy. λx. y) x: the inner binder must not capture the outer x
+        let program = lambda(
+            "x",
+            call(lambda("y", lambda("x", var("y"))), vec![var("x")]),
+        );
+
+        assert_eq!(
+            run(EvalStrategy::NormalOrder, &program).0,
+            Ok(lambda("x", lambda("x'", var("x"))))
+        );
+        assert_eq!(
+            run(EvalStrategy::CallByValue, &program).0,
+            Ok(program.clone())
+        );
+
+        // A closure is read back with its environment substituted in
+        let closure = call(lambda("k", lambda("z", var("k"))), vec![int(3)]);
+        assert_eq!(
+            run(EvalStrategy::CallByNeed, &closure).0,
+            Ok(lambda("z", int(3)))
+        );
+
+        // Work that depends on the free variable is left in place
+        let stuck = lambda(
+            "b",
+            Expression::Match {
+                expr: Box::new(var("b")),
+                arms: vec![arm(
+                    Pattern::Literal(Literal::Bool(true)),
+                    None,
+                    prim("add", vec![int(1), int(1)]),
+                )],
+            },
+        );
+        assert_eq!(run(EvalStrategy::NormalOrder, &stuck).0, Ok(stuck));
+        assert_eq!(
+            run(
+                EvalStrategy::NormalOrder,
+                &lambda("n", prim("add", vec![var("n"), int(1)]))
+            )
+            .0,
+            Ok(lambda("n", prim("add", vec![var("n"), int(1)])))
+        );
+    }
+
+    #[test]
+    fn test_recursion_through_environment() {
+        // Z combinator factorial over an environment binding for n
+        let z = lambda(
+            "f",
+            call(
+                lambda(
+                    "x",
+                    call(
+                        var("f"),
+                        vec![lambda("v", call(var("x"), vec![var("x"), var("v")]))],
+                    ),
+                ),
+                vec![lambda(
+                    "x",
+                    call(
+                        var("f"),
+                        vec![lambda("v", call(var("x"), vec![var("x"), var("v")]))],
+                    ),
+                )],
+            ),
+        );
+        let factorial = lambda(
+            "fact",
+            lambda(
+                "k",
+                Expression::Match {
+                    expr: Box::new(var("k")),
+                    arms: vec![
+                        arm(Pattern::Literal(Literal::Int(0)), None, int(1)),
+                        arm(
+                            Pattern::Wildcard,
+                            None,
+                            prim(
+                                "mul",
+                                vec![
+                                    var("k"),
+                                    call(var("fact"), vec![prim("sub", vec![var("k"), int(1)])]),
+                                ],
+                            ),
+                        ),
+                    ],
+                },
+            ),
+        );
+        let program = call(z, vec![factorial, var("n")]);
+        let bindings: Bindings = [("n".to_string(), int(5))].into_iter().collect();
+
+        for strategy in STRATEGIES {
+            let mut evaluator = Evaluator::new(EvalConfig {
+                strategy,
+                ..EvalConfig::default()
+            });
+            assert_eq!(evaluator.evaluate_in(&program, &bindings), Ok(int(120)));
+            assert_eq!(
+                evaluator.evaluate(&program),
+                Err(EvalError::UnboundVariable("n".to_string()))
+            );
+        }
+    }
+}
@@ -1,6 +1,12 @@
+pub mod condition;
+pub mod eval;
 pub mod pattern;
 pub mod substitute;
 
+pub use condition::{evaluate, evaluate_condition, ConditionError};
+
+pub use eval::{EvalConfig, EvalError, EvalStrategy, Evaluator};
+
 pub use pattern::{
     Bindings, Expression, Literal, MatchArm, MatchResult, Pattern,
     deserialize_match_result, match_any_pattern, match_pattern, match_pattern_many,
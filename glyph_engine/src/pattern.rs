@@ -1,77 +1,6 @@
-use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub enum Expression {
-    Literal(Literal),
-    Var(String),
-    Lambda {
-        param: String,
-        body: Box<Expression>,
-    },
-    Apply {
-        func: Box<Expression>,
-        arg: Box<Expression>,
-    },
-    LinearApply {
-        func: Box<Expression>,
-        arg: Box<Expression>,
-    },
-    Let {
-        name: String,
-        value: Box<Expression>,
-        body: Box<Expression>,
-    },
-    Match {
-        expr: Box<Expression>,
-        arms: Vec<MatchArm>,
-    },
-    Tuple(Vec<Expression>),
-    List(Vec<Expression>),
-    Record(Vec<(String, Expression)>),
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub enum Literal {
-    Int(i64),
-    Float(String),
-    String(String),
-    Bool(bool),
-    Unit,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub struct MatchArm {
-    pub pattern: Pattern,
-    pub guard: Option<Box<Expression>>,
-    pub body: Box<Expression>,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub enum Pattern {
-    Wildcard,
-    Var(String),
-    Literal(Literal),
-    Bind {
-        name: String,
-        pattern: Box<Pattern>,
-    },
-    Tuple(Vec<Pattern>),
-    List(Vec<Pattern>),
-    Constructor {
-        name: String,
-        args: Vec<Pattern>,
-    },
-    Record(Vec<(String, Pattern)>),
-    Lambda {
-        param_pattern: Box<Pattern>,
-        body_pattern: Box<Pattern>,
-    },
-    Apply {
-        func_pattern: Box<Pattern>,
-        arg_pattern: Box<Pattern>,
-    },
-}
+pub use glyph_ast::{Expression, Literal, MatchArm, Pattern};
 
 pub type Bindings = BTreeMap<String, Expression>;
 pub type MatchResult = Vec<Bindings>;
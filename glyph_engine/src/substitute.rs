@@ -273,7 +273,8 @@ fn substitute_internal(
             } else {
                 let replacement_free_vars = free_vars(replacement);
                 
-                if replacement_free_vars.contains(param) {
+                // Only rename a binder that would actually capture something
+                if replacement_free_vars.contains(param) && free_vars(body).contains(var) {
                     let mut avoid_set = free_vars(expr);
                     avoid_set.extend(replacement_free_vars);
                     avoid_set.insert(var.to_string());
@@ -333,7 +334,7 @@ fn substitute_internal(
             } else {
                 let replacement_free_vars = free_vars(replacement);
                 
-                if replacement_free_vars.contains(name) {
+                if replacement_free_vars.contains(name) && free_vars(body).contains(var) {
                     let mut avoid_set = free_vars(expr);
                     avoid_set.extend(replacement_free_vars);
                     avoid_set.insert(var.to_string());
@@ -421,8 +422,10 @@ fn substitute_match_arm(
     
     let replacement_free_vars = free_vars(replacement);
     let captures: Vec<_> = pattern_vars.intersection(&replacement_free_vars).cloned().collect();
+    let occurs = free_vars(&arm.body).contains(var)
+        || arm.guard.as_ref().is_some_and(|g| free_vars(g).contains(var));
     
-    if captures.is_empty() {
+    if captures.is_empty() || !occurs {
         let mut new_bound = bound.clone();
         new_bound.extend(pattern_vars);
         
@@ -503,9 +506,30 @@ pub fn substitute_many(
     let mut sorted_vars: Vec<_> = substitutions.keys().cloned().collect();
     sorted_vars.sort();
     
-    for var in sorted_vars {
-        if let Some(replacement) = substitutions.get(&var) {
-            result = substitute(&result, &var, replacement);
+    // One variable at a time is only simultaneous when no replacement
+    // mentions a variable still to be replaced; otherwise every variable
+    // goes through a fresh placeholder first.
+    let interferes = substitutions
+        .values()
+        .any(|r| free_vars(r).iter().any(|v| substitutions.contains_key(v)));
+    
+    if interferes {
+        let placeholders: Vec<(String, String)> = sorted_vars
+            .into_iter()
+            .map(|var| {
+                let placeholder = gensym(&var);
+                (var, placeholder)
+            })
+            .collect();
+        for (var, placeholder) in &placeholders {
+            result = substitute(&result, var, &Expression::Var(placeholder.clone()));
+        }
+        for (var, placeholder) in &placeholders {
+            result = substitute(&result, placeholder, &substitutions[var]);
+        }
+    } else {
+        for var in sorted_vars {
+            result = substitute(&result, &var, &substitutions[&var]);
         }
     }
     
@@ -958,6 +982,19 @@ mod tests {
         assert_eq!(result, apply(apply(var("g"), int(1)), int(2)));
     }
 
+    #[test]
+    fn test_substitute_many_is_simultaneous() {
+        let expr = apply(var("x"), lambda("z", apply(var("y"), var("z"))));
+        
+        let mut subs = HashMap::new();
+        subs.insert("x".to_string(), var("y"));
+        subs.insert("y".to_string(), var("x"));
+        
+        let result = substitute_many(&expr, &subs);
+        
+        assert_eq!(result, apply(var("y"), lambda("z", apply(var("x"), var("z")))));
+    }
+
     #[test]
     fn test_alpha_rename_simple() {
         reset_gensym();
@@ -1148,6 +1185,20 @@ mod tests {
         assert_eq!(result, lambda("x", var("x")));
     }
 
+    #[test]
+    fn test_no_renaming_without_an_occurrence() {
+        // y would be captured, but there is no x under the binders to replace
+        let replacement = var("y");
+        assert_eq!(
+            substitute(&lambda("y", var("y")), "x", &replacement),
+            lambda("y", var("y"))
+        );
+        assert_eq!(
+            substitute(&let_expr("y", var("x"), var("y")), "x", &replacement),
+            let_expr("y", var("y"), var("y"))
+        );
+    }
+
     #[test]
     fn test_multiple_occurrences() {
         let expr = apply(
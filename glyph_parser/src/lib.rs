@@ -1,70 +1,7 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use thiserror::Error;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Expression {
-    Literal(Literal),
-    
-    Var(String),
-    
-    Lambda {
-        param: String,
-        body: Box<Expression>,
-    },
-    
-    Apply {
-        func: Box<Expression>,
-        arg: Box<Expression>,
-    },
-    
-    LinearApply {
-        func: Box<Expression>,
-        arg: Box<Expression>,
-    },
-    
-    Let {
-        name: String,
-        value: Box<Expression>,
-        body: Box<Expression>,
-    },
-    
-    Match {
-        expr: Box<Expression>,
-        arms: Vec<MatchArm>,
-    },
-    
-    Tuple(Vec<Expression>),
-    
-    List(Vec<Expression>),
-    
-    Record(HashMap<String, Expression>),
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Literal {
-    Int(i64),
-    Float(String),
-    String(String),
-    Bool(bool),
-    Unit,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct MatchArm {
-    pub pattern: Pattern,
-    pub guard: Option<Box<Expression>>,
-    pub body: Box<Expression>,
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Pattern {
-    Wildcard,
-    Var(String),
-    Literal(Literal),
-    Tuple(Vec<Pattern>),
-    Constructor { name: String, args: Vec<Pattern> },
-}
+pub use glyph_ast::{Expression, Literal, MatchArm, Pattern};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
@@ -296,7 +233,7 @@ impl Lexer {
                 Ok(Token::Arrow)
             }
             
-            Some('-') if self.peek(1).map_or(false, |c| c.is_ascii_digit()) => {
+            Some('-') if self.peek(1).is_some_and(|c| c.is_ascii_digit()) => {
                 self.advance();
                 let num_token = self.read_number()?;
                 match num_token {
@@ -639,7 +576,9 @@ impl Parser {
             }
             Token::LBrace => {
                 self.advance();
-                let mut fields = HashMap::new();
+                // Keyed like the source map: the last duplicate wins and
+                // fields come out sorted, which is the canonical order
+                let mut fields = BTreeMap::new();
                 
                 while !matches!(self.current(), Token::RBrace | Token::Eof) {
                     let key = match self.advance() {
@@ -657,7 +596,7 @@ impl Parser {
                 }
                 
                 self.expect(Token::RBrace)?;
-                Ok(Expression::Record(fields))
+                Ok(Expression::Record(fields.into_iter().collect()))
             }
             t => Err(ParseError::UnexpectedToken(t)),
         }
@@ -665,15 +604,11 @@ impl Parser {
 }
 
 pub fn canonical_serialize(expr: &Expression) -> Result<Vec<u8>, String> {
-    let mut buffer = Vec::new();
-    ciborium::into_writer(expr, &mut buffer)
-        .map_err(|e| format!("Serialization failed: {}", e))?;
-    Ok(buffer)
+    Ok(glyph_ast::canonical_serialize(expr))
 }
 
 pub fn deserialize(data: &[u8]) -> Result<Expression, String> {
-    ciborium::from_reader(data)
-        .map_err(|e| format!("Deserialization failed: {}", e))
+    glyph_ast::deserialize(data).map_err(|e| e.to_string())
 }
 
 pub fn parse(input: &str) -> Result<Expression, ParseError> {
@@ -915,7 +850,8 @@ mod tests {
         let s1 = canonical_serialize(&ast1).unwrap();
         let s2 = canonical_serialize(&ast2).unwrap();
         
-        println!("Record serialization lengths: {} vs {}", s1.len(), s2.len());
+        assert_eq!(ast1, ast2);
+        assert_eq!(s1, s2);
     }
 
     #[test]
@@ -979,7 +915,7 @@ mod tests {
     fn test_lexer_robustness() {
         let mut lexer = Lexer::new("42 + 3.14");
         let tokens = lexer.tokenize().unwrap();
-        assert!(tokens.len() > 0);
+        assert!(!tokens.is_empty());
     }
 
     #[test]
@@ -5,8 +5,10 @@ use std::sync::Arc;
 use parking_lot::RwLock;
 use thiserror::Error;
 
-pub use glyph_engine::pattern::{Expression, Literal, MatchArm, Pattern, match_pattern as engine_match_pattern, Bindings};
+pub use glyph_ast::{Expression, Literal, MatchArm, Pattern};
+pub use glyph_engine::pattern::{match_pattern as engine_match_pattern, Bindings};
 pub use glyph_engine::substitute::substitute_many;
+use glyph_engine::condition::{self, ConditionError};
 
 pub type Hash = String;
 pub type NodeId = String;
@@ -206,6 +208,13 @@ pub enum TransactionError {
     #[error("Rule application failed: {0}")]
     RuleApplicationFailed(String),
 
+    #[error("Condition of rule {rule_id} failed: {source}")]
+    ConditionFailed {
+        rule_id: String,
+        #[source]
+        source: ConditionError,
+    },
+
     #[error("Cycle detected")]
     CycleDetected,
 }
@@ -352,8 +361,14 @@ impl Transaction {
                 }
 
                 if let Some(condition) = &rule.condition {
-                    if !evaluate_condition(condition, &bindings[0]) {
-                        continue;
+                    match evaluate_condition(&rule.id, condition, &bindings[0]) {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(e) => {
+                            drop(write_guard);
+                            self.rollback()?;
+                            return Err(e);
+                        }
                     }
                 }
 
@@ -468,7 +483,10 @@ pub fn apply_ruleset_transactionally(
     let rewrites = match tx.apply_ruleset() {
         Ok(count) => count,
         Err(e) => {
-            tx.rollback()?;
+            // apply_ruleset rolls back itself on failures it detects
+            if !tx.is_rolled_back {
+                tx.rollback()?;
+            }
             return Err(e);
         }
     };
@@ -532,9 +550,17 @@ fn apply_bindings(expr: &Expression, bindings: &Bindings) -> Expression {
     substitute_many(expr, &substitutions)
 }
 
-fn evaluate_condition(condition: &Expression, bindings: &Bindings) -> bool {
-    let evaluated = apply_bindings(condition, bindings);
-    matches!(evaluated, Expression::Literal(Literal::Bool(true)))
+fn evaluate_condition(
+    rule_id: &str,
+    condition: &Expression,
+    bindings: &Bindings,
+) -> Result<bool, TransactionError> {
+    condition::evaluate_condition(condition, bindings).map_err(|source| {
+        TransactionError::ConditionFailed {
+            rule_id: rule_id.to_string(),
+            source,
+        }
+    })
 }
 
 #[cfg(test)]
@@ -1194,6 +1220,98 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    fn call(name: &str, args: Vec<Expression>) -> Expression {
+        args.into_iter().fold(var(name), |func, arg| Expression::Apply {
+            func: Box::new(func),
+            arg: Box::new(arg),
+        })
+    }
+
+    fn graph_with_values(values: &[(&str, i64)]) -> Arc<RwLock<GenesisGraph>> {
+        let graph = GenesisGraph::new_wrapped(create_test_root()).unwrap();
+        {
+            let mut g = graph.write();
+            for (id, value) in values {
+                let node = GraphNode {
+                    id: id.to_string(),
+                    root_ref: g.root_hash().clone(),
+                    data: int(*value),
+                    metadata: NodeMetadata {
+                        timestamp: current_timestamp(),
+                        lineage_depth: 1,
+                        tags: vec![],
+                    },
+                };
+                g.insert_node_internal(node).unwrap();
+            }
+        }
+        graph
+    }
+
+    #[test]
+    fn test_rule_condition_filters_matches() {
+        let graph = graph_with_values(&[("a", 2), ("b", 7)]);
+
+        // n -> 100 for positive n below 5
+        let rule = RewriteRule::new(
+            "small".to_string(),
+            10,
+            Pattern::Var("n".to_string()),
+            int(100),
+        )
+        .with_condition(call(
+            "and",
+            vec![
+                call("gt", vec![var("n"), int(0)]),
+                call("lt", vec![var("n"), int(5)]),
+            ],
+        ));
+
+        let ruleset = RuleSet::new("cond".to_string()).add_rule(rule);
+        let result = apply_ruleset_transactionally(graph.clone(), ruleset).unwrap();
+        assert_eq!(result.rewrites_applied, 1);
+
+        let g = graph.read();
+        let data: Vec<_> = g
+            .nodes_sorted_by_id()
+            .into_iter()
+            .map(|(_, n)| n.data.clone())
+            .collect();
+        assert_eq!(data, vec![int(100), int(7), int(0)]);
+    }
+
+    #[test]
+    fn test_rule_condition_error_rolls_back() {
+        let graph = graph_with_values(&[("a", 1), ("b", 5)]);
+        let before = compute_graph_hash(&graph.read());
+
+        let ruleset = RuleSet::new("cond".to_string())
+            .add_rule(RewriteRule::new(
+                "one".to_string(),
+                20,
+                Pattern::Literal(Literal::Int(1)),
+                int(2),
+            ))
+            .add_rule(
+                RewriteRule::new(
+                    "five".to_string(),
+                    10,
+                    Pattern::Var("n".to_string()),
+                    int(6),
+                )
+                .with_condition(call("add", vec![var("n"), int(1)])),
+            );
+
+        match apply_ruleset_transactionally(graph.clone(), ruleset) {
+            Err(TransactionError::ConditionFailed { rule_id, source }) => {
+                assert_eq!(rule_id, "five");
+                assert_eq!(source, ConditionError::NotBoolean(int(6)));
+            }
+            other => panic!("expected a condition failure, got {:?}", other),
+        }
+        assert_eq!(compute_graph_hash(&graph.read()), before);
+    }
+
     #[test]
     fn test_empty_ruleset() {
         let root = create_test_root();